@@ -62,9 +62,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         database_name: "my_app_logs".to_string(),
         log_units_collection: "application_log_units".to_string(),
         log_entries_collection: "application_log_entries".to_string(),
+        ..Default::default()
     };
 
-    let service = DefaultLogService::new_mongodb(custom_config).await?;
+    // Echo every write to the console as well
+    let service = DefaultLogService::new_mongodb(custom_config)
+        .await?
+        .with_console_echo();
 
     // Create another log unit for a different workflow
     let workflow_unit =
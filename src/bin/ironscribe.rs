@@ -0,0 +1,525 @@
+//! Command-line tool to query and tail logs stored by IronScribe
+
+#[cfg(not(any(feature = "postgres", feature = "mongo")))]
+compile_error!("The ironscribe CLI needs the `postgres` or `mongo` feature");
+
+use chrono::{DateTime, Duration, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use ironscribe::core::archive;
+use ironscribe::core::log_service::LogResult;
+use ironscribe::core::LogService;
+use ironscribe::{ArchiveReport, Compression, ConsoleDestination, ExportOptions, LogEntry, LogLevel, LogUnit, SearchHit, SearchOptions, SubscriptionFilter};
+
+#[cfg(feature = "mongo")]
+use ironscribe::{MongoConfig, MongoDestination};
+#[cfg(feature = "postgres")]
+use ironscribe::{PostgresConfig, PostgresDestination};
+
+/// Bold on and off, used to highlight search matches on the terminal
+const HIGHLIGHT_START: &str = "\u{1b}[1m";
+const HIGHLIGHT_END: &str = "\u{1b}[22m";
+
+#[derive(Parser)]
+#[command(name = "ironscribe", version, about = "Query and tail logs stored by IronScribe")]
+struct Cli {
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    /// Print one JSON object per line instead of colored text
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Show where each entry was logged from, if known
+    #[arg(long, global = true)]
+    locations: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct ConnectionArgs {
+    /// JSON file with connection settings; the options below override it
+    #[arg(long, env = "IRONSCRIBE_CONFIG", global = true)]
+    config: Option<PathBuf>,
+
+    /// Storage backend to read from
+    #[arg(long, value_enum, env = "IRONSCRIBE_BACKEND", global = true)]
+    backend: Option<Backend>,
+
+    /// Connection string; defaults to the backend's default configuration
+    #[arg(long, env = "IRONSCRIBE_URL", global = true)]
+    url: Option<String>,
+
+    /// Table or collection holding log units
+    #[arg(long, env = "IRONSCRIBE_UNITS_TABLE", global = true)]
+    units_table: Option<String>,
+
+    /// Table or collection holding log entries
+    #[arg(long, env = "IRONSCRIBE_ENTRIES_TABLE", global = true)]
+    entries_table: Option<String>,
+
+    /// MongoDB database name
+    #[arg(long, env = "IRONSCRIBE_DATABASE", global = true)]
+    database: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Backend {
+    #[cfg(feature = "postgres")]
+    Postgres,
+    #[cfg(feature = "mongo")]
+    Mongo,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Inspect log units
+    Units {
+        #[command(subcommand)]
+        command: UnitsCommand,
+    },
+    /// Inspect log entries
+    Entries {
+        #[command(subcommand)]
+        command: EntriesCommand,
+    },
+    /// Search entry messages across all units
+    Search {
+        /// Words that must all appear in the message
+        text: String,
+        /// Only search units with this external ID
+        #[arg(long)]
+        external_id: Option<String>,
+        /// Maximum number of hits
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        #[command(flatten)]
+        filter: EntryFilter,
+    },
+    /// Show the latest entries and optionally follow new ones
+    Tail {
+        /// Log unit to show
+        #[arg(long)]
+        unit: Option<Uuid>,
+        /// Show the units with this external ID
+        #[arg(long)]
+        external_id: Option<String>,
+        /// Number of existing entries to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        lines: usize,
+        /// Keep running and print new entries as they are written.
+        ///
+        /// With PostgreSQL, the application writing the logs must enable `notify_on_insert`.
+        #[arg(short, long)]
+        follow: bool,
+        #[command(flatten)]
+        filter: EntryFilter,
+    },
+    /// Write log units and their entries to an NDJSON archive
+    Export {
+        /// Archive file to write; standard output if omitted
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Compress the archive with gzip
+        #[arg(long)]
+        gzip: bool,
+        /// Only units with this external ID
+        #[arg(long)]
+        external_id: Option<String>,
+        /// Only units newer than this age, e.g. `30m`, `2h` or `7d`
+        #[arg(long, value_parser = parse_age)]
+        since: Option<Duration>,
+        /// Only units created at or after this RFC 3339 timestamp
+        #[arg(long)]
+        from: Option<DateTime<Utc>>,
+        /// Only units created before this RFC 3339 timestamp
+        #[arg(long)]
+        until: Option<DateTime<Utc>>,
+    },
+    /// Load an archive written by `export`, keeping all IDs and timestamps
+    Import {
+        /// Archive file to read, plain or gzip compressed; `-` for standard input
+        input: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum UnitsCommand {
+    /// List the log units with an external ID
+    List {
+        #[arg(long)]
+        external_id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum EntriesCommand {
+    /// Show the entries of a log unit
+    Show {
+        unit: Uuid,
+        #[command(flatten)]
+        filter: EntryFilter,
+    },
+}
+
+#[derive(Args, Clone)]
+struct EntryFilter {
+    /// Only entries at least as severe as this level (error, warning, info, success)
+    #[arg(long)]
+    min_level: Option<LogLevel>,
+    /// Only entries newer than this age, e.g. `30m`, `2h` or `7d`
+    #[arg(long, value_parser = parse_age)]
+    since: Option<Duration>,
+    /// Only entries at or after this RFC 3339 timestamp
+    #[arg(long)]
+    from: Option<DateTime<Utc>>,
+    /// Only entries before this RFC 3339 timestamp
+    #[arg(long)]
+    until: Option<DateTime<Utc>>,
+    /// Only entries logged from this module or its submodules, e.g. `my_app::db`
+    #[arg(long)]
+    module: Option<String>,
+}
+
+impl EntryFilter {
+    /// Start of the time window, combining `--since` and `--from`
+    fn start(&self) -> Option<DateTime<Utc>> {
+        let since = self.since.map(|age| Utc::now() - age);
+        since.max(self.from)
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        self.min_level.is_none_or(|level| entry.level.is_at_least(level))
+            && self.start().is_none_or(|start| entry.timestamp >= start)
+            && self.until.is_none_or(|until| entry.timestamp < until)
+            && self.module.as_ref().is_none_or(|module| entry.is_in_module(module))
+    }
+}
+
+/// Connection settings read from `--config`, named like the fields of
+/// `PostgresConfig` and `MongoConfig`
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ConfigFile {
+    backend: Option<Backend>,
+    connection_string: Option<String>,
+    log_units_table: Option<String>,
+    log_entries_table: Option<String>,
+    text_search_config: Option<String>,
+    database_name: Option<String>,
+    log_units_collection: Option<String>,
+    log_entries_collection: Option<String>,
+}
+
+impl ConfigFile {
+    async fn load(path: &Path) -> LogResult<Self> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&content).map_err(|e| format!("Invalid config file {}: {}", path.display(), e).into())
+    }
+}
+
+/// Parses an age such as `45s`, `30m`, `2h`, `7d` or `2w`
+fn parse_age(age: &str) -> Result<Duration, String> {
+    let split = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    let (amount, unit) = age.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| format!("Invalid age: {}", age))?;
+
+    match unit {
+        "s" => Ok(Duration::seconds(amount)),
+        "m" => Ok(Duration::minutes(amount)),
+        "h" | "" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(format!("Unknown age unit in {}, expected s, m, h, d or w", age)),
+    }
+}
+
+/// Connects to the configured backend, leaving PostgreSQL schemas untouched
+async fn connect(args: &ConnectionArgs) -> LogResult<Arc<dyn LogService>> {
+    let file = match &args.config {
+        Some(path) => ConfigFile::load(path).await?,
+        None => ConfigFile::default(),
+    };
+    let backend = args.backend
+        .or(file.backend)
+        .ok_or("No backend given, use --backend, IRONSCRIBE_BACKEND or a config file")?;
+    let url = args.url.clone().or(file.connection_string);
+
+    match backend {
+        #[cfg(feature = "postgres")]
+        Backend::Postgres => {
+            let mut config = PostgresConfig::default();
+            if let Some(url) = url {
+                config.connection_string = url;
+            }
+            if let Some(table) = args.units_table.clone().or(file.log_units_table) {
+                config.log_units_table = table;
+            }
+            if let Some(table) = args.entries_table.clone().or(file.log_entries_table) {
+                config.log_entries_table = table;
+            }
+            if let Some(text_search_config) = file.text_search_config {
+                config.text_search_config = text_search_config;
+            }
+            Ok(Arc::new(PostgresDestination::connect_existing(config).await?))
+        }
+        #[cfg(feature = "mongo")]
+        Backend::Mongo => {
+            let mut config = MongoConfig::default();
+            if let Some(url) = url {
+                config.connection_string = url;
+            }
+            if let Some(database) = args.database.clone().or(file.database_name) {
+                config.database_name = database;
+            }
+            if let Some(collection) = args.units_table.clone().or(file.log_units_collection) {
+                config.log_units_collection = collection;
+            }
+            if let Some(collection) = args.entries_table.clone().or(file.log_entries_collection) {
+                config.log_entries_collection = collection;
+            }
+            Ok(Arc::new(MongoDestination::new(config).await?))
+        }
+    }
+}
+
+/// Writes records as colored text or JSON lines
+struct Output {
+    json: bool,
+    locations: bool,
+}
+
+impl Output {
+    fn print_json(&self, value: &impl Serialize) -> LogResult<()> {
+        println!("{}", serde_json::to_string(value)?);
+        Ok(())
+    }
+
+    fn unit(&self, unit: &LogUnit) -> LogResult<()> {
+        if self.json {
+            return self.print_json(unit);
+        }
+        println!(
+            "[{}] {} {}",
+            unit.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            unit.log_unit_id,
+            unit.external_id
+        );
+        Ok(())
+    }
+
+    fn entry(&self, entry: &LogEntry) -> LogResult<()> {
+        if self.json {
+            return self.print_json(entry);
+        }
+        println!("{}", self.format_entry(entry));
+        Ok(())
+    }
+
+    fn format_entry(&self, entry: &LogEntry) -> String {
+        if self.locations {
+            ConsoleDestination::format_entry_with_location(entry)
+        } else {
+            ConsoleDestination::format_entry(entry)
+        }
+    }
+
+    fn hit(&self, hit: &SearchHit) -> LogResult<()> {
+        if self.json {
+            return self.print_json(hit);
+        }
+        let external_id = hit.log_unit.as_ref().map_or("?", |unit| unit.external_id.as_str());
+        let shown = LogEntry {
+            message: format!("{} ({}, score {:.3})", hit.snippet, external_id, hit.score),
+            ..hit.entry.clone()
+        };
+        println!("{}", self.format_entry(&shown));
+        Ok(())
+    }
+}
+
+fn print_report(action: &str, report: &ArchiveReport) {
+    eprintln!("{} {} units with {} entries", action, report.units, report.entries);
+    if report.skipped_units > 0 || report.skipped_entries > 0 {
+        eprintln!(
+            "Skipped {} units and {} entries that already exist",
+            report.skipped_units,
+            report.skipped_entries
+        );
+    }
+}
+
+/// Loads the entries of a unit, or of all units with an external ID, oldest first
+async fn load_entries(
+    service: &dyn LogService,
+    unit: Option<Uuid>,
+    external_id: Option<&str>,
+) -> LogResult<Vec<LogEntry>> {
+    let log_unit_ids = match (unit, external_id) {
+        (Some(unit), _) => vec![unit],
+        (None, Some(external_id)) => service
+            .get_log_units_by_external_id(external_id)
+            .await?
+            .into_iter()
+            .map(|unit| unit.log_unit_id)
+            .collect(),
+        (None, None) => Vec::new(),
+    };
+
+    let mut entries = Vec::new();
+    for log_unit_id in log_unit_ids {
+        entries.extend(service.get_log_entries(log_unit_id).await?);
+    }
+    entries.sort_by_key(|entry| entry.timestamp);
+    Ok(entries)
+}
+
+async fn run(cli: Cli) -> LogResult<()> {
+    let service = connect(&cli.connection).await?;
+    let output = Output {
+        json: cli.json,
+        locations: cli.locations,
+    };
+
+    match cli.command {
+        Command::Units { command: UnitsCommand::List { external_id } } => {
+            let mut units = service.get_log_units_by_external_id(&external_id).await?;
+            units.sort_by_key(|unit| unit.timestamp);
+            for unit in &units {
+                output.unit(unit)?;
+            }
+        }
+        Command::Entries { command: EntriesCommand::Show { unit, filter } } => {
+            for entry in service.get_log_entries(unit).await? {
+                if filter.matches(&entry) {
+                    output.entry(&entry)?;
+                }
+            }
+        }
+        Command::Search { text, external_id, limit, filter } => {
+            let (highlight_start, highlight_end) = if cli.json {
+                let defaults = SearchOptions::default();
+                (defaults.highlight_start, defaults.highlight_end)
+            } else {
+                (HIGHLIGHT_START.to_string(), HIGHLIGHT_END.to_string())
+            };
+            let options = SearchOptions {
+                limit,
+                min_level: filter.min_level,
+                external_id,
+                from: filter.start(),
+                to: filter.until,
+                module_path: filter.module,
+                highlight_start,
+                highlight_end,
+            };
+            for hit in service.search_entries(&text, &options).await? {
+                output.hit(&hit)?;
+            }
+        }
+        Command::Tail { unit, external_id, lines, follow, filter } => {
+            if unit.is_none() && external_id.is_none() && !follow {
+                return Err("Give --unit or --external-id, or --follow to see all new entries".into());
+            }
+
+            // Subscribe first so that nothing written while loading is lost
+            let subscription = if follow {
+                let subscription_filter = SubscriptionFilter {
+                    log_unit_id: unit,
+                    external_id: external_id.clone(),
+                    min_level: filter.min_level,
+                };
+                Some(service.subscribe(subscription_filter).await?)
+            } else {
+                None
+            };
+
+            let entries: Vec<LogEntry> = load_entries(service.as_ref(), unit, external_id.as_deref())
+                .await?
+                .into_iter()
+                .filter(|entry| filter.matches(entry))
+                .collect();
+            for entry in &entries[entries.len().saturating_sub(lines)..] {
+                output.entry(entry)?;
+            }
+
+            if let Some(mut subscription) = subscription {
+                // Entries written while loading arrive through both paths
+                let mut loaded: HashSet<Uuid> = entries.iter().map(|entry| entry.message_id).collect();
+                while let Some(entry) = subscription.next().await {
+                    if !loaded.remove(&entry.message_id) && filter.matches(&entry) {
+                        output.entry(&entry)?;
+                    }
+                }
+            }
+        }
+        Command::Export { output, gzip, external_id, since, from, until } => {
+            let options = ExportOptions {
+                from: since.map(|age| Utc::now() - age).max(from),
+                to: until,
+                external_id,
+                compression: if gzip { Compression::Gzip } else { Compression::None },
+            };
+            let report = match output {
+                Some(path) => archive::export(service.as_ref(), &options, tokio::fs::File::create(path).await?).await?,
+                None => archive::export(service.as_ref(), &options, tokio::io::stdout()).await?,
+            };
+            print_report("Exported", &report);
+        }
+        Command::Import { input } => {
+            let report = if input.as_os_str() == "-" {
+                archive::import(service.as_ref(), tokio::io::stdin()).await?
+            } else {
+                archive::import(service.as_ref(), tokio::fs::File::open(input).await?).await?
+            };
+            print_report("Imported", &report);
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run(Cli::parse()).await {
+        eprintln!("ironscribe: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30m"), Ok(Duration::minutes(30)));
+        assert_eq!(parse_age("2h"), Ok(Duration::hours(2)));
+        assert_eq!(parse_age("7d"), Ok(Duration::days(7)));
+        assert!(parse_age("7y").is_err());
+        assert!(parse_age("d").is_err());
+    }
+
+    #[test]
+    fn test_config_file_uses_config_field_names() {
+        let file: ConfigFile = serde_json::from_str(
+            r#"{"connection_string": "postgresql://db/logs", "log_entries_table": "app.entries", "purge_batch_size": 500}"#,
+        ).unwrap();
+
+        assert!(file.backend.is_none());
+        assert_eq!(file.connection_string.as_deref(), Some("postgresql://db/logs"));
+        assert_eq!(file.log_entries_table.as_deref(), Some("app.entries"));
+        assert!(file.log_units_table.is_none());
+    }
+}
@@ -0,0 +1,260 @@
+//! Current log handle and unit, so code can log without passing them around
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::sync::RwLock;
+use tokio::task::JoinHandle;
+
+use crate::core::{LogEntry, LogMessageType, LogUnit, SourceLocation, TraceContext};
+use crate::service::LogHandle;
+
+/// Handle and unit that context-aware macros such as `info!("...")` log to
+#[derive(Clone)]
+pub struct LogContext {
+    handle: Option<LogHandle>,
+    unit: LogUnit,
+    trace: Option<TraceContext>,
+}
+
+impl LogContext {
+    /// Creates a context logging to `unit` through `handle`
+    pub fn new(handle: LogHandle, unit: LogUnit) -> Self {
+        Self {
+            handle: Some(handle),
+            unit,
+            trace: None,
+        }
+    }
+
+    /// Records `trace` on every entry logged in this context
+    pub fn with_trace_context(mut self, trace: TraceContext) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// Returns the handle entries are logged through, if one is in scope
+    pub fn handle(&self) -> Option<&LogHandle> {
+        self.handle.as_ref()
+    }
+
+    /// Returns the unit entries are logged to
+    pub fn unit(&self) -> &LogUnit {
+        &self.unit
+    }
+
+    /// Returns the trace context recorded on entries, if any
+    pub fn trace_context(&self) -> Option<&TraceContext> {
+        self.trace.as_ref()
+    }
+}
+
+tokio::task_local! {
+    static TASK_CONTEXT: LogContext;
+}
+
+thread_local! {
+    static THREAD_CONTEXT: RefCell<Option<LogContext>> = const { RefCell::new(None) };
+}
+
+/// Handle `scope` logs through when no context is current
+static DEFAULT_HANDLE: RwLock<Option<LogHandle>> = RwLock::new(None);
+
+/// Makes `handle` the process-wide default used by `scope` outside of any context.
+///
+/// The handle, and the destination it writes to, are kept alive until another
+/// default is set or `clear_default_handle` is called.
+pub fn set_default_handle(handle: LogHandle) {
+    *DEFAULT_HANDLE.write().unwrap_or_else(|e| e.into_inner()) = Some(handle);
+}
+
+/// Removes the process-wide default handle, releasing it
+pub fn clear_default_handle() {
+    DEFAULT_HANDLE.write().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// Returns the process-wide default handle, unless its background task has stopped
+pub fn default_handle() -> Option<LogHandle> {
+    DEFAULT_HANDLE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .filter(|handle| !handle.is_closed())
+}
+
+/// Returns the current context.
+///
+/// The context of the current task scope takes precedence over the one
+/// entered on the current thread.
+pub fn current() -> Option<LogContext> {
+    TASK_CONTEXT
+        .try_with(|context| context.clone())
+        .ok()
+        .or_else(|| THREAD_CONTEXT.with(|context| context.borrow().clone()))
+}
+
+/// Runs `future` with `context` as the current context
+pub async fn scope_context<F: Future>(context: LogContext, future: F) -> F::Output {
+    TASK_CONTEXT.scope(context, future).await
+}
+
+/// Runs `future` logging to `unit` through `handle`
+pub async fn scope_with<F: Future>(handle: LogHandle, unit: LogUnit, future: F) -> F::Output {
+    scope_context(LogContext::new(handle, unit), future).await
+}
+
+/// Runs `future` logging to `unit` through the handle of the current context.
+///
+/// The trace context of the current context is kept as well. Without a
+/// current context, the default handle is used, which is only set explicitly,
+/// e.g. with `DefaultLogService::set_as_default` or `set_default_handle`.
+/// Without either, logging from `future` fails with an error printed to
+/// stderr; use `scope_with` to pass the handle explicitly.
+pub async fn scope<F: Future>(unit: LogUnit, future: F) -> F::Output {
+    let (handle, trace) = current().map_or((None, None), |context| (context.handle, context.trace));
+    let handle = handle.or_else(default_handle);
+    scope_context(LogContext { handle, unit, trace }, future).await
+}
+
+/// Makes `context` current on this thread until the returned guard is dropped.
+///
+/// This is the fallback for code that does not run inside a task scope, for
+/// example futures driven by a single-threaded runtime on the calling thread.
+pub fn enter(context: LogContext) -> ContextGuard {
+    let previous = THREAD_CONTEXT.with(|current| current.replace(Some(context)));
+    ContextGuard { previous }
+}
+
+/// Restores the previous thread context when dropped
+#[must_use = "the context is left as soon as the guard is dropped"]
+pub struct ContextGuard {
+    previous: Option<LogContext>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        THREAD_CONTEXT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Wraps `future` so that it runs with the context current at the time of the call
+pub fn in_current_scope<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let context = current();
+    async move {
+        match context {
+            Some(context) => scope_context(context, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Spawns a task that inherits the current context
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(in_current_scope(future))
+}
+
+/// Runs a blocking closure on the blocking thread pool with the current context entered
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let context = current();
+    tokio::task::spawn_blocking(move || {
+        let _guard = context.map(enter);
+        f()
+    })
+}
+
+/// Queues `message` on the current context without blocking; used by the context-aware macros
+pub fn log_in_scope(message_type: LogMessageType, message: String, location: SourceLocation) {
+    let kind = match message_type {
+        LogMessageType::Error => "error",
+        LogMessageType::Warning => "warning",
+        LogMessageType::Info => "info",
+        LogMessageType::Success => "success",
+    };
+
+    let Some(context) = current() else {
+        eprintln!("Failed to log {} message: no log unit in scope: {}", kind, message);
+        return;
+    };
+    let Some(handle) = context.handle else {
+        eprintln!("Failed to log {} message: no log handle in scope: {}", kind, message);
+        return;
+    };
+
+    let mut entry = LogEntry::new(context.unit.log_unit_id, message, message_type).with_location(location);
+    entry.trace = context.trace;
+    if let Err(e) = handle.try_log(entry) {
+        eprintln!("Failed to log {} message: {}", kind, e);
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "console")]
+mod tests {
+    use super::*;
+    use crate::core::LogService;
+    use crate::service::DefaultLogService;
+
+    #[tokio::test]
+    async fn test_scope_propagates_into_spawned_tasks() {
+        let service = DefaultLogService::new();
+        let parent = service.create_log_unit("parent".to_string()).await.unwrap();
+        let child = service.create_log_unit("child".to_string()).await.unwrap();
+
+        scope_with(service.handle(), parent.clone(), async {
+            crate::info!("In parent");
+            spawn(async { crate::warn!("In spawned task") }).await.unwrap();
+            scope(child.clone(), async { crate::error!("In child {}", 1) }).await;
+            spawn_blocking(|| assert_eq!(current().map(|c| c.unit().external_id.clone()), Some("parent".to_string())))
+                .await
+                .unwrap();
+        })
+        .await;
+
+        service.handle().flush().await.unwrap();
+        assert!(current().is_none());
+        assert_eq!(service.get_log_entries(parent.log_unit_id).await.unwrap().len(), 2);
+        let child_entries = service.get_log_entries(child.log_unit_id).await.unwrap();
+        assert_eq!(child_entries[0].message, "In child 1");
+    }
+
+    #[tokio::test]
+    async fn test_thread_context_fallback() {
+        let service = DefaultLogService::new();
+        let unit = service.create_log_unit("thread".to_string()).await.unwrap();
+        let context = LogContext::new(service.handle(), unit.clone());
+
+        // A plain thread outside the runtime, logging from synchronous code
+        std::thread::spawn(move || {
+            let _guard = enter(context);
+            crate::success!("From the thread context");
+        })
+        .join()
+        .unwrap();
+
+        service.handle().flush().await.unwrap();
+        assert!(current().is_none());
+        assert_eq!(service.get_log_entries(unit.log_unit_id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_trace_context_is_recorded_on_entries() {
+        let service = DefaultLogService::new();
+        let request = service.create_log_unit("request".to_string()).await.unwrap();
+        let trace = TraceContext::from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        let context = LogContext::new(service.handle(), request.clone()).with_trace_context(trace.clone());
+
+        scope_context(context, async { crate::info!("Handling request") }).await;
+
+        service.handle().flush().await.unwrap();
+        let entries = service.get_log_entries(request.log_unit_id).await.unwrap();
+        assert_eq!(entries[0].trace, Some(trace));
+    }
+}
@@ -0,0 +1,214 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::core::{LogEntry, LogLevel, LogUnit};
+
+/// Selects the entries counted by the analytics queries
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsFilter {
+    /// Only entries logged at or after this time
+    pub from: Option<DateTime<Utc>>,
+    /// Only entries logged before this time
+    pub to: Option<DateTime<Utc>>,
+    /// Only entries of units with this external ID
+    pub external_id: Option<String>,
+    /// Only entries at least as severe as this level
+    pub min_level: Option<LogLevel>,
+    /// Only entries logged from this module or its submodules
+    pub module_path: Option<String>,
+}
+
+impl StatsFilter {
+    /// Creates a filter that counts every entry
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Restricts the filter to entries logged at or after `from` and before `to`
+    pub fn between(mut self, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        self.from = Some(from);
+        self.to = Some(to);
+        self
+    }
+
+    /// Restricts the filter to units with the given external ID
+    pub fn external_id(mut self, external_id: impl Into<String>) -> Self {
+        self.external_id = Some(external_id.into());
+        self
+    }
+
+    /// Restricts the filter to entries at least as severe as `level`
+    pub fn min_level(mut self, level: LogLevel) -> Self {
+        self.min_level = Some(level);
+        self
+    }
+
+    /// Restricts the filter to entries logged from `module` or its submodules
+    pub fn module_path(mut self, module: impl Into<String>) -> Self {
+        self.module_path = Some(module.into());
+        self
+    }
+
+    /// Returns whether `entry` of `log_unit` is counted
+    pub fn matches(&self, log_unit: Option<&LogUnit>, entry: &LogEntry) -> bool {
+        self.from.is_none_or(|from| entry.timestamp >= from)
+            && self.to.is_none_or(|to| entry.timestamp < to)
+            && self.min_level.is_none_or(|level| entry.level.is_at_least(level))
+            && self.module_path.as_ref().is_none_or(|module| entry.is_in_module(module))
+            && self
+                .external_id
+                .as_ref()
+                .is_none_or(|external_id| log_unit.is_some_and(|unit| &unit.external_id == external_id))
+    }
+}
+
+/// Number of entries per log level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelCounts {
+    pub error: u64,
+    pub warning: u64,
+    pub info: u64,
+    pub success: u64,
+}
+
+impl LevelCounts {
+    /// Returns the count for `level`
+    pub fn get(&self, level: LogLevel) -> u64 {
+        match level {
+            LogLevel::Error => self.error,
+            LogLevel::Warning => self.warning,
+            LogLevel::Info => self.info,
+            LogLevel::Success => self.success,
+        }
+    }
+
+    /// Adds `count` entries of `level`
+    pub fn add(&mut self, level: LogLevel, count: u64) {
+        match level {
+            LogLevel::Error => self.error += count,
+            LogLevel::Warning => self.warning += count,
+            LogLevel::Info => self.info += count,
+            LogLevel::Success => self.success += count,
+        }
+    }
+
+    /// Returns the number of entries of all levels
+    pub fn total(&self) -> u64 {
+        self.error + self.warning + self.info + self.success
+    }
+}
+
+/// Width of the time buckets of a histogram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBucket {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl TimeBucket {
+    /// Returns the width of the bucket in seconds
+    pub fn seconds(self) -> i64 {
+        match self {
+            TimeBucket::Minute => 60,
+            TimeBucket::Hour => 60 * 60,
+            TimeBucket::Day => 24 * 60 * 60,
+        }
+    }
+
+    /// Returns the start of the bucket containing `timestamp`
+    pub fn start_of(self, timestamp: DateTime<Utc>) -> DateTime<Utc> {
+        let seconds = timestamp.timestamp();
+        DateTime::from_timestamp(seconds - seconds.rem_euclid(self.seconds()), 0).unwrap_or(timestamp)
+    }
+}
+
+/// Entry counts of one time bucket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    /// Start of the bucket
+    pub start: DateTime<Utc>,
+    pub counts: LevelCounts,
+}
+
+/// Order of the units returned by `LogService::top_units`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnitRanking {
+    /// Most entries of any level first
+    #[default]
+    Entries,
+    /// Most error entries first
+    Errors,
+}
+
+impl UnitRanking {
+    /// Returns the count units are ranked by
+    pub fn key(self, counts: &LevelCounts) -> u64 {
+        match self {
+            UnitRanking::Entries => counts.total(),
+            UnitRanking::Errors => counts.error,
+        }
+    }
+}
+
+/// Entry counts of one log unit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitStats {
+    pub log_unit_id: Uuid,
+    /// The unit the entries belong to, if it still exists
+    pub log_unit: Option<LogUnit>,
+    pub counts: LevelCounts,
+}
+
+/// Entry counts of all units sharing an external ID
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExternalIdStats {
+    pub external_id: String,
+    pub counts: LevelCounts,
+}
+
+/// Sorts `units` by `ranking`, most first, and keeps the first `limit`
+#[cfg(feature = "console")]
+pub(crate) fn rank_units(units: &mut Vec<UnitStats>, ranking: UnitRanking, limit: usize) {
+    units.sort_by(|a, b| {
+        ranking
+            .key(&b.counts)
+            .cmp(&ranking.key(&a.counts))
+            .then_with(|| b.counts.total().cmp(&a.counts.total()))
+    });
+    units.truncate(limit);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_bucket_start() {
+        let timestamp = DateTime::parse_from_rfc3339("2025-03-14T15:09:26.5Z").unwrap().with_timezone(&Utc);
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+
+        assert_eq!(TimeBucket::Minute.start_of(timestamp), at("2025-03-14T15:09:00Z"));
+        assert_eq!(TimeBucket::Hour.start_of(timestamp), at("2025-03-14T15:00:00Z"));
+        assert_eq!(TimeBucket::Day.start_of(timestamp), at("2025-03-14T00:00:00Z"));
+    }
+
+    #[test]
+    #[cfg(feature = "console")]
+    fn test_rank_units() {
+        let stats = |error, info| UnitStats {
+            log_unit_id: Uuid::new_v4(),
+            log_unit: None,
+            counts: LevelCounts { error, info, ..Default::default() },
+        };
+        let mut units = vec![stats(1, 10), stats(5, 0), stats(2, 2)];
+
+        rank_units(&mut units, UnitRanking::Errors, 2);
+        assert_eq!(units.iter().map(|u| u.counts.error).collect::<Vec<_>>(), vec![5, 2]);
+
+        let mut units = vec![stats(1, 10), stats(5, 0), stats(2, 2)];
+        rank_units(&mut units, UnitRanking::Entries, 1);
+        assert_eq!(units[0].counts.total(), 11);
+    }
+}
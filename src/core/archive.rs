@@ -0,0 +1,281 @@
+use async_compression::tokio::bufread::GzipDecoder;
+use async_compression::tokio::write::GzipEncoder;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use uuid::Uuid;
+
+use crate::core::log_service::LogResult;
+use crate::core::{LogEntry, LogService, LogUnit};
+
+/// Value of `format` in the header of every archive
+pub const ARCHIVE_FORMAT: &str = "ironscribe-archive";
+
+/// Newest archive layout this version can read, and the one it writes
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// Gzip streams start with these bytes
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Compression applied to an exported archive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+}
+
+/// Selects the log units written by `export`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportOptions {
+    /// Only units created at or after this time
+    pub from: Option<DateTime<Utc>>,
+    /// Only units created before this time
+    pub to: Option<DateTime<Utc>>,
+    /// Only units with this external ID
+    pub external_id: Option<String>,
+    pub compression: Compression,
+}
+
+/// First line of an archive, describing its contents
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ArchiveHeader {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    /// Time range the units were selected from
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Line of an archive after the header; every unit is followed by its entries
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ArchiveRecord {
+    Unit(LogUnit),
+    Entry(LogEntry),
+}
+
+/// Number of records written by `export` or replayed by `import`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveReport {
+    pub units: u64,
+    pub entries: u64,
+    /// Units not imported because the destination already has them
+    pub skipped_units: u64,
+    /// Entries not imported because the destination already has them
+    pub skipped_entries: u64,
+}
+
+/// Writes the selected log units and their entries as an NDJSON archive.
+///
+/// The writer is shut down once the archive is complete.
+pub async fn export<S, W>(service: &S, options: &ExportOptions, writer: W) -> LogResult<ArchiveReport>
+where
+    S: LogService + ?Sized,
+    W: AsyncWrite + Unpin + Send,
+{
+    match options.compression {
+        Compression::None => write_archive(service, options, writer).await,
+        Compression::Gzip => write_archive(service, options, GzipEncoder::new(writer)).await,
+    }
+}
+
+/// Replays an archive written by `export` into `service`, keeping all IDs and timestamps.
+///
+/// Gzip compression is detected automatically. Units and entries the
+/// destination already has are skipped, compared by ID, so an interrupted
+/// import can be run again.
+pub async fn import<S, R>(service: &S, reader: R) -> LogResult<ArchiveReport>
+where
+    S: LogService + ?Sized,
+    R: AsyncRead + Unpin + Send,
+{
+    let mut reader = BufReader::new(reader);
+    if reader.fill_buf().await?.starts_with(&GZIP_MAGIC) {
+        let mut decoder = GzipDecoder::new(reader);
+        decoder.multiple_members(true);
+        read_archive(service, BufReader::new(decoder)).await
+    } else {
+        read_archive(service, reader).await
+    }
+}
+
+async fn write_line<W: AsyncWrite + Unpin>(writer: &mut W, value: &impl Serialize) -> LogResult<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+async fn write_archive<S, W>(service: &S, options: &ExportOptions, mut writer: W) -> LogResult<ArchiveReport>
+where
+    S: LogService + ?Sized,
+    W: AsyncWrite + Unpin + Send,
+{
+    let header = ArchiveHeader {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now(),
+        from: options.from,
+        to: options.to,
+    };
+    write_line(&mut writer, &header).await?;
+
+    let units = match &options.external_id {
+        Some(external_id) => {
+            let mut units: Vec<LogUnit> = service
+                .get_log_units_by_external_id(external_id)
+                .await?
+                .into_iter()
+                .filter(|unit| options.from.is_none_or(|from| unit.timestamp >= from))
+                .filter(|unit| options.to.is_none_or(|to| unit.timestamp < to))
+                .collect();
+            units.sort_by_key(|unit| unit.timestamp);
+            units
+        }
+        None => service.list_log_units(options.from, options.to).await?,
+    };
+
+    let mut report = ArchiveReport::default();
+    for unit in units {
+        let entries = service.get_log_entries(unit.log_unit_id).await?;
+        write_line(&mut writer, &ArchiveRecord::Unit(unit)).await?;
+        report.units += 1;
+
+        for entry in entries {
+            write_line(&mut writer, &ArchiveRecord::Entry(entry)).await?;
+            report.entries += 1;
+        }
+    }
+
+    // Also writes the gzip trailer when compressing
+    writer.shutdown().await?;
+    Ok(report)
+}
+
+async fn read_archive<S, R>(service: &S, reader: R) -> LogResult<ArchiveReport>
+where
+    S: LogService + ?Sized,
+    R: AsyncBufRead + Unpin + Send,
+{
+    let mut lines = reader.lines();
+    let header = lines.next_line().await?.ok_or("Archive is empty")?;
+    let header: ArchiveHeader = serde_json::from_str(&header)
+        .map_err(|e| format!("Invalid archive header: {}", e))?;
+    if header.format != ARCHIVE_FORMAT {
+        return Err(format!("Unknown archive format: {}", header.format).into());
+    }
+    if header.version > ARCHIVE_VERSION {
+        return Err(format!("Unsupported archive version: {}", header.version).into());
+    }
+
+    let mut report = ArchiveReport::default();
+    // Message IDs stored for the units that existed before the import
+    let mut existing: HashMap<Uuid, HashSet<Uuid>> = HashMap::new();
+    let mut line_number = 1;
+
+    while let Some(line) = lines.next_line().await? {
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record: ArchiveRecord = serde_json::from_str(&line)
+            .map_err(|e| format!("Invalid archive record on line {}: {}", line_number, e))?;
+        match record {
+            ArchiveRecord::Unit(unit) => {
+                if service.get_log_unit(unit.log_unit_id).await?.is_some() {
+                    let entries = service.get_log_entries(unit.log_unit_id).await?;
+                    existing.insert(unit.log_unit_id, entries.into_iter().map(|entry| entry.message_id).collect());
+                    report.skipped_units += 1;
+                } else {
+                    service.insert_log_unit(unit).await?;
+                    report.units += 1;
+                }
+            }
+            ArchiveRecord::Entry(entry) => {
+                let stored = existing
+                    .get(&entry.log_unit_id)
+                    .is_some_and(|message_ids| message_ids.contains(&entry.message_id));
+                if stored {
+                    report.skipped_entries += 1;
+                } else {
+                    service.log(entry).await?;
+                    report.entries += 1;
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+#[cfg(feature = "console")]
+mod tests {
+    use super::*;
+    use crate::destinations::ConsoleDestination;
+
+    #[tokio::test]
+    async fn test_export_import_round_trip() {
+        let source = ConsoleDestination::new();
+        let unit = source.create_log_unit("export".to_string()).await.unwrap();
+        source.log(LogEntry::info(unit.log_unit_id, "First".to_string())).await.unwrap();
+        source.log(LogEntry::error(unit.log_unit_id, "Second".to_string())).await.unwrap();
+
+        for compression in [Compression::None, Compression::Gzip] {
+            let options = ExportOptions { compression, ..Default::default() };
+            let mut archive = Vec::new();
+            let exported = export(&source, &options, &mut archive).await.unwrap();
+            assert_eq!(exported, ArchiveReport { units: 1, entries: 2, ..Default::default() });
+            assert_eq!(archive.starts_with(&GZIP_MAGIC), compression == Compression::Gzip);
+
+            let target = ConsoleDestination::new();
+            let imported = import(&target, archive.as_slice()).await.unwrap();
+            assert_eq!(imported, exported);
+            assert_eq!(target.get_log_unit(unit.log_unit_id).await.unwrap(), Some(unit.clone()));
+            assert_eq!(
+                target.get_log_entries(unit.log_unit_id).await.unwrap(),
+                source.get_log_entries(unit.log_unit_id).await.unwrap()
+            );
+
+            let again = import(&target, archive.as_slice()).await.unwrap();
+            assert_eq!(again, ArchiveReport { units: 0, entries: 0, skipped_units: 1, skipped_entries: 2 });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_import_can_be_resumed() {
+        let source = ConsoleDestination::new();
+        let unit = source.create_log_unit("resume".to_string()).await.unwrap();
+        let first = LogEntry::info(unit.log_unit_id, "First".to_string());
+        source.log(first.clone()).await.unwrap();
+        source.log(LogEntry::info(unit.log_unit_id, "Second".to_string())).await.unwrap();
+
+        let mut archive = Vec::new();
+        export(&source, &ExportOptions::default(), &mut archive).await.unwrap();
+
+        // The unit and its first entry made it before the import stopped
+        let target = ConsoleDestination::new();
+        target.insert_log_unit(unit.clone()).await.unwrap();
+        target.log(first).await.unwrap();
+
+        let resumed = import(&target, archive.as_slice()).await.unwrap();
+        assert_eq!(resumed, ArchiveReport { units: 0, entries: 1, skipped_units: 1, skipped_entries: 1 });
+        assert_eq!(
+            target.get_log_entries(unit.log_unit_id).await.unwrap(),
+            source.get_log_entries(unit.log_unit_id).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_foreign_files() {
+        let target = ConsoleDestination::new();
+        let error = import(&target, &b"{\"format\":\"other\",\"version\":1,\"exported_at\":\"2025-01-01T00:00:00Z\"}\n"[..])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Unknown archive format"));
+    }
+}
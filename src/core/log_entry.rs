@@ -1,9 +1,13 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 use uuid::Uuid;
 
 /// Represents the type of log message
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum LogMessageType {
     Error,
     Warning,
@@ -12,7 +16,7 @@ pub enum LogMessageType {
 }
 
 /// Numeric log levels for sorting
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum LogLevel {
     Error = 0,
     Warning = 1,
@@ -20,6 +24,36 @@ pub enum LogLevel {
     Success = 3,
 }
 
+impl LogLevel {
+    /// All log levels, from most to least severe
+    pub const ALL: [LogLevel; 4] = [
+        LogLevel::Error,
+        LogLevel::Warning,
+        LogLevel::Info,
+        LogLevel::Success,
+    ];
+
+    /// Returns whether this level is at least as severe as `threshold`
+    pub fn is_at_least(self, threshold: LogLevel) -> bool {
+        self <= threshold
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    /// Parses a level name such as `error` or `WARN`, ignoring case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warning" | "warn" => Ok(LogLevel::Warning),
+            "info" => Ok(LogLevel::Info),
+            "success" => Ok(LogLevel::Success),
+            _ => Err(format!("Unknown log level: {}", s)),
+        }
+    }
+}
+
 impl From<LogMessageType> for LogLevel {
     fn from(msg_type: LogMessageType) -> Self {
         match msg_type {
@@ -31,6 +65,134 @@ impl From<LogMessageType> for LogLevel {
     }
 }
 
+/// Place in the source code an entry was logged from
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    /// Module path of the logging code, e.g. `my_app::worker`
+    pub module_path: String,
+}
+
+impl SourceLocation {
+    /// Creates a source location; see the `source_location!` macro
+    pub fn new(file: &str, line: u32, column: u32, module_path: &str) -> Self {
+        Self {
+            file: file.to_string(),
+            line,
+            column,
+            module_path: module_path.to_string(),
+        }
+    }
+
+    /// Returns whether the location is in `module` or one of its submodules
+    pub fn is_in_module(&self, module: &str) -> bool {
+        self.module_path
+            .strip_prefix(module)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{} in {}", self.file, self.line, self.column, self.module_path)
+    }
+}
+
+/// Error recorded on an entry, with its chain of sources
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ErrorDetails {
+    /// The error followed by each of its sources, outermost first
+    pub chain: Vec<String>,
+    /// Backtrace captured when the entry was created, if enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backtrace: Option<String>,
+}
+
+impl ErrorDetails {
+    /// Records the chain of `error`.
+    ///
+    /// A backtrace is captured when enabled through `RUST_BACKTRACE` or
+    /// `RUST_LIB_BACKTRACE`, like `Backtrace::capture`.
+    pub fn new(error: &dyn Error) -> Self {
+        let mut chain = Vec::new();
+        let mut current = Some(error);
+        while let Some(error) = current {
+            chain.push(error.to_string());
+            current = error.source().map(|source| source as &dyn Error);
+        }
+
+        Self { chain, backtrace: None }.with_backtrace(&Backtrace::capture())
+    }
+
+    /// Records `backtrace`, unless it was not captured
+    pub fn with_backtrace(mut self, backtrace: &Backtrace) -> Self {
+        if backtrace.status() == BacktraceStatus::Captured {
+            self.backtrace = Some(backtrace.to_string());
+        }
+        self
+    }
+}
+
+/// W3C trace context of the span an entry was logged in
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TraceContext {
+    /// Trace ID as 32 lowercase hex digits
+    pub trace_id: String,
+    /// Span ID as 16 lowercase hex digits
+    pub span_id: String,
+}
+
+impl TraceContext {
+    /// Creates a trace context from binary trace and span IDs
+    pub fn new(trace_id: [u8; 16], span_id: [u8; 8]) -> Self {
+        Self {
+            trace_id: to_hex(&trace_id),
+            span_id: to_hex(&span_id),
+        }
+    }
+
+    /// Parses a W3C `traceparent` header such as
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`
+    pub fn from_traceparent(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let (_version, trace_id, span_id, _flags) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        let trace_id = from_hex::<16>(trace_id)?;
+        let span_id = from_hex::<8>(span_id)?;
+        // All-zero IDs are invalid
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(Self::new(trace_id, span_id))
+    }
+
+    /// Returns the trace ID as bytes, or `None` if it is not 32 hex digits
+    pub fn trace_id_bytes(&self) -> Option<[u8; 16]> {
+        from_hex(&self.trace_id)
+    }
+
+    /// Returns the span ID as bytes, or `None` if it is not 16 hex digits
+    pub fn span_id_bytes(&self) -> Option<[u8; 8]> {
+        from_hex(&self.span_id)
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != 2 * N || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
 /// Represents a single log entry/message
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct LogEntry {
@@ -46,6 +208,15 @@ pub struct LogEntry {
     pub message_type: LogMessageType,
     /// Timestamp when the log message was created
     pub timestamp: DateTime<Utc>,
+    /// Where the entry was logged from, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<SourceLocation>,
+    /// Error the entry reports, if it was logged from one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetails>,
+    /// Trace context of the span the entry was logged in, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<TraceContext>,
 }
 
 impl LogEntry {
@@ -62,9 +233,29 @@ impl LogEntry {
             message,
             message_type,
             timestamp: Utc::now(),
+            location: None,
+            error: None,
+            trace: None,
         }
     }
 
+    /// Records where the entry was logged from
+    pub fn with_location(mut self, location: SourceLocation) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    /// Records the trace context of the span the entry was logged in
+    pub fn with_trace_context(mut self, trace: TraceContext) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// Returns whether the entry was logged from `module` or one of its submodules
+    pub fn is_in_module(&self, module: &str) -> bool {
+        self.location.as_ref().is_some_and(|location| location.is_in_module(module))
+    }
+
     /// Creates an error log entry
     pub fn error(log_unit_id: Uuid, message: String) -> Self {
         Self::new(log_unit_id, message, LogMessageType::Error)
@@ -84,6 +275,17 @@ impl LogEntry {
     pub fn success(log_unit_id: Uuid, message: String) -> Self {
         Self::new(log_unit_id, message, LogMessageType::Success)
     }
+
+    /// Creates an error log entry reporting `error`, with the error as message
+    pub fn from_error(log_unit_id: Uuid, error: &dyn Error) -> Self {
+        Self::error(log_unit_id, error.to_string()).with_error(error)
+    }
+
+    /// Records `error` and its chain of sources; see `ErrorDetails::new`
+    pub fn with_error(mut self, error: &dyn Error) -> Self {
+        self.error = Some(ErrorDetails::new(error));
+        self
+    }
 }
 
 #[cfg(test)]
@@ -109,6 +311,60 @@ mod tests {
         assert!(LogLevel::Info < LogLevel::Success);
     }
 
+    #[test]
+    fn test_log_level_from_str() {
+        assert_eq!("error".parse::<LogLevel>(), Ok(LogLevel::Error));
+        assert_eq!("WARN".parse::<LogLevel>(), Ok(LogLevel::Warning));
+        assert_eq!("Success".parse::<LogLevel>(), Ok(LogLevel::Success));
+        assert!("verbose".parse::<LogLevel>().is_err());
+    }
+
+    #[test]
+    fn test_source_location_module_match() {
+        let location = SourceLocation::new("src/worker.rs", 12, 5, "my_app::worker::jobs");
+
+        assert!(location.is_in_module("my_app::worker"));
+        assert!(location.is_in_module("my_app::worker::jobs"));
+        assert!(!location.is_in_module("my_app::work"));
+        assert_eq!(location.to_string(), "src/worker.rs:12:5 in my_app::worker::jobs");
+    }
+
+    #[test]
+    fn test_from_error_records_chain() {
+        #[derive(Debug)]
+        struct ConfigError(std::io::Error);
+
+        impl fmt::Display for ConfigError {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "failed to load config")
+            }
+        }
+
+        impl Error for ConfigError {
+            fn source(&self) -> Option<&(dyn Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let error = ConfigError(std::io::Error::new(std::io::ErrorKind::NotFound, "config.toml not found"));
+        let entry = LogEntry::from_error(Uuid::new_v4(), &error);
+
+        assert_eq!(entry.level, LogLevel::Error);
+        assert_eq!(entry.message, "failed to load config");
+        let chain = &entry.error.unwrap().chain;
+        assert_eq!(chain, &vec!["failed to load config".to_string(), "config.toml not found".to_string()]);
+    }
+
+    #[test]
+    fn test_trace_context_from_traceparent() {
+        let trace = TraceContext::from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+
+        assert_eq!(trace.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(trace.span_id_bytes(), Some([0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7]));
+        assert!(TraceContext::from_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none());
+        assert!(TraceContext::from_traceparent("00-4bf92f35-00f067aa0ba902b7-01").is_none());
+    }
+
     #[test]
     fn test_message_type_to_level_conversion() {
         assert_eq!(LogLevel::from(LogMessageType::Error), LogLevel::Error);
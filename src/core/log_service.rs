@@ -1,17 +1,32 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use std::error::Error;
+use std::time::Duration;
 use uuid::Uuid;
 
-use crate::core::{LogEntry, LogUnit};
+use crate::destinations::retry::RetryMetrics;
+use crate::core::{retention, ExternalIdStats, HistogramBucket, LevelCounts, LogEntry, LogLevel, LogUnit, PurgeReport, RetentionPolicy, SearchHit, SearchOptions, StatsFilter, Subscription, SubscriptionFilter, TimeBucket, UnitRanking, UnitStats};
 
 /// Result type for log service operations
 pub type LogResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
 
+/// Error returned by optional operations a destination does not implement
+pub(crate) fn unsupported<T>(operation: &str) -> LogResult<T> {
+    Err(format!("{} is not supported by this destination", operation).into())
+}
+
 /// Core trait for log service implementations
 #[async_trait]
 pub trait LogService: Send + Sync {
     /// Creates a new log unit
-    async fn create_log_unit(&self, external_id: String) -> LogResult<LogUnit>;
+    async fn create_log_unit(&self, external_id: String) -> LogResult<LogUnit> {
+        let log_unit = LogUnit::new(external_id);
+        self.insert_log_unit(log_unit.clone()).await?;
+        Ok(log_unit)
+    }
+
+    /// Stores an existing log unit, keeping its ID and timestamp
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()>;
 
     /// Logs an entry to the service
     async fn log(&self, entry: LogEntry) -> LogResult<()>;
@@ -24,4 +39,108 @@ pub trait LogService: Send + Sync {
 
     /// Retrieves log units by external ID
     async fn get_log_units_by_external_id(&self, external_id: &str) -> LogResult<Vec<LogUnit>>;
-}
\ No newline at end of file
+
+    /// Lists the log units created at or after `from` and before `to`, oldest first.
+    ///
+    /// A `None` bound leaves that side of the range open.
+    async fn list_log_units(&self, _from: Option<DateTime<Utc>>, _to: Option<DateTime<Utc>>) -> LogResult<Vec<LogUnit>> {
+        unsupported("list_log_units")
+    }
+
+    /// Searches the messages of all entries for the words in `text`.
+    ///
+    /// Hits contain every word and are ordered by relevance, most relevant first.
+    async fn search_entries(&self, _text: &str, _options: &SearchOptions) -> LogResult<Vec<SearchHit>> {
+        unsupported("search_entries")
+    }
+
+    /// Counts the entries matching `filter` per level
+    async fn count_by_level(&self, _filter: &StatsFilter) -> LogResult<LevelCounts> {
+        unsupported("count_by_level")
+    }
+
+    /// Counts the entries matching `filter` per level and time bucket.
+    ///
+    /// Buckets are ordered oldest first; buckets without entries are left out.
+    async fn entry_histogram(&self, _filter: &StatsFilter, _bucket: TimeBucket) -> LogResult<Vec<HistogramBucket>> {
+        unsupported("entry_histogram")
+    }
+
+    /// Returns the `limit` units with the most entries matching `filter`, ordered by `ranking`
+    async fn top_units(&self, _filter: &StatsFilter, _ranking: UnitRanking, _limit: usize) -> LogResult<Vec<UnitStats>> {
+        unsupported("top_units")
+    }
+
+    /// Counts the entries matching `filter` per external ID, ordered by external ID
+    async fn counts_by_external_id(&self, _filter: &StatsFilter) -> LogResult<Vec<ExternalIdStats>> {
+        unsupported("counts_by_external_id")
+    }
+
+    /// Subscribes to entries logged from now on that match `filter`
+    async fn subscribe(&self, _filter: SubscriptionFilter) -> LogResult<Subscription> {
+        unsupported("subscribe")
+    }
+
+    /// Deletes a log unit together with all of its entries.
+    ///
+    /// Returns `false` if the unit did not exist.
+    async fn delete_log_unit(&self, _log_unit_id: Uuid) -> LogResult<bool> {
+        unsupported("delete_log_unit")
+    }
+
+    /// Deletes entries older than `before`, optionally restricted to one level.
+    ///
+    /// Returns the number of deleted entries.
+    async fn purge_entries(&self, _before: DateTime<Utc>, _level: Option<LogLevel>) -> LogResult<u64> {
+        unsupported("purge_entries")
+    }
+
+    /// Deletes log units created before `before` that have no entries left.
+    ///
+    /// Returns the number of deleted units.
+    async fn purge_empty_log_units(&self, _before: DateTime<Utc>) -> LogResult<u64> {
+        unsupported("purge_empty_log_units")
+    }
+
+    /// Keeps only the newest `max_units` log units per external ID, deleting the
+    /// older ones together with their entries.
+    ///
+    /// Returns the number of deleted units.
+    async fn trim_log_units(&self, _max_units: usize) -> LogResult<u64> {
+        unsupported("trim_log_units")
+    }
+
+    /// Deletes all entries older than `before` and the units left empty by it
+    async fn purge(&self, before: DateTime<Utc>) -> LogResult<PurgeReport> {
+        Ok(PurgeReport {
+            entries: self.purge_entries(before, None).await?,
+            units: self.purge_empty_log_units(before).await?,
+            ..Default::default()
+        })
+    }
+
+    /// Enforces a retention policy once
+    async fn apply_retention(&self, policy: &RetentionPolicy) -> LogResult<PurgeReport> {
+        retention::apply(self, policy, Utc::now()).await
+    }
+
+    /// Waits until every write started before the call has completed
+    async fn flush(&self) -> LogResult<()> {
+        Ok(())
+    }
+
+    /// Flushes, then closes connections and stops background tasks, waiting at most `timeout`.
+    ///
+    /// Writes after a shutdown fail.
+    async fn shutdown(&self, _timeout: Duration) -> LogResult<()> {
+        Ok(())
+    }
+
+    /// Returns the circuit breaker state and retry counters of the
+    /// `RetryingDestination` writes go through, if any.
+    ///
+    /// Services and destinations wrapping another destination forward this.
+    fn retry_metrics(&self) -> Option<RetryMetrics> {
+        None
+    }
+}
@@ -1,9 +1,22 @@
 //! Core traits and types for the IronScribe logging framework
 
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod analytics;
 pub mod log_unit;
 pub mod log_entry;
 pub mod log_service;
+pub mod retention;
+pub mod search;
+pub mod subscription;
 
+pub use analytics::{ExternalIdStats, HistogramBucket, LevelCounts, StatsFilter, TimeBucket, UnitRanking, UnitStats};
 pub use log_unit::LogUnit;
-pub use log_entry::{LogEntry, LogLevel, LogMessageType};
-pub use log_service::LogService;
\ No newline at end of file
+pub use log_entry::{ErrorDetails, LogEntry, LogLevel, LogMessageType, SourceLocation, TraceContext};
+pub use log_service::LogService;
+pub use retention::{PurgeReport, RetentionPolicy};
+pub use search::{SearchHit, SearchOptions};
+pub use subscription::{Subscription, SubscriptionFilter};
+
+#[cfg(feature = "archive")]
+pub use archive::{ArchiveHeader, ArchiveReport, Compression, ExportOptions};
\ No newline at end of file
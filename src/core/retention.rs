@@ -0,0 +1,122 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+
+use crate::core::log_service::LogResult;
+use crate::core::{LogLevel, LogService};
+
+/// Describes how long stored log data is kept
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionPolicy {
+    /// Maximum age of entries per log level; levels without an entry are kept forever
+    pub max_age: BTreeMap<LogLevel, Duration>,
+    /// Maximum number of log units kept per external ID, newest first
+    pub max_units_per_external_id: Option<usize>,
+}
+
+impl RetentionPolicy {
+    /// Creates a policy that keeps everything
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps entries of the given level for at most `max_age`
+    pub fn keep_level(mut self, level: LogLevel, max_age: Duration) -> Self {
+        self.max_age.insert(level, max_age);
+        self
+    }
+
+    /// Keeps entries of every level for at most `max_age`
+    pub fn keep_all_levels(self, max_age: Duration) -> Self {
+        LogLevel::ALL
+            .into_iter()
+            .fold(self, |policy, level| policy.keep_level(level, max_age))
+    }
+
+    /// Keeps at most `max_units` log units per external ID
+    pub fn max_units_per_external_id(mut self, max_units: usize) -> Self {
+        self.max_units_per_external_id = Some(max_units);
+        self
+    }
+
+    /// Returns the cutoff timestamp for each configured level relative to `now`
+    pub fn cutoffs(&self, now: DateTime<Utc>) -> Vec<(LogLevel, DateTime<Utc>)> {
+        self.max_age
+            .iter()
+            .map(|(level, max_age)| (*level, now - *max_age))
+            .collect()
+    }
+
+    /// Returns the cutoff before which entries of every level are expired.
+    ///
+    /// This is `None` unless a maximum age is configured for all levels.
+    pub fn full_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if !LogLevel::ALL.iter().all(|level| self.max_age.contains_key(level)) {
+            return None;
+        }
+        self.unit_cutoff(now)
+    }
+
+    /// Returns the cutoff before which empty log units can be removed.
+    ///
+    /// This is the cutoff of the longest configured age, or `None` when no
+    /// age is configured.
+    pub fn unit_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.max_age.values().max().map(|max_age| now - *max_age)
+    }
+}
+
+/// Number of records removed by a purge
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PurgeReport {
+    /// Number of deleted log entries
+    pub entries: u64,
+    /// Number of deleted log units
+    pub units: u64,
+    /// Number of dropped or detached partitions, for partitioned storage
+    pub partitions: u64,
+}
+
+/// Enforces `policy` through the purge primitives of `service`.
+///
+/// This is the default behaviour of `LogService::apply_retention`; destinations
+/// that override it can run their own preparation and then call this.
+pub async fn apply<S: LogService + ?Sized>(
+    service: &S,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> LogResult<PurgeReport> {
+    let mut report = PurgeReport::default();
+
+    for (level, before) in policy.cutoffs(now) {
+        report.entries += service.purge_entries(before, Some(level)).await?;
+    }
+    if let Some(max_units) = policy.max_units_per_external_id {
+        report.units += service.trim_log_units(max_units).await?;
+    }
+    if let Some(before) = policy.unit_cutoff(now) {
+        report.units += service.purge_empty_log_units(before).await?;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retention_cutoffs() {
+        let now = Utc::now();
+        let policy = RetentionPolicy::new()
+            .keep_level(LogLevel::Error, Duration::days(90))
+            .keep_level(LogLevel::Info, Duration::days(7));
+
+        let cutoffs = policy.cutoffs(now);
+        assert_eq!(cutoffs, vec![
+            (LogLevel::Error, now - Duration::days(90)),
+            (LogLevel::Info, now - Duration::days(7)),
+        ]);
+        assert_eq!(policy.unit_cutoff(now), Some(now - Duration::days(90)));
+        assert_eq!(RetentionPolicy::new().unit_cutoff(now), None);
+    }
+}
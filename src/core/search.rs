@@ -0,0 +1,186 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "console", feature = "mongo", feature = "elastic"))]
+use std::collections::HashSet;
+#[cfg(feature = "console")]
+use std::collections::HashMap;
+#[cfg(feature = "console")]
+use uuid::Uuid;
+
+use crate::core::{LogEntry, LogLevel, LogUnit};
+
+/// Filters and presentation settings for a full-text search
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchOptions {
+    /// Maximum number of hits returned
+    pub limit: usize,
+    /// Only entries at least as severe as this level
+    pub min_level: Option<LogLevel>,
+    /// Only entries of units with this external ID
+    pub external_id: Option<String>,
+    /// Only entries logged at or after this time
+    pub from: Option<DateTime<Utc>>,
+    /// Only entries logged before this time
+    pub to: Option<DateTime<Utc>>,
+    /// Only entries logged from this module or its submodules
+    pub module_path: Option<String>,
+    /// Marker inserted before each matched word in the snippet
+    pub highlight_start: String,
+    /// Marker inserted after each matched word in the snippet
+    pub highlight_end: String,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            limit: 50,
+            min_level: None,
+            external_id: None,
+            from: None,
+            to: None,
+            module_path: None,
+            highlight_start: "<b>".to_string(),
+            highlight_end: "</b>".to_string(),
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Returns whether `entry` passes the level, time and module filters
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        self.min_level.is_none_or(|level| entry.level.is_at_least(level))
+            && self.from.is_none_or(|from| entry.timestamp >= from)
+            && self.to.is_none_or(|to| entry.timestamp < to)
+            && self.module_path.as_ref().is_none_or(|module| entry.is_in_module(module))
+    }
+}
+
+/// A log entry matching a full-text search
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SearchHit {
+    pub entry: LogEntry,
+    /// The unit the entry belongs to, if it still exists
+    pub log_unit: Option<LogUnit>,
+    /// Part of the message around the matches, with matched words highlighted
+    pub snippet: String,
+    /// Relevance of the hit; higher is more relevant
+    pub score: f32,
+}
+
+/// Maximum number of words in a snippet
+#[cfg(any(feature = "console", feature = "mongo", feature = "elastic"))]
+const SNIPPET_WORDS: usize = 35;
+
+/// Splits text into lowercase alphanumeric search terms
+#[cfg(any(feature = "console", feature = "mongo", feature = "elastic"))]
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Builds a snippet of `message` around the first word matching one of `terms`
+#[cfg(any(feature = "console", feature = "mongo", feature = "elastic"))]
+pub(crate) fn snippet(message: &str, terms: &HashSet<String>, options: &SearchOptions) -> String {
+    let words: Vec<&str> = message.split_whitespace().collect();
+    let is_match = |word: &str| tokenize(word).iter().any(|term| terms.contains(term));
+
+    let first_match = words.iter().position(|word| is_match(word)).unwrap_or(0);
+    let start = first_match.saturating_sub(SNIPPET_WORDS / 4);
+    let end = (start + SNIPPET_WORDS).min(words.len());
+
+    words[start..end]
+        .iter()
+        .map(|word| {
+            if is_match(word) {
+                format!("{}{}{}", options.highlight_start, word, options.highlight_end)
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// In-memory inverted index over entry messages
+#[cfg(feature = "console")]
+#[derive(Debug, Default)]
+pub(crate) struct InvertedIndex {
+    /// Term -> message ID -> (log unit ID, occurrences of the term)
+    postings: HashMap<String, HashMap<Uuid, (Uuid, u32)>>,
+    entries: usize,
+}
+
+#[cfg(feature = "console")]
+impl InvertedIndex {
+    pub(crate) fn insert(&mut self, entry: &LogEntry) {
+        for term in tokenize(&entry.message) {
+            let posting = self.postings
+                .entry(term)
+                .or_default()
+                .entry(entry.message_id)
+                .or_insert((entry.log_unit_id, 0));
+            posting.1 += 1;
+        }
+        self.entries += 1;
+    }
+
+    pub(crate) fn remove(&mut self, entry: &LogEntry) {
+        for term in tokenize(&entry.message) {
+            if let Some(postings) = self.postings.get_mut(&term) {
+                postings.remove(&entry.message_id);
+                if postings.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+        self.entries = self.entries.saturating_sub(1);
+    }
+
+    /// Returns `(log_unit_id, message_id, score)` of every entry containing all `terms`
+    pub(crate) fn search(&self, terms: &HashSet<String>) -> Vec<(Uuid, Uuid, f32)> {
+        let mut postings: Vec<&HashMap<Uuid, (Uuid, u32)>> = Vec::new();
+        for term in terms {
+            match self.postings.get(term) {
+                Some(term_postings) => postings.push(term_postings),
+                None => return Vec::new(),
+            }
+        }
+        // Intersect starting from the rarest term
+        postings.sort_by_key(|term_postings| term_postings.len());
+        let Some((rarest, others)) = postings.split_first() else {
+            return Vec::new();
+        };
+
+        rarest
+            .iter()
+            .filter(|(message_id, _)| others.iter().all(|p| p.contains_key(message_id)))
+            .map(|(message_id, (log_unit_id, _))| {
+                let score = postings
+                    .iter()
+                    .map(|p| {
+                        let occurrences = p[message_id].1 as f32;
+                        let idf = (1.0 + self.entries as f32 / p.len() as f32).ln();
+                        occurrences * idf
+                    })
+                    .sum();
+                (*log_unit_id, *message_id, score)
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, any(feature = "console", feature = "mongo", feature = "elastic")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_and_snippet() {
+        assert_eq!(tokenize("Order #8812 failed: timeout"), vec!["order", "8812", "failed", "timeout"]);
+
+        let terms: HashSet<String> = tokenize("order 8812").into_iter().collect();
+        let snippet = snippet("Retrying order 8812 after timeout", &terms, &SearchOptions::default());
+        assert_eq!(snippet, "Retrying <b>order</b> <b>8812</b> after timeout");
+    }
+}
@@ -0,0 +1,186 @@
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::core::{LogEntry, LogLevel, LogService};
+
+/// Selects the entries delivered to a subscription
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubscriptionFilter {
+    /// Only entries of this log unit
+    pub log_unit_id: Option<Uuid>,
+    /// Only entries of units with this external ID
+    pub external_id: Option<String>,
+    /// Only entries at least as severe as this level
+    pub min_level: Option<LogLevel>,
+}
+
+impl SubscriptionFilter {
+    /// Creates a filter that lets every entry through
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Creates a filter for the entries of one log unit
+    pub fn for_unit(log_unit_id: Uuid) -> Self {
+        Self {
+            log_unit_id: Some(log_unit_id),
+            ..Self::default()
+        }
+    }
+
+    /// Creates a filter for the entries of all units with the given external ID
+    pub fn for_external_id(external_id: impl Into<String>) -> Self {
+        Self {
+            external_id: Some(external_id.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Restricts the filter to entries at least as severe as `level`
+    pub fn min_level(mut self, level: LogLevel) -> Self {
+        self.min_level = Some(level);
+        self
+    }
+
+    /// Checks the unit ID and level of an entry; the external ID needs a unit lookup
+    pub fn matches_entry(&self, log_unit_id: Uuid, level: LogLevel) -> bool {
+        self.log_unit_id.is_none_or(|id| id == log_unit_id)
+            && self.min_level.is_none_or(|min_level| level.is_at_least(min_level))
+    }
+}
+
+/// Stream of newly logged entries matching a `SubscriptionFilter`.
+///
+/// A subscriber that falls too far behind skips the entries it could not keep
+/// up with; their number is reported by `missed_entries`.
+pub struct Subscription {
+    entries: BoxStream<'static, LogEntry>,
+    missed: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    /// Creates a subscription from a stream of already filtered entries
+    pub fn new(entries: impl Stream<Item = LogEntry> + Send + 'static) -> Self {
+        Self::with_missed_counter(entries, Arc::new(AtomicU64::new(0)))
+    }
+
+    /// Creates a subscription whose source counts skipped entries in `missed`
+    pub fn with_missed_counter(
+        entries: impl Stream<Item = LogEntry> + Send + 'static,
+        missed: Arc<AtomicU64>,
+    ) -> Self {
+        Self {
+            entries: entries.boxed(),
+            missed,
+        }
+    }
+
+    /// Returns the number of entries skipped because the subscriber lagged behind
+    pub fn missed_entries(&self) -> u64 {
+        self.missed.load(Ordering::Relaxed)
+    }
+}
+
+impl Stream for Subscription {
+    type Item = LogEntry;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<LogEntry>> {
+        self.entries.as_mut().poll_next(cx)
+    }
+}
+
+/// Resolves and caches whether log units belong to the filtered external ID
+pub(crate) struct ExternalIdMatcher {
+    external_id: Option<String>,
+    units: Arc<dyn LogService>,
+    known: HashMap<Uuid, bool>,
+}
+
+impl ExternalIdMatcher {
+    pub(crate) fn new(external_id: Option<String>, units: Arc<dyn LogService>) -> Self {
+        Self {
+            external_id,
+            units,
+            known: HashMap::new(),
+        }
+    }
+
+    pub(crate) async fn matches(&mut self, log_unit_id: Uuid) -> bool {
+        let Some(external_id) = &self.external_id else {
+            return true;
+        };
+        if let Some(known) = self.known.get(&log_unit_id) {
+            return *known;
+        }
+
+        match self.units.get_log_unit(log_unit_id).await {
+            Ok(unit) => {
+                let matches = unit.is_some_and(|unit| &unit.external_id == external_id);
+                self.known.insert(log_unit_id, matches);
+                matches
+            }
+            // Not cached, so the lookup is retried for the next entry of the unit
+            Err(_) => false,
+        }
+    }
+}
+
+/// Creates a subscription fed by a broadcast channel of logged entries.
+///
+/// `units` is used to look up the external ID of units when filtering by it.
+pub(crate) fn from_broadcast(
+    receiver: broadcast::Receiver<LogEntry>,
+    filter: SubscriptionFilter,
+    units: Arc<dyn LogService>,
+) -> Subscription {
+    let missed = Arc::new(AtomicU64::new(0));
+    let matcher = ExternalIdMatcher::new(filter.external_id.clone(), units);
+
+    let entries = stream::unfold(
+        (receiver, matcher, Arc::clone(&missed)),
+        move |(mut receiver, mut matcher, missed)| {
+            let filter = filter.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(entry) => {
+                            if filter.matches_entry(entry.log_unit_id, entry.level)
+                                && matcher.matches(entry.log_unit_id).await
+                            {
+                                return Some((entry, (receiver, matcher, missed)));
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            missed.fetch_add(skipped, Ordering::Relaxed);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            }
+        },
+    );
+
+    Subscription::with_missed_counter(entries, missed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_matches_unit_and_level() {
+        let unit_id = Uuid::new_v4();
+        let filter = SubscriptionFilter::for_unit(unit_id).min_level(LogLevel::Warning);
+
+        assert!(filter.matches_entry(unit_id, LogLevel::Error));
+        assert!(filter.matches_entry(unit_id, LogLevel::Warning));
+        assert!(!filter.matches_entry(unit_id, LogLevel::Info));
+        assert!(!filter.matches_entry(Uuid::new_v4(), LogLevel::Error));
+    }
+}
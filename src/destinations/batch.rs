@@ -0,0 +1,129 @@
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::core::log_service::LogResult;
+
+/// Sends the batches collected by a `Batcher`
+#[async_trait]
+pub(crate) trait BatchSink: Send + Sync + 'static {
+    type Item: Send + 'static;
+
+    /// Size of an item, counted against `BatchLimits::max_bytes`
+    fn size(_item: &Self::Item) -> usize {
+        0
+    }
+
+    async fn send(&self, items: Vec<Self::Item>) -> LogResult<()>;
+}
+
+/// When a `Batcher` sends its batch
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BatchLimits {
+    pub(crate) max_items: usize,
+    pub(crate) max_bytes: usize,
+    pub(crate) interval: Duration,
+}
+
+struct Shared<S: BatchSink> {
+    sink: S,
+    limits: BatchLimits,
+    /// Pending items and the sum of their sizes
+    batch: Mutex<(Vec<S::Item>, usize)>,
+    /// Held while a batch is sent, so batches arrive in the order they were filled
+    sending: Mutex<()>,
+    closed: AtomicBool,
+}
+
+impl<S: BatchSink> Shared<S> {
+    /// Sends the pending items; they are dropped if the sink fails
+    async fn send_pending(&self) -> LogResult<()> {
+        let _sending = self.sending.lock().await;
+        let (items, _) = std::mem::take(&mut *self.batch.lock().await);
+        if items.is_empty() {
+            return Ok(());
+        }
+        self.sink.send(items).await
+    }
+}
+
+/// Collects items into batches sent once full, on a timer and on `flush`
+pub(crate) struct Batcher<S: BatchSink> {
+    shared: Arc<Shared<S>>,
+    ticker: JoinHandle<()>,
+}
+
+impl<S: BatchSink> Batcher<S> {
+    /// Creates a batcher sending to `sink`; must be called from within a Tokio runtime
+    pub(crate) fn new(sink: S, limits: BatchLimits) -> Self {
+        let shared = Arc::new(Shared {
+            sink,
+            limits,
+            batch: Mutex::new((Vec::new(), 0)),
+            sending: Mutex::new(()),
+            closed: AtomicBool::new(false),
+        });
+        let ticker_shared = Arc::clone(&shared);
+        let ticker = tokio::spawn(async move {
+            let period = ticker_shared.limits.interval;
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if let Err(e) = ticker_shared.send_pending().await {
+                    eprintln!("Failed to send batch: {}", e);
+                }
+            }
+        });
+        Self { shared, ticker }
+    }
+
+    pub(crate) fn sink(&self) -> &S {
+        &self.shared.sink
+    }
+
+    /// Adds `item` to the batch, waiting for the batch to be sent if that fills it.
+    ///
+    /// The error of sending a full batch is returned here, although the batch
+    /// also holds items pushed by other callers.
+    pub(crate) async fn push(&self, item: S::Item) -> LogResult<()> {
+        if self.shared.closed.load(Ordering::Acquire) {
+            return Err("destination is shut down".into());
+        }
+        let limits = &self.shared.limits;
+        let full = {
+            let mut batch = self.shared.batch.lock().await;
+            batch.1 += S::size(&item);
+            batch.0.push(item);
+            batch.0.len() >= limits.max_items || batch.1 >= limits.max_bytes
+        };
+        if full {
+            self.shared.send_pending().await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the pending items, if any
+    pub(crate) async fn flush(&self) -> LogResult<()> {
+        self.shared.send_pending().await
+    }
+
+    /// Sends the pending items within `timeout`, then makes further pushes fail
+    pub(crate) async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        self.shared.closed.store(true, Ordering::Release);
+        let sent = tokio::time::timeout(timeout, self.shared.send_pending())
+            .await
+            .map_err(|_| format!("timed out after {:?} sending pending entries", timeout));
+        self.ticker.abort();
+        sent?
+    }
+}
+
+impl<S: BatchSink> Drop for Batcher<S> {
+    fn drop(&mut self) {
+        self.ticker.abort();
+    }
+}
@@ -0,0 +1,221 @@
+//! Message bus destination publishing entries and units for downstream consumers
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::core::log_service::{unsupported, LogResult};
+use crate::core::{LogEntry, LogService, LogUnit};
+
+/// How entries and units are serialized into message payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    Json,
+    /// MessagePack with field names, so optional fields can be left out
+    MessagePack,
+}
+
+impl MessageFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            MessageFormat::Json => "application/json",
+            MessageFormat::MessagePack => "application/msgpack",
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> LogResult<Vec<u8>> {
+        Ok(match self {
+            MessageFormat::Json => serde_json::to_vec(value)?,
+            MessageFormat::MessagePack => rmp_serde::to_vec_named(value)?,
+        })
+    }
+
+    /// Decodes a payload published in this format, e.g. `format.decode::<LogEntry>(&payload)`
+    pub fn decode<T: DeserializeOwned>(self, payload: &[u8]) -> LogResult<T> {
+        Ok(match self {
+            MessageFormat::Json => serde_json::from_slice(payload)?,
+            MessageFormat::MessagePack => rmp_serde::from_slice(payload)?,
+        })
+    }
+}
+
+/// A message to publish
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusMessage {
+    pub topic: String,
+    /// The `log_unit_id` of the entry or unit
+    pub key: String,
+    pub payload: Vec<u8>,
+    pub content_type: &'static str,
+}
+
+/// Publishes messages to a message bus
+#[async_trait]
+pub trait MessagePublisher: Send + Sync {
+    /// Publishes a message; messages with the same key must reach consumers in the order they were published.
+    ///
+    /// Publishers may return before the message is delivered.
+    async fn publish(&self, message: BusMessage) -> LogResult<()>;
+
+    /// Waits until every message published so far has been delivered
+    async fn flush(&self) -> LogResult<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageBusConfig {
+    /// Topic (Kafka) or subject prefix (NATS) entries are published to
+    pub entries_topic: String,
+    /// Topic units are published to; `None` publishes entries only
+    pub units_topic: Option<String>,
+    pub format: MessageFormat,
+}
+
+impl Default for MessageBusConfig {
+    fn default() -> Self {
+        Self {
+            entries_topic: "ironscribe.entries".to_string(),
+            units_topic: Some("ironscribe.units".to_string()),
+            format: MessageFormat::Json,
+        }
+    }
+}
+
+/// Write-only destination publishing every entry and unit as a message.
+///
+/// Messages are keyed by `log_unit_id`, so consumers see the entries of a unit
+/// in the order they were logged. Reads are not supported.
+pub struct MessageBusDestination {
+    publisher: Arc<dyn MessagePublisher>,
+    config: MessageBusConfig,
+    closed: AtomicBool,
+}
+
+impl MessageBusDestination {
+    pub fn new(publisher: Arc<dyn MessagePublisher>, config: MessageBusConfig) -> Self {
+        Self { publisher, config, closed: AtomicBool::new(false) }
+    }
+
+    async fn publish<T: Serialize + Sync>(&self, topic: &str, log_unit_id: Uuid, value: &T) -> LogResult<()> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err("destination is shut down".into());
+        }
+        let format = self.config.format;
+        self.publisher
+            .publish(BusMessage {
+                topic: topic.to_string(),
+                key: log_unit_id.to_string(),
+                payload: format.encode(value)?,
+                content_type: format.content_type(),
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl LogService for MessageBusDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        match &self.config.units_topic {
+            Some(topic) => self.publish(topic, log_unit.log_unit_id, &log_unit).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        self.publish(&self.config.entries_topic, entry.log_unit_id, &entry).await
+    }
+
+    async fn get_log_entries(&self, _log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        unsupported("get_log_entries")
+    }
+
+    async fn get_log_unit(&self, _log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        unsupported("get_log_unit")
+    }
+
+    async fn get_log_units_by_external_id(&self, _external_id: &str) -> LogResult<Vec<LogUnit>> {
+        unsupported("get_log_units_by_external_id")
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        self.publisher.flush().await
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        self.closed.store(true, Ordering::Release);
+        tokio::time::timeout(timeout, self.publisher.flush())
+            .await
+            .map_err(|_| format!("timed out after {:?} delivering pending messages", timeout))?
+    }
+}
+
+/// Publisher keeping messages in memory, for tests and in-process consumers
+#[derive(Debug, Default)]
+pub struct InMemoryPublisher {
+    messages: Mutex<Vec<BusMessage>>,
+}
+
+impl InMemoryPublisher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the messages published so far, oldest first
+    pub fn messages(&self) -> Vec<BusMessage> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    /// Removes and returns the messages published so far
+    pub fn take_messages(&self) -> Vec<BusMessage> {
+        std::mem::take(&mut *self.messages.lock().unwrap())
+    }
+}
+
+#[async_trait]
+impl MessagePublisher for InMemoryPublisher {
+    async fn publish(&self, message: BusMessage) -> LogResult<()> {
+        self.messages.lock().unwrap().push(message);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SourceLocation;
+
+    #[tokio::test]
+    async fn test_publishes_keyed_messages() {
+        let publisher = Arc::new(InMemoryPublisher::new());
+        let destination = MessageBusDestination::new(publisher.clone(), MessageBusConfig::default());
+        let unit = destination.create_log_unit("job-1".to_string()).await.unwrap();
+        let entry = LogEntry::info(unit.log_unit_id, "started".to_string());
+        destination.log(entry.clone()).await.unwrap();
+        destination.shutdown(Duration::from_secs(1)).await.unwrap();
+        assert!(destination.log(entry.clone()).await.is_err());
+
+        let messages = publisher.take_messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].topic, "ironscribe.units");
+        assert_eq!(messages[1].topic, "ironscribe.entries");
+        assert!(messages.iter().all(|message| message.key == unit.log_unit_id.to_string()));
+        assert_eq!(MessageFormat::Json.decode::<LogUnit>(&messages[0].payload).unwrap(), unit);
+        assert_eq!(MessageFormat::Json.decode::<LogEntry>(&messages[1].payload).unwrap(), entry);
+        assert!(publisher.messages().is_empty());
+    }
+
+    #[test]
+    fn test_message_pack_round_trip() {
+        let entry = LogEntry::error(Uuid::new_v4(), "disk full".to_string())
+            .with_location(SourceLocation::new("src/disk.rs", 3, 5, "app::disk"));
+        let json = MessageFormat::Json.encode(&entry).unwrap();
+        let packed = MessageFormat::MessagePack.encode(&entry).unwrap();
+        assert!(packed.len() < json.len());
+        assert_eq!(MessageFormat::MessagePack.decode::<LogEntry>(&packed).unwrap(), entry);
+    }
+}
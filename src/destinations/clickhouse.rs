@@ -0,0 +1,790 @@
+//! ClickHouse destination storing entries in `MergeTree` tables through the HTTP interface
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::core::log_service::LogResult;
+use crate::core::{ErrorDetails, ExternalIdStats, HistogramBucket, LevelCounts, LogEntry, LogLevel, LogMessageType, LogService, LogUnit, SourceLocation, StatsFilter, TimeBucket, TraceContext, UnitRanking, UnitStats};
+use crate::destinations::batch::{BatchLimits, BatchSink, Batcher};
+use crate::destinations::http::{send_with_retries, HttpAuth};
+use crate::destinations::retry::RetryPolicy;
+
+/// Format rows are inserted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClickHouseFormat {
+    /// ClickHouse's native binary row format, the cheapest to parse
+    RowBinary,
+    /// One JSON object per row
+    JsonEachRow,
+}
+
+impl ClickHouseFormat {
+    fn name(self) -> &'static str {
+        match self {
+            ClickHouseFormat::RowBinary => "RowBinary",
+            ClickHouseFormat::JsonEachRow => "JSONEachRow",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClickHouseConfig {
+    /// URL of the HTTP interface, e.g. `http://localhost:8123`
+    pub url: String,
+    pub auth: Option<HttpAuth>,
+    /// Database holding the tables; created if missing
+    pub database: String,
+    pub log_entries_table: String,
+    pub log_units_table: String,
+    pub format: ClickHouseFormat,
+    /// Deletes entries and units this long after their timestamp, with a table TTL.
+    ///
+    /// Only applied when the tables are created; change it on existing tables with `ALTER TABLE ... MODIFY TTL`.
+    pub ttl: Option<Duration>,
+    /// Lets the server buffer entry inserts too (`async_insert`), waiting until they are written.
+    ///
+    /// Units are always inserted this way, as each is sent on its own.
+    pub async_insert: bool,
+    /// Sends a batch once it holds this many entries
+    pub max_batch_entries: usize,
+    /// Sends a batch once its encoded rows take this many bytes
+    pub max_batch_bytes: usize,
+    /// Sends the pending entries at least this often
+    pub flush_interval: Duration,
+    /// Timeout of a single request
+    pub request_timeout: Duration,
+    /// Retries of inserts failing with a connection error, a 5xx or a 429
+    pub retry: RetryPolicy,
+}
+
+impl Default for ClickHouseConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:8123".to_string(),
+            auth: None,
+            database: "ironscribe".to_string(),
+            log_entries_table: "log_entries".to_string(),
+            log_units_table: "log_units".to_string(),
+            format: ClickHouseFormat::RowBinary,
+            ttl: None,
+            async_insert: false,
+            max_batch_entries: 100_000,
+            max_batch_bytes: 16 * 1024 * 1024,
+            flush_interval: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(60),
+            retry: RetryPolicy {
+                max_retries: 5,
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(30),
+                jitter: 0.5,
+            },
+        }
+    }
+}
+
+/// Columns of the entries table, in the order rows are encoded
+const ENTRY_COLUMNS: &str = "log_unit_id, message_id, level, message_type, message, timestamp, location_file, location_line, location_column, module_path, error_chain, error_backtrace, trace_id, span_id";
+
+const UNIT_COLUMNS: &str = "log_unit_id, external_id, timestamp";
+
+/// An entry as stored in the entries table; absent optional parts are empty values
+#[derive(Debug, Serialize, Deserialize)]
+struct EntryRow {
+    log_unit_id: Uuid,
+    message_id: Uuid,
+    level: u8,
+    message_type: LogMessageType,
+    message: String,
+    timestamp: DateTime<Utc>,
+    location_file: String,
+    location_line: u32,
+    location_column: u32,
+    module_path: String,
+    error_chain: Vec<String>,
+    error_backtrace: Option<String>,
+    trace_id: String,
+    span_id: String,
+}
+
+impl From<LogEntry> for EntryRow {
+    fn from(entry: LogEntry) -> Self {
+        let location = entry.location.unwrap_or_else(|| SourceLocation::new("", 0, 0, ""));
+        let (error_chain, error_backtrace) = entry.error.map_or((Vec::new(), None), |error| (error.chain, error.backtrace));
+        let (trace_id, span_id) = entry.trace.map_or((String::new(), String::new()), |trace| (trace.trace_id, trace.span_id));
+        Self {
+            log_unit_id: entry.log_unit_id,
+            message_id: entry.message_id,
+            level: entry.level as u8,
+            message_type: entry.message_type,
+            message: entry.message,
+            timestamp: entry.timestamp,
+            location_file: location.file,
+            location_line: location.line,
+            location_column: location.column,
+            module_path: location.module_path,
+            error_chain,
+            error_backtrace,
+            trace_id,
+            span_id,
+        }
+    }
+}
+
+impl From<EntryRow> for LogEntry {
+    fn from(row: EntryRow) -> Self {
+        let location = (!row.location_file.is_empty())
+            .then(|| SourceLocation::new(&row.location_file, row.location_line, row.location_column, &row.module_path));
+        let error = (!row.error_chain.is_empty()).then_some(ErrorDetails { chain: row.error_chain, backtrace: row.error_backtrace });
+        let trace = (!row.trace_id.is_empty()).then_some(TraceContext { trace_id: row.trace_id, span_id: row.span_id });
+        Self {
+            log_unit_id: row.log_unit_id,
+            message_id: row.message_id,
+            level: level(row.level),
+            message: row.message,
+            message_type: row.message_type,
+            timestamp: row.timestamp,
+            location,
+            error,
+            trace,
+        }
+    }
+}
+
+fn level(value: u8) -> LogLevel {
+    LogLevel::ALL.get(value as usize).copied().unwrap_or(LogLevel::Info)
+}
+
+/// Writes values in ClickHouse's `RowBinary` format
+#[derive(Default)]
+struct RowBinary(Vec<u8>);
+
+impl RowBinary {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn string(&mut self, value: &str) {
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    /// Both 8-byte halves of a UUID are stored little-endian
+    fn uuid(&mut self, value: Uuid) {
+        let (high, low) = value.as_u64_pair();
+        self.0.extend_from_slice(&high.to_le_bytes());
+        self.0.extend_from_slice(&low.to_le_bytes());
+    }
+
+    /// `DateTime64(9)`: nanoseconds since the epoch
+    fn timestamp(&mut self, value: DateTime<Utc>) {
+        self.0.extend_from_slice(&value.timestamp_nanos_opt().unwrap_or_default().to_le_bytes());
+    }
+
+    fn entry(mut self, row: &EntryRow) -> Vec<u8> {
+        self.uuid(row.log_unit_id);
+        self.uuid(row.message_id);
+        self.0.push(row.level);
+        self.string(&format!("{:?}", row.message_type));
+        self.string(&row.message);
+        self.timestamp(row.timestamp);
+        self.string(&row.location_file);
+        self.0.extend_from_slice(&row.location_line.to_le_bytes());
+        self.0.extend_from_slice(&row.location_column.to_le_bytes());
+        self.string(&row.module_path);
+        self.varint(row.error_chain.len() as u64);
+        for error in &row.error_chain {
+            self.string(error);
+        }
+        match &row.error_backtrace {
+            Some(backtrace) => {
+                self.0.push(0);
+                self.string(backtrace);
+            }
+            None => self.0.push(1),
+        }
+        self.string(&row.trace_id);
+        self.string(&row.span_id);
+        self.0
+    }
+
+    fn unit(mut self, unit: &LogUnit) -> Vec<u8> {
+        self.uuid(unit.log_unit_id);
+        self.string(&unit.external_id);
+        self.timestamp(unit.timestamp);
+        self.0
+    }
+}
+
+/// Formats a timestamp as a `DateTime64(9, 'UTC')` query parameter
+fn timestamp_param(value: DateTime<Utc>) -> String {
+    value.format("%Y-%m-%d %H:%M:%S%.9f").to_string()
+}
+
+/// A `WHERE` clause with the query parameters it references
+struct Conditions {
+    clauses: Vec<String>,
+    params: Vec<(String, String)>,
+}
+
+impl Conditions {
+    fn new() -> Self {
+        Self { clauses: Vec::new(), params: Vec::new() }
+    }
+
+    fn add(&mut self, clause: impl Into<String>, params: &[(&str, String)]) {
+        self.clauses.push(clause.into());
+        self.params.extend(params.iter().map(|(name, value)| (name.to_string(), value.clone())));
+    }
+
+    fn time_range(&mut self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) {
+        if let Some(from) = from {
+            self.add("timestamp >= {from:DateTime64(9, 'UTC')}", &[("from", timestamp_param(from))]);
+        }
+        if let Some(to) = to {
+            self.add("timestamp < {to:DateTime64(9, 'UTC')}", &[("to", timestamp_param(to))]);
+        }
+    }
+
+    fn sql(&self) -> String {
+        match self.clauses.is_empty() {
+            true => String::new(),
+            false => format!("WHERE {}", self.clauses.join(" AND ")),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LevelCount {
+    level: u8,
+    count: u64,
+}
+
+#[derive(Deserialize)]
+struct BucketCount {
+    bucket: DateTime<Utc>,
+    level: u8,
+    count: u64,
+}
+
+#[derive(Deserialize)]
+struct UnitCounts {
+    log_unit_id: Uuid,
+    error: u64,
+    warning: u64,
+    info: u64,
+    success: u64,
+}
+
+#[derive(Deserialize)]
+struct ExternalIdCount {
+    external_id: String,
+    level: u8,
+    count: u64,
+}
+
+/// Talks to the HTTP interface; batches are encoded entry rows
+struct Server {
+    config: ClickHouseConfig,
+    client: Client,
+}
+
+#[async_trait]
+impl BatchSink for Server {
+    type Item = Vec<u8>;
+
+    fn size(row: &Vec<u8>) -> usize {
+        row.len()
+    }
+
+    async fn send(&self, rows: Vec<Vec<u8>>) -> LogResult<()> {
+        let count = rows.len();
+        self.insert(&self.config.log_entries_table, ENTRY_COLUMNS, rows.concat(), self.config.async_insert)
+            .await
+            .map_err(|e| format!("Failed to insert {} log entries into {}: {}", count, self.config.url, e).into())
+    }
+}
+
+impl Server {
+    fn table(&self, name: &str) -> String {
+        format!("{}.{}", self.config.database, name)
+    }
+
+    fn request(&self, settings: &[(&str, String)]) -> RequestBuilder {
+        let request = self.client.post(&self.config.url).query(settings);
+        match &self.config.auth {
+            Some(auth) => auth.apply(request),
+            None => request,
+        }
+    }
+
+    /// Runs a statement, returning the response body
+    async fn execute(&self, sql: &str, params: &[(String, String)]) -> LogResult<String> {
+        let mut settings = vec![
+            ("date_time_output_format", "iso".to_string()),
+            ("output_format_json_quote_64bit_integers", "0".to_string()),
+        ];
+        let params: Vec<(String, String)> = params.iter().map(|(name, value)| (format!("param_{}", name), value.clone())).collect();
+        settings.extend(params.iter().map(|(name, value)| (name.as_str(), value.clone())));
+
+        let response = self.request(&settings).body(sql.to_string()).send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(format!("HTTP {}: {}", status, text.trim()).into());
+        }
+        Ok(text)
+    }
+
+    /// Runs a query, parsing its `JSONEachRow` output
+    async fn select<T: DeserializeOwned>(&self, sql: &str, params: &[(String, String)]) -> LogResult<Vec<T>> {
+        let text = self.execute(&format!("{} FORMAT JSONEachRow", sql), params).await?;
+        text.lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    /// Inserts encoded rows, retrying transient failures.
+    ///
+    /// With `async_insert` the server collects the rows of several inserts into
+    /// one part, and the request returns once that part is written.
+    async fn insert(&self, table: &str, columns: &str, body: Vec<u8>, async_insert: bool) -> LogResult<()> {
+        let query = format!("INSERT INTO {} ({}) FORMAT {}", self.table(table), columns, self.config.format.name());
+        let mut settings = vec![("query", query), ("date_time_input_format", "best_effort".to_string())];
+        if async_insert {
+            settings.push(("async_insert", "1".to_string()));
+            settings.push(("wait_for_async_insert", "1".to_string()));
+        }
+        send_with_retries(&self.config.retry, || self.request(&settings).body(body.clone())).await?;
+        Ok(())
+    }
+
+    fn encode_entry(&self, entry: LogEntry) -> LogResult<Vec<u8>> {
+        let row = EntryRow::from(entry);
+        Ok(match self.config.format {
+            ClickHouseFormat::RowBinary => RowBinary::default().entry(&row),
+            ClickHouseFormat::JsonEachRow => {
+                let mut line = serde_json::to_vec(&row)?;
+                line.push(b'\n');
+                line
+            }
+        })
+    }
+
+    fn encode_unit(&self, unit: &LogUnit) -> LogResult<Vec<u8>> {
+        Ok(match self.config.format {
+            ClickHouseFormat::RowBinary => RowBinary::default().unit(unit),
+            ClickHouseFormat::JsonEachRow => serde_json::to_vec(unit)?,
+        })
+    }
+
+    /// Creates the database and tables if missing
+    async fn create_tables(&self) -> LogResult<()> {
+        let ttl = self.config.ttl.map_or(String::new(), |ttl| format!("TTL toDateTime(timestamp) + INTERVAL {} SECOND", ttl.as_secs()));
+        self.execute(&format!("CREATE DATABASE IF NOT EXISTS {}", self.config.database), &[]).await?;
+        self.execute(
+            &format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {} (
+                    log_unit_id UUID,
+                    message_id UUID,
+                    level UInt8,
+                    message_type LowCardinality(String),
+                    message String,
+                    timestamp DateTime64(9, 'UTC'),
+                    location_file LowCardinality(String),
+                    location_line UInt32,
+                    location_column UInt32,
+                    module_path LowCardinality(String),
+                    error_chain Array(String),
+                    error_backtrace Nullable(String) CODEC(ZSTD),
+                    trace_id String,
+                    span_id String
+                )
+                ENGINE = MergeTree
+                PARTITION BY toYYYYMM(timestamp)
+                ORDER BY (log_unit_id, timestamp)
+                {}
+                "#,
+                self.table(&self.config.log_entries_table),
+                ttl
+            ),
+            &[],
+        )
+        .await?;
+        // Rows inserted again for the same unit are merged away
+        self.execute(
+            &format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {} (
+                    log_unit_id UUID,
+                    external_id String,
+                    timestamp DateTime64(9, 'UTC'),
+                    INDEX external_id_index external_id TYPE bloom_filter GRANULARITY 4
+                )
+                ENGINE = ReplacingMergeTree
+                ORDER BY log_unit_id
+                {}
+                "#,
+                self.table(&self.config.log_units_table),
+                ttl
+            ),
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Conditions selecting the entries counted for `filter`
+    fn stats_conditions(&self, filter: &StatsFilter) -> Conditions {
+        let mut conditions = Conditions::new();
+        conditions.time_range(filter.from, filter.to);
+        if let Some(level) = filter.min_level {
+            conditions.add("level <= {min_level:UInt8}", &[("min_level", (level as u8).to_string())]);
+        }
+        if let Some(module) = &filter.module_path {
+            conditions.add(
+                "(module_path = {module:String} OR startsWith(module_path, {module_prefix:String}))",
+                &[("module", module.clone()), ("module_prefix", format!("{}::", module))],
+            );
+        }
+        if let Some(external_id) = &filter.external_id {
+            let units = format!("SELECT log_unit_id FROM {} WHERE external_id = {{external_id:String}}", self.table(&self.config.log_units_table));
+            conditions.add(format!("log_unit_id IN ({})", units), &[("external_id", external_id.clone())]);
+        }
+        conditions
+    }
+
+    async fn units(&self, conditions: Conditions) -> LogResult<Vec<LogUnit>> {
+        let sql = format!(
+            "SELECT {} FROM {} FINAL {} ORDER BY timestamp, log_unit_id",
+            UNIT_COLUMNS,
+            self.table(&self.config.log_units_table),
+            conditions.sql()
+        );
+        self.select(&sql, &conditions.params).await
+    }
+}
+
+/// Destination storing entries in ClickHouse, built for billions of rows.
+///
+/// Entries go to a `MergeTree` table partitioned by month and ordered by
+/// `(log_unit_id, timestamp)`, so reading a unit touches few granules. They are
+/// inserted in large batches, sent once full, when `flush_interval` has passed
+/// and on `flush`; reads only see flushed entries. An insert still failing
+/// after the retries of `retry` drops its batch, and the error goes to the
+/// call that filled it, or to stderr for timed inserts. Units go to a
+/// `ReplacingMergeTree` table right away, as asynchronous inserts so the
+/// server merges them into few parts.
+pub struct ClickHouseDestination {
+    batcher: Batcher<Server>,
+}
+
+impl ClickHouseDestination {
+    /// Connects to ClickHouse, creating the database and tables if missing
+    pub async fn new(config: ClickHouseConfig) -> LogResult<Self> {
+        let client = Client::builder().timeout(config.request_timeout).build()?;
+        let limits = BatchLimits {
+            max_items: config.max_batch_entries,
+            max_bytes: config.max_batch_bytes,
+            interval: config.flush_interval,
+        };
+        let server = Server { config, client };
+        server.create_tables().await?;
+        Ok(Self { batcher: Batcher::new(server, limits) })
+    }
+
+    fn server(&self) -> &Server {
+        self.batcher.sink()
+    }
+
+    /// Counts the entries matching `filter` per `key` and level
+    async fn level_counts<T: DeserializeOwned>(&self, filter: &StatsFilter, key: &str) -> LogResult<Vec<T>> {
+        let server = self.server();
+        let conditions = server.stats_conditions(filter);
+        let sql = format!(
+            "SELECT {key}level, count() AS count FROM {} {} GROUP BY {key}level ORDER BY {key}level",
+            server.table(&server.config.log_entries_table),
+            conditions.sql()
+        );
+        server.select(&sql, &conditions.params).await
+    }
+}
+
+#[async_trait]
+impl LogService for ClickHouseDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        let server = self.server();
+        let row = server.encode_unit(&log_unit)?;
+        server.insert(&server.config.log_units_table, UNIT_COLUMNS, row, true).await
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        let row = self.server().encode_entry(entry)?;
+        self.batcher.push(row).await
+    }
+
+    async fn get_log_entries(&self, log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        let server = self.server();
+        let sql = format!(
+            "SELECT {} FROM {} WHERE log_unit_id = {{log_unit_id:UUID}} ORDER BY timestamp, message_id",
+            ENTRY_COLUMNS,
+            server.table(&server.config.log_entries_table)
+        );
+        let rows: Vec<EntryRow> = server.select(&sql, &[("log_unit_id".to_string(), log_unit_id.to_string())]).await?;
+        Ok(rows.into_iter().map(LogEntry::from).collect())
+    }
+
+    async fn get_log_unit(&self, log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        let mut conditions = Conditions::new();
+        conditions.add("log_unit_id = {log_unit_id:UUID}", &[("log_unit_id", log_unit_id.to_string())]);
+        Ok(self.server().units(conditions).await?.into_iter().next())
+    }
+
+    async fn get_log_units_by_external_id(&self, external_id: &str) -> LogResult<Vec<LogUnit>> {
+        let mut conditions = Conditions::new();
+        conditions.add("external_id = {external_id:String}", &[("external_id", external_id.to_string())]);
+        self.server().units(conditions).await
+    }
+
+    async fn list_log_units(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> LogResult<Vec<LogUnit>> {
+        let mut conditions = Conditions::new();
+        conditions.time_range(from, to);
+        self.server().units(conditions).await
+    }
+
+    async fn count_by_level(&self, filter: &StatsFilter) -> LogResult<LevelCounts> {
+        let rows: Vec<LevelCount> = self.level_counts(filter, "").await?;
+        let mut counts = LevelCounts::default();
+        for row in rows {
+            counts.add(level(row.level), row.count);
+        }
+        Ok(counts)
+    }
+
+    async fn entry_histogram(&self, filter: &StatsFilter, bucket: TimeBucket) -> LogResult<Vec<HistogramBucket>> {
+        let key = format!("toDateTime(toStartOfInterval(timestamp, INTERVAL {} SECOND), 'UTC') AS bucket, ", bucket.seconds());
+        let rows: Vec<BucketCount> = self.level_counts(filter, &key).await?;
+        let mut buckets: Vec<HistogramBucket> = Vec::new();
+        for row in rows {
+            if buckets.last().is_none_or(|last| last.start != row.bucket) {
+                buckets.push(HistogramBucket { start: row.bucket, counts: LevelCounts::default() });
+            }
+            if let Some(last) = buckets.last_mut() {
+                last.counts.add(level(row.level), row.count);
+            }
+        }
+        Ok(buckets)
+    }
+
+    async fn top_units(&self, filter: &StatsFilter, ranking: UnitRanking, limit: usize) -> LogResult<Vec<UnitStats>> {
+        let server = self.server();
+        let conditions = server.stats_conditions(filter);
+        let order = match ranking {
+            UnitRanking::Entries => "count()",
+            UnitRanking::Errors => "error",
+        };
+        let sql = format!(
+            r#"
+            SELECT log_unit_id,
+                   countIf(level = 0) AS error,
+                   countIf(level = 1) AS warning,
+                   countIf(level = 2) AS info,
+                   countIf(level = 3) AS success
+            FROM {} {}
+            GROUP BY log_unit_id
+            ORDER BY {} DESC, count() DESC
+            LIMIT {}
+            "#,
+            server.table(&server.config.log_entries_table),
+            conditions.sql(),
+            order,
+            limit
+        );
+        let rows: Vec<UnitCounts> = server.select(&sql, &conditions.params).await?;
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut units = Conditions::new();
+        let ids: Vec<String> = rows.iter().map(|row| format!("'{}'", row.log_unit_id)).collect();
+        units.add(format!("log_unit_id IN ({})", ids.join(", ")), &[]);
+        let units = server.units(units).await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| UnitStats {
+                log_unit_id: row.log_unit_id,
+                log_unit: units.iter().find(|unit| unit.log_unit_id == row.log_unit_id).cloned(),
+                counts: LevelCounts { error: row.error, warning: row.warning, info: row.info, success: row.success },
+            })
+            .collect())
+    }
+
+    async fn counts_by_external_id(&self, filter: &StatsFilter) -> LogResult<Vec<ExternalIdStats>> {
+        let server = self.server();
+        let conditions = server.stats_conditions(filter);
+        // Entries are counted per unit first, so only the counts are joined with the units
+        let sql = format!(
+            r#"
+            SELECT u.external_id AS external_id, e.level AS level, sum(e.count) AS count
+            FROM (SELECT log_unit_id, level, count() AS count FROM {} {} GROUP BY log_unit_id, level) AS e
+            INNER JOIN (SELECT log_unit_id, external_id FROM {} FINAL) AS u ON u.log_unit_id = e.log_unit_id
+            GROUP BY external_id, level
+            ORDER BY external_id, level
+            "#,
+            server.table(&server.config.log_entries_table),
+            conditions.sql(),
+            server.table(&server.config.log_units_table)
+        );
+        let rows: Vec<ExternalIdCount> = server.select(&sql, &conditions.params).await?;
+        let mut groups: Vec<ExternalIdStats> = Vec::new();
+        for row in rows {
+            if groups.last().is_none_or(|last| last.external_id != row.external_id) {
+                groups.push(ExternalIdStats { external_id: row.external_id.clone(), counts: LevelCounts::default() });
+            }
+            if let Some(last) = groups.last_mut() {
+                last.counts.add(level(row.level), row.count);
+            }
+        }
+        Ok(groups)
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        self.batcher.flush().await
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        self.batcher.shutdown(timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::destinations::test_server::MockServer;
+
+    /// Decodes the `query` and `param_*` settings of a recorded request
+    fn query_parameter(path: &str, name: &str) -> Option<String> {
+        let query = path.split_once('?')?.1;
+        query.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            (key == name).then(|| {
+                let value = value.replace('+', " ");
+                let mut decoded = Vec::new();
+                let mut bytes = value.bytes();
+                while let Some(byte) = bytes.next() {
+                    match byte {
+                        b'%' => {
+                            let hex: String = bytes.by_ref().take(2).map(char::from).collect();
+                            decoded.push(u8::from_str_radix(&hex, 16).unwrap());
+                        }
+                        _ => decoded.push(byte),
+                    }
+                }
+                String::from_utf8(decoded).unwrap()
+            })
+        })
+    }
+
+    #[test]
+    fn test_row_binary_encoding() {
+        let mut row = RowBinary::default();
+        row.uuid("61f0c404-5cb3-11e7-907b-a6006ad3dba0".parse().unwrap());
+        row.string("hé");
+        row.varint(300);
+        assert_eq!(
+            row.0,
+            [
+                0xe7, 0x11, 0xb3, 0x5c, 0x04, 0xc4, 0xf0, 0x61, 0xa0, 0xdb, 0xd3, 0x6a, 0x00, 0xa6, 0x7b, 0x90, // UUID
+                3, b'h', 0xc3, 0xa9, // String
+                0xac, 0x02, // varint
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_creates_tables_and_inserts_batches() {
+        let server = MockServer::start(|_| (200, String::new())).await;
+        let destination = ClickHouseDestination::new(ClickHouseConfig {
+            url: server.url.clone(),
+            ttl: Some(Duration::from_secs(90 * 24 * 60 * 60)),
+            flush_interval: Duration::from_secs(3600),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let unit = destination.create_log_unit("job-1".to_string()).await.unwrap();
+        destination.log(LogEntry::info(unit.log_unit_id, "first".to_string())).await.unwrap();
+        destination.log(LogEntry::error(unit.log_unit_id, "second".to_string())).await.unwrap();
+        destination.flush().await.unwrap();
+
+        let requests = server.requests.lock().await;
+        let statements: Vec<String> = requests.iter().map(|request| String::from_utf8_lossy(&request.body).to_string()).collect();
+        assert_eq!(statements[0], "CREATE DATABASE IF NOT EXISTS ironscribe");
+        assert!(statements[1].contains("message_type LowCardinality(String)"));
+        assert!(statements[1].contains("ORDER BY (log_unit_id, timestamp)"));
+        assert!(statements[1].contains("TTL toDateTime(timestamp) + INTERVAL 7776000 SECOND"));
+        assert!(statements[2].contains("ENGINE = ReplacingMergeTree"));
+
+        // The unit is inserted right away, the entries in one batch
+        assert_eq!(requests.len(), 5);
+        let insert = query_parameter(&requests[4].path, "query").unwrap();
+        assert_eq!(insert, format!("INSERT INTO ironscribe.log_entries ({}) FORMAT RowBinary", ENTRY_COLUMNS));
+        assert_eq!(requests[3].body, RowBinary::default().unit(&unit));
+        assert_eq!(query_parameter(&requests[3].path, "async_insert").as_deref(), Some("1"));
+        assert_eq!(query_parameter(&requests[4].path, "async_insert"), None);
+        assert_eq!(requests[4].body[..16], RowBinary::default().unit(&unit)[..16]);
+    }
+
+    #[tokio::test]
+    async fn test_reads_and_aggregations() {
+        let unit = LogUnit::new("job-1".to_string());
+        let entry = LogEntry::warning(unit.log_unit_id, "disk almost full".to_string())
+            .with_location(SourceLocation::new("src/disk.rs", 42, 9, "app::disk"));
+        let entry_row = serde_json::to_string(&EntryRow::from(entry.clone())).unwrap();
+        let unit_row = serde_json::to_string(&unit).unwrap();
+        let server = MockServer::start(move |request| {
+            let sql = String::from_utf8_lossy(&request.body).to_string();
+            let body = if sql.contains("FROM ironscribe.log_entries WHERE log_unit_id") {
+                entry_row.clone()
+            } else if sql.contains("FROM ironscribe.log_units FINAL") {
+                unit_row.clone()
+            } else if sql.contains("countIf") {
+                format!(r#"{{"log_unit_id":"{}","error":2,"warning":1,"info":0,"success":0}}"#, unit.log_unit_id)
+            } else if sql.contains("GROUP BY level") {
+                "{\"level\":0,\"count\":2}\n{\"level\":1,\"count\":1}\n".to_string()
+            } else {
+                String::new()
+            };
+            (200, body)
+        })
+        .await;
+        let destination = ClickHouseDestination::new(ClickHouseConfig { url: server.url.clone(), ..Default::default() }).await.unwrap();
+
+        assert_eq!(destination.get_log_entries(entry.log_unit_id).await.unwrap(), vec![entry.clone()]);
+        assert_eq!(destination.get_log_unit(entry.log_unit_id).await.unwrap().unwrap().external_id, "job-1");
+        let filter = StatsFilter::all().external_id("job-1").min_level(LogLevel::Warning);
+        let counts = destination.count_by_level(&filter).await.unwrap();
+        assert_eq!((counts.error, counts.warning), (2, 1));
+        let top = destination.top_units(&filter, UnitRanking::Errors, 10).await.unwrap();
+        assert_eq!(top[0].counts.error, 2);
+        assert_eq!(top[0].log_unit.as_ref().unwrap().external_id, "job-1");
+
+        let requests = server.requests.lock().await;
+        let count_request = requests.iter().find(|request| String::from_utf8_lossy(&request.body).contains("GROUP BY level")).unwrap();
+        let sql = String::from_utf8_lossy(&count_request.body);
+        assert!(sql.contains("level <= {min_level:UInt8}"));
+        assert!(sql.contains("external_id = {external_id:String}"));
+        assert_eq!(query_parameter(&count_request.path, "param_min_level").as_deref(), Some("1"));
+        assert_eq!(query_parameter(&count_request.path, "param_external_id").as_deref(), Some("job-1"));
+    }
+}
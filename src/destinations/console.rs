@@ -1,17 +1,25 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use owo_colors::OwoColorize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
-use crate::core::{LogEntry, LogService, LogUnit, LogMessageType};
+use crate::core::{ErrorDetails, ExternalIdStats, HistogramBucket, LevelCounts, LogEntry, LogLevel, LogService, LogUnit, LogMessageType, SearchHit, SearchOptions, StatsFilter, TimeBucket, UnitRanking, UnitStats};
+use crate::core::analytics;
 use crate::core::log_service::LogResult;
+use crate::core::search::{self, InvertedIndex};
 
 /// Console-based log destination that prints colored output
 pub struct ConsoleDestination {
     log_units: Arc<RwLock<HashMap<Uuid, LogUnit>>>,
     log_entries: Arc<RwLock<HashMap<Uuid, Vec<LogEntry>>>>,
+    search_index: Arc<RwLock<InvertedIndex>>,
+    store: bool,
+    show_locations: bool,
 }
 
 impl ConsoleDestination {
@@ -20,11 +28,31 @@ impl ConsoleDestination {
         Self {
             log_units: Arc::new(RwLock::new(HashMap::new())),
             log_entries: Arc::new(RwLock::new(HashMap::new())),
+            search_index: Arc::new(RwLock::new(InvertedIndex::default())),
+            store: true,
+            show_locations: false,
         }
     }
 
-    /// Prints a log entry to the console with appropriate colors
-    fn print_entry(&self, entry: &LogEntry) {
+    /// Creates a console destination that only prints and keeps nothing in memory.
+    ///
+    /// Intended as a mirror next to a storage destination, where reads are
+    /// answered by the storage destination.
+    pub fn echo_only() -> Self {
+        Self {
+            store: false,
+            ..Self::new()
+        }
+    }
+
+    /// Formats a log entry the way it is printed, with appropriate colors.
+    ///
+    /// A recorded error follows on indented lines, one per error in its chain.
+    pub fn format_entry(entry: &LogEntry) -> String {
+        Self::format_lines(entry, false)
+    }
+
+    fn format_lines(entry: &LogEntry, show_location: bool) -> String {
         let formatted_time = entry.timestamp.format("%Y-%m-%d %H:%M:%S UTC");
         let level_str = match entry.message_type {
             LogMessageType::Error => "ERROR".red().bold().to_string(),
@@ -33,13 +61,70 @@ impl ConsoleDestination {
             LogMessageType::Success => "SUCCESS".green().bold().to_string(),
         };
 
-        println!(
+        let mut formatted = format!(
             "[{}] [{}] [{}] {}",
             formatted_time,
             level_str,
             entry.log_unit_id.to_string().dimmed(),
             entry.message
         );
+        if let Some(location) = entry.location.as_ref().filter(|_| show_location) {
+            formatted.push_str(&format!(" {}", format!("at {}", location).dimmed()));
+        }
+        if let Some(error) = &entry.error {
+            formatted.push_str(&Self::format_error(error, &entry.message));
+        }
+        formatted
+    }
+
+    /// Formats the chain of `error`, each source indented below the error it caused
+    fn format_error(error: &ErrorDetails, message: &str) -> String {
+        let mut formatted = String::new();
+        // `LogEntry::from_error` uses the error itself as the message
+        let skip = usize::from(error.chain.first().is_some_and(|first| first == message));
+        for (depth, cause) in error.chain.iter().enumerate().skip(skip) {
+            let label = if depth == 0 { "error" } else { "caused by" };
+            formatted.push_str(&format!("\n{:indent$}{}: {}", "", label, cause, indent = 4 + 2 * depth));
+        }
+        if let Some(backtrace) = &error.backtrace {
+            formatted.push_str("\n    backtrace:");
+            for line in backtrace.lines() {
+                formatted.push_str(&format!("\n      {}", line.dimmed()));
+            }
+        }
+        formatted
+    }
+
+    /// Prints the source location after each entry that has one
+    pub fn with_locations(mut self) -> Self {
+        self.show_locations = true;
+        self
+    }
+
+    /// Formats a log entry like `format_entry`, with its source location if known
+    pub fn format_entry_with_location(entry: &LogEntry) -> String {
+        Self::format_lines(entry, true)
+    }
+
+    /// Prints a log entry to the console with appropriate colors
+    fn print_entry(&self, entry: &LogEntry) {
+        if self.show_locations {
+            println!("{}", Self::format_entry_with_location(entry));
+        } else {
+            println!("{}", Self::format_entry(entry));
+        }
+    }
+
+    /// Calls `f` with every stored entry matching `filter` and the unit it belongs to
+    async fn for_each_matching(&self, filter: &StatsFilter, mut f: impl FnMut(Option<&LogUnit>, &LogEntry) + Send) {
+        let units = self.log_units.read().await;
+        let entries = self.log_entries.read().await;
+        for (log_unit_id, unit_entries) in entries.iter() {
+            let log_unit = units.get(log_unit_id);
+            for entry in unit_entries.iter().filter(|entry| filter.matches(log_unit, entry)) {
+                f(log_unit, entry);
+            }
+        }
     }
 }
 
@@ -51,26 +136,34 @@ impl Default for ConsoleDestination {
 
 #[async_trait]
 impl LogService for ConsoleDestination {
-    async fn create_log_unit(&self, external_id: String) -> LogResult<LogUnit> {
-        let log_unit = LogUnit::new(external_id);
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        if !self.store {
+            return Ok(());
+        }
 
         // Store the log unit
         let mut units = self.log_units.write().await;
-        units.insert(log_unit.log_unit_id, log_unit.clone());
+        let log_unit_id = log_unit.log_unit_id;
+        units.insert(log_unit_id, log_unit);
 
         // Initialize empty entries vector for this unit
         let mut entries = self.log_entries.write().await;
-        entries.insert(log_unit.log_unit_id, Vec::new());
+        entries.entry(log_unit_id).or_default();
 
-        Ok(log_unit)
+        Ok(())
     }
 
     async fn log(&self, entry: LogEntry) -> LogResult<()> {
         // Print to console
         self.print_entry(&entry);
 
+        if !self.store {
+            return Ok(());
+        }
+
         // Store the entry
         let mut entries = self.log_entries.write().await;
+        self.search_index.write().await.insert(&entry);
         if let Some(unit_entries) = entries.get_mut(&entry.log_unit_id) {
             unit_entries.push(entry);
         } else {
@@ -99,6 +192,182 @@ impl LogService for ConsoleDestination {
             .collect();
         Ok(matching_units)
     }
+
+    async fn list_log_units(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> LogResult<Vec<LogUnit>> {
+        let units = self.log_units.read().await;
+        let mut matching_units: Vec<LogUnit> = units
+            .values()
+            .filter(|unit| from.is_none_or(|from| unit.timestamp >= from) && to.is_none_or(|to| unit.timestamp < to))
+            .cloned()
+            .collect();
+        matching_units.sort_by_key(|unit| unit.timestamp);
+        Ok(matching_units)
+    }
+
+    async fn search_entries(&self, text: &str, options: &SearchOptions) -> LogResult<Vec<SearchHit>> {
+        let units = self.log_units.read().await;
+        let entries = self.log_entries.read().await;
+        let terms: HashSet<String> = search::tokenize(text).into_iter().collect();
+
+        let mut hits: Vec<SearchHit> = self.search_index
+            .read()
+            .await
+            .search(&terms)
+            .into_iter()
+            .filter_map(|(log_unit_id, message_id, score)| {
+                let entry = entries.get(&log_unit_id)?.iter().find(|e| e.message_id == message_id)?;
+                let log_unit = units.get(&log_unit_id);
+                let external_id_matches = options
+                    .external_id
+                    .as_ref()
+                    .is_none_or(|external_id| log_unit.is_some_and(|unit| &unit.external_id == external_id));
+                if !external_id_matches || !options.matches(entry) {
+                    return None;
+                }
+
+                Some(SearchHit {
+                    entry: entry.clone(),
+                    log_unit: log_unit.cloned(),
+                    snippet: search::snippet(&entry.message, &terms, options),
+                    score,
+                })
+            })
+            .collect();
+
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| b.entry.timestamp.cmp(&a.entry.timestamp))
+        });
+        hits.truncate(options.limit);
+        Ok(hits)
+    }
+
+    async fn count_by_level(&self, filter: &StatsFilter) -> LogResult<LevelCounts> {
+        let mut counts = LevelCounts::default();
+        self.for_each_matching(filter, |_, entry| counts.add(entry.level, 1)).await;
+        Ok(counts)
+    }
+
+    async fn entry_histogram(&self, filter: &StatsFilter, bucket: TimeBucket) -> LogResult<Vec<HistogramBucket>> {
+        let mut buckets: BTreeMap<DateTime<Utc>, LevelCounts> = BTreeMap::new();
+        self.for_each_matching(filter, |_, entry| {
+            buckets.entry(bucket.start_of(entry.timestamp)).or_default().add(entry.level, 1);
+        })
+        .await;
+        Ok(buckets.into_iter().map(|(start, counts)| HistogramBucket { start, counts }).collect())
+    }
+
+    async fn top_units(&self, filter: &StatsFilter, ranking: UnitRanking, limit: usize) -> LogResult<Vec<UnitStats>> {
+        let mut by_unit: HashMap<Uuid, UnitStats> = HashMap::new();
+        self.for_each_matching(filter, |log_unit, entry| {
+            by_unit
+                .entry(entry.log_unit_id)
+                .or_insert_with(|| UnitStats {
+                    log_unit_id: entry.log_unit_id,
+                    log_unit: log_unit.cloned(),
+                    counts: LevelCounts::default(),
+                })
+                .counts
+                .add(entry.level, 1);
+        })
+        .await;
+
+        let mut units: Vec<UnitStats> = by_unit.into_values().collect();
+        analytics::rank_units(&mut units, ranking, limit);
+        Ok(units)
+    }
+
+    async fn counts_by_external_id(&self, filter: &StatsFilter) -> LogResult<Vec<ExternalIdStats>> {
+        let mut by_external_id: BTreeMap<String, LevelCounts> = BTreeMap::new();
+        self.for_each_matching(filter, |log_unit, entry| {
+            if let Some(log_unit) = log_unit {
+                by_external_id.entry(log_unit.external_id.clone()).or_default().add(entry.level, 1);
+            }
+        })
+        .await;
+        Ok(by_external_id
+            .into_iter()
+            .map(|(external_id, counts)| ExternalIdStats { external_id, counts })
+            .collect())
+    }
+
+    async fn delete_log_unit(&self, log_unit_id: Uuid) -> LogResult<bool> {
+        let mut units = self.log_units.write().await;
+        let mut entries = self.log_entries.write().await;
+        let mut search_index = self.search_index.write().await;
+
+        let removed_entries = entries.remove(&log_unit_id);
+        for entry in removed_entries.iter().flatten() {
+            search_index.remove(entry);
+        }
+        Ok(units.remove(&log_unit_id).is_some() || removed_entries.is_some())
+    }
+
+    async fn purge_entries(&self, before: DateTime<Utc>, level: Option<LogLevel>) -> LogResult<u64> {
+        let mut entries = self.log_entries.write().await;
+        let mut search_index = self.search_index.write().await;
+        let mut removed = 0;
+        for unit_entries in entries.values_mut() {
+            let expired = unit_entries.extract_if(.., |entry| {
+                entry.timestamp < before && level.is_none_or(|level| entry.level == level)
+            });
+            for entry in expired {
+                search_index.remove(&entry);
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    async fn purge_empty_log_units(&self, before: DateTime<Utc>) -> LogResult<u64> {
+        let mut units = self.log_units.write().await;
+        let mut entries = self.log_entries.write().await;
+        let expired: Vec<Uuid> = units
+            .values()
+            .filter(|unit| unit.timestamp < before)
+            .filter(|unit| entries.get(&unit.log_unit_id).is_none_or(|e| e.is_empty()))
+            .map(|unit| unit.log_unit_id)
+            .collect();
+        for log_unit_id in &expired {
+            units.remove(log_unit_id);
+            entries.remove(log_unit_id);
+        }
+        Ok(expired.len() as u64)
+    }
+
+    async fn trim_log_units(&self, max_units: usize) -> LogResult<u64> {
+        let mut units = self.log_units.write().await;
+        let mut entries = self.log_entries.write().await;
+        let mut search_index = self.search_index.write().await;
+
+        let mut by_external_id: HashMap<&str, Vec<&LogUnit>> = HashMap::new();
+        for unit in units.values() {
+            by_external_id.entry(unit.external_id.as_str()).or_default().push(unit);
+        }
+        let mut expired = Vec::new();
+        for group in by_external_id.values_mut() {
+            group.sort_by_key(|unit| std::cmp::Reverse(unit.timestamp));
+            expired.extend(group.iter().skip(max_units).map(|unit| unit.log_unit_id));
+        }
+
+        for log_unit_id in &expired {
+            units.remove(log_unit_id);
+            for entry in entries.remove(log_unit_id).iter().flatten() {
+                search_index.remove(entry);
+            }
+        }
+        Ok(expired.len() as u64)
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        std::io::stdout().flush()?;
+        Ok(())
+    }
+
+    async fn shutdown(&self, _timeout: Duration) -> LogResult<()> {
+        self.flush().await
+    }
 }
 
 #[cfg(test)]
@@ -117,4 +386,114 @@ mod tests {
         assert_eq!(entries.len(), 1);
         assert_eq!(entries[0].message, "Test message");
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_echo_only_keeps_nothing() {
+        let destination = ConsoleDestination::echo_only();
+        let unit = destination.create_log_unit("echo".to_string()).await.unwrap();
+
+        let entry = LogEntry::info(unit.log_unit_id, "Echoed".to_string());
+        destination.log(entry).await.unwrap();
+
+        assert!(destination.get_log_unit(unit.log_unit_id).await.unwrap().is_none());
+        assert!(destination.get_log_entries(unit.log_unit_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retention_evicts_from_memory() {
+        let destination = ConsoleDestination::new();
+        let unit = destination.create_log_unit("job".to_string()).await.unwrap();
+        let newer = destination.create_log_unit("job".to_string()).await.unwrap();
+
+        let mut old_info = LogEntry::info(unit.log_unit_id, "Old info".to_string());
+        old_info.timestamp -= chrono::Duration::days(10);
+        let mut old_error = LogEntry::error(unit.log_unit_id, "Old error".to_string());
+        old_error.timestamp -= chrono::Duration::days(10);
+        destination.log(old_info).await.unwrap();
+        destination.log(old_error).await.unwrap();
+
+        let policy = crate::core::RetentionPolicy::new()
+            .keep_level(LogLevel::Error, chrono::Duration::days(90))
+            .keep_level(LogLevel::Info, chrono::Duration::days(7));
+        let report = destination.apply_retention(&policy).await.unwrap();
+        assert_eq!(report.entries, 1);
+        let entries = destination.get_log_entries(unit.log_unit_id).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].message, "Old error");
+
+        let policy = crate::core::RetentionPolicy::new().max_units_per_external_id(1);
+        let report = destination.apply_retention(&policy).await.unwrap();
+        assert_eq!(report.units, 1);
+        let remaining = destination.get_log_units_by_external_id("job").await.unwrap();
+        assert_eq!(remaining, vec![newer]);
+        assert!(destination.get_log_entries(unit.log_unit_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_entries() {
+        let destination = ConsoleDestination::new();
+        let orders = destination.create_log_unit("orders".to_string()).await.unwrap();
+        let billing = destination.create_log_unit("billing".to_string()).await.unwrap();
+
+        destination.log(LogEntry::info(orders.log_unit_id, "Received order 8812".to_string())).await.unwrap();
+        destination.log(LogEntry::error(orders.log_unit_id, "Order 8812 failed, order 8812 retried".to_string())).await.unwrap();
+        destination.log(LogEntry::info(billing.log_unit_id, "Invoiced order 9000".to_string())).await.unwrap();
+
+        let hits = destination.search_entries("order 8812", &SearchOptions::default()).await.unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].entry.message, "Order 8812 failed, order 8812 retried");
+        assert_eq!(hits[0].log_unit, Some(orders.clone()));
+        assert!(hits[0].score > hits[1].score);
+        assert_eq!(hits[1].snippet, "Received <b>order</b> <b>8812</b>");
+
+        let options = SearchOptions { min_level: Some(LogLevel::Error), ..Default::default() };
+        assert_eq!(destination.search_entries("order 8812", &options).await.unwrap().len(), 1);
+
+        destination.delete_log_unit(orders.log_unit_id).await.unwrap();
+        assert!(destination.search_entries("8812", &SearchOptions::default()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_analytics() {
+        let destination = ConsoleDestination::new();
+        let noisy = destination.create_log_unit("orders".to_string()).await.unwrap();
+        let quiet = destination.create_log_unit("billing".to_string()).await.unwrap();
+
+        for _ in 0..3 {
+            destination.log(LogEntry::info(noisy.log_unit_id, "Polling".to_string())).await.unwrap();
+        }
+        destination.log(LogEntry::error(quiet.log_unit_id, "Card declined".to_string())).await.unwrap();
+        destination.log(LogEntry::error(quiet.log_unit_id, "Card declined".to_string())).await.unwrap();
+
+        let counts = destination.count_by_level(&StatsFilter::all()).await.unwrap();
+        assert_eq!((counts.error, counts.info, counts.total()), (2, 3, 5));
+
+        let histogram = destination.entry_histogram(&StatsFilter::all(), TimeBucket::Day).await.unwrap();
+        assert_eq!(histogram.len(), 1);
+        assert_eq!(histogram[0].counts, counts);
+
+        let top = destination.top_units(&StatsFilter::all(), UnitRanking::Entries, 1).await.unwrap();
+        assert_eq!(top[0].log_unit, Some(noisy));
+        let top = destination.top_units(&StatsFilter::all(), UnitRanking::Errors, 1).await.unwrap();
+        assert_eq!(top[0].log_unit_id, quiet.log_unit_id);
+
+        let errors = StatsFilter::all().min_level(LogLevel::Error);
+        let by_external_id = destination.counts_by_external_id(&errors).await.unwrap();
+        assert_eq!(by_external_id.len(), 1);
+        assert_eq!(by_external_id[0].external_id, "billing");
+        assert_eq!(by_external_id[0].counts.error, 2);
+    }
+
+    #[test]
+    fn test_format_error_chain() {
+        let error = ErrorDetails {
+            chain: vec!["request failed".to_string(), "connection refused".to_string()],
+            backtrace: None,
+        };
+
+        let lines = ConsoleDestination::format_error(&error, "Sync failed");
+        assert_eq!(lines, "\n    error: request failed\n      caused by: connection refused");
+        let lines = ConsoleDestination::format_error(&error, "request failed");
+        assert_eq!(lines, "\n      caused by: connection refused");
+    }
+}
@@ -0,0 +1,670 @@
+//! Elasticsearch destination indexing entries into daily indices through the `_bulk` API
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::core::log_service::LogResult;
+use crate::core::search;
+use crate::core::{ErrorDetails, LogEntry, LogLevel, LogMessageType, LogService, LogUnit, SearchHit, SearchOptions, SourceLocation, TraceContext};
+use crate::destinations::batch::{BatchLimits, BatchSink, Batcher};
+use crate::destinations::http::{retry_after, HttpAuth, HttpError};
+use crate::destinations::retry::RetryPolicy;
+
+/// Number of hits fetched per search request when reading all matches
+const PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct ElasticConfig {
+    /// Base URL of the cluster, e.g. `http://localhost:9200`
+    pub url: String,
+    pub auth: Option<HttpAuth>,
+    /// Entries are indexed into `{prefix}-entries-YYYY.MM.DD`, units into `{prefix}-units`
+    pub index_prefix: String,
+    /// Sends a bulk request once it holds this many entries
+    pub max_batch_entries: usize,
+    /// Sends the pending entries at least this often
+    pub flush_interval: Duration,
+    /// Waits for writes to become searchable before they complete (`refresh=wait_for`)
+    pub refresh_on_write: bool,
+    /// Timeout of a single request
+    pub request_timeout: Duration,
+    /// Retries of bulk requests failing with a connection error, a 5xx or a 429.
+    ///
+    /// Entries rejected with a 429 or 5xx within a successful bulk request are
+    /// retried on their own; other rejections fail the batch.
+    pub retry: RetryPolicy,
+}
+
+impl Default for ElasticConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:9200".to_string(),
+            auth: None,
+            index_prefix: "ironscribe".to_string(),
+            max_batch_entries: 500,
+            flush_interval: Duration::from_secs(1),
+            refresh_on_write: false,
+            request_timeout: Duration::from_secs(30),
+            retry: RetryPolicy {
+                max_retries: 5,
+                initial_backoff: Duration::from_millis(500),
+                max_backoff: Duration::from_secs(30),
+                jitter: 0.5,
+            },
+        }
+    }
+}
+
+/// Indexed form of an entry, storing the level as a number so it can be filtered by range
+#[derive(Debug, Serialize, Deserialize)]
+struct EntryDocument {
+    log_unit_id: Uuid,
+    message_id: Uuid,
+    level: u8,
+    message: String,
+    message_type: LogMessageType,
+    timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location: Option<SourceLocation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorDetails>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trace: Option<TraceContext>,
+}
+
+impl From<LogEntry> for EntryDocument {
+    fn from(entry: LogEntry) -> Self {
+        Self {
+            log_unit_id: entry.log_unit_id,
+            message_id: entry.message_id,
+            level: entry.level as u8,
+            message: entry.message,
+            message_type: entry.message_type,
+            timestamp: entry.timestamp,
+            location: entry.location,
+            error: entry.error,
+            trace: entry.trace,
+        }
+    }
+}
+
+impl From<EntryDocument> for LogEntry {
+    fn from(document: EntryDocument) -> Self {
+        Self {
+            log_unit_id: document.log_unit_id,
+            message_id: document.message_id,
+            level: LogLevel::ALL.get(document.level as usize).copied().unwrap_or(document.message_type.into()),
+            message: document.message,
+            message_type: document.message_type,
+            timestamp: document.timestamp,
+            location: document.location,
+            error: document.error,
+            trace: document.trace,
+        }
+    }
+}
+
+fn entry_mappings() -> Value {
+    json!({
+        // Fields added by newer versions are kept in `_source` without failing older indices
+        "dynamic": false,
+        "properties": {
+            "log_unit_id": { "type": "keyword" },
+            "message_id": { "type": "keyword" },
+            "level": { "type": "byte" },
+            "message": { "type": "text" },
+            "message_type": { "type": "keyword" },
+            "timestamp": { "type": "date_nanos" },
+            "location": {
+                "properties": {
+                    "file": { "type": "keyword" },
+                    "line": { "type": "integer" },
+                    "column": { "type": "integer" },
+                    "module_path": { "type": "keyword" }
+                }
+            },
+            "error": {
+                "properties": {
+                    "chain": { "type": "text" },
+                    "backtrace": { "type": "text", "index": false }
+                }
+            },
+            "trace": {
+                "properties": {
+                    "trace_id": { "type": "keyword" },
+                    "span_id": { "type": "keyword" }
+                }
+            }
+        }
+    })
+}
+
+fn unit_mappings() -> Value {
+    json!({
+        "dynamic": false,
+        "properties": {
+            "log_unit_id": { "type": "keyword" },
+            "external_id": { "type": "keyword" },
+            "timestamp": { "type": "date_nanos" }
+        }
+    })
+}
+
+/// A bulk item the cluster did not index
+struct Rejection {
+    /// Position of the item in the request
+    index: usize,
+    status: u16,
+    reason: String,
+}
+
+impl Rejection {
+    fn is_transient(&self) -> bool {
+        self.status == StatusCode::TOO_MANY_REQUESTS.as_u16() || self.status >= 500
+    }
+}
+
+/// Returns the reason given in an Elasticsearch error response, or the body itself
+fn error_reason(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|json| json["error"]["reason"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+/// Talks to the cluster; batches are pairs of bulk action and document lines
+struct Cluster {
+    config: ElasticConfig,
+    client: Client,
+}
+
+#[async_trait]
+impl BatchSink for Cluster {
+    type Item = String;
+
+    async fn send(&self, actions: Vec<String>) -> LogResult<()> {
+        let count = actions.len();
+        self.index(actions)
+            .await
+            .map_err(|e| format!("Failed to index {} log entries in {}: {}", count, self.config.url, e).into())
+    }
+}
+
+impl Cluster {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}/{}", self.config.url.trim_end_matches('/'), path);
+        let request = self.client.request(method, url);
+        match &self.config.auth {
+            Some(auth) => auth.apply(request),
+            None => request,
+        }
+    }
+
+    /// Sends `body` as JSON, returning the status and the response body
+    async fn call(&self, method: Method, path: &str, body: Option<&Value>) -> LogResult<(StatusCode, String)> {
+        let mut request = self.request(method, path);
+        if let Some(body) = body {
+            request = request.header(CONTENT_TYPE, "application/json").body(serde_json::to_vec(body)?);
+        }
+        let response = request.send().await?;
+        Ok((response.status(), response.text().await?))
+    }
+
+    /// Like `call`, failing unless the request succeeded
+    async fn call_ok(&self, method: Method, path: &str, body: Option<&Value>) -> LogResult<Value> {
+        let (status, text) = self.call(method, path, body).await?;
+        if !status.is_success() {
+            return Err(format!("HTTP {} from {}: {}", status, path, error_reason(&text)).into());
+        }
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    fn refresh_parameter(&self) -> &'static str {
+        if self.config.refresh_on_write { "?refresh=wait_for" } else { "" }
+    }
+
+    fn entries_pattern(&self) -> String {
+        format!("{}-entries-*", self.config.index_prefix)
+    }
+
+    fn units_index(&self) -> String {
+        format!("{}-units", self.config.index_prefix)
+    }
+
+    /// Installs the template of the entry indices and creates the units index
+    async fn setup(&self) -> LogResult<()> {
+        let template = json!({
+            "index_patterns": [self.entries_pattern()],
+            "priority": 200,
+            "template": { "mappings": entry_mappings() }
+        });
+        let path = format!("_index_template/{}-entries", self.config.index_prefix);
+        self.call_ok(Method::PUT, &path, Some(&template)).await?;
+
+        let units = json!({ "mappings": unit_mappings() });
+        let (status, text) = self.call(Method::PUT, &self.units_index(), Some(&units)).await?;
+        let exists = serde_json::from_str::<Value>(&text)
+            .is_ok_and(|json| json["error"]["type"] == "resource_already_exists_exception");
+        if !status.is_success() && !exists {
+            return Err(format!("Failed to create index {}: HTTP {}: {}", self.units_index(), status, error_reason(&text)).into());
+        }
+        Ok(())
+    }
+
+    /// Bulk-indexes `actions`, retrying the request or its transiently rejected items
+    async fn index(&self, actions: Vec<String>) -> LogResult<()> {
+        // Each attempt sends the actions left by the previous one
+        let pending = Mutex::new(actions);
+        let rejected = Mutex::new(Vec::new());
+        let attempt = || async {
+            let actions = pending.lock().unwrap().clone();
+            let rejections = self.bulk(&actions).await?;
+            let (transient, permanent): (Vec<_>, Vec<_>) = rejections.into_iter().partition(Rejection::is_transient);
+            rejected.lock().unwrap().extend(permanent.into_iter().map(|rejection| rejection.reason));
+            let Some(first) = transient.first() else { return Ok(()) };
+            let error = HttpError {
+                status: StatusCode::from_u16(first.status).ok(),
+                retry_after: None,
+                message: format!("{} entries rejected: {}", transient.len(), first.reason),
+            };
+            *pending.lock().unwrap() = transient.iter().map(|rejection| actions[rejection.index].clone()).collect();
+            Err(error)
+        };
+        self.config.retry.run(attempt, HttpError::failure).await?;
+        let rejected = rejected.into_inner().unwrap();
+        match rejected.first() {
+            Some(reason) => Err(format!("{} entries rejected: {}", rejected.len(), reason).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends one bulk request, returning the items that were not indexed
+    async fn bulk(&self, actions: &[String]) -> Result<Vec<Rejection>, HttpError> {
+        let request = self
+            .request(Method::POST, &format!("_bulk{}", self.refresh_parameter()))
+            .header(CONTENT_TYPE, "application/x-ndjson")
+            .body(actions.concat());
+        let failure = |status, retry_after, message| HttpError { status, retry_after, message };
+        let response = request.send().await.map_err(|e| failure(None, None, e.to_string()))?;
+        let status = response.status();
+        let retry_after = retry_after(response.headers());
+        let text = response.text().await.map_err(|e| failure(None, None, e.to_string()))?;
+        if !status.is_success() {
+            return Err(failure(Some(status), retry_after, format!("HTTP {}: {}", status, error_reason(&text))));
+        }
+
+        let json: Value = serde_json::from_str(&text).map_err(|e| failure(Some(status), None, format!("invalid bulk response: {}", e)))?;
+        if json["errors"] != true {
+            return Ok(Vec::new());
+        }
+        let items = json["items"].as_array().map(Vec::as_slice).unwrap_or_default();
+        Ok(items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                // Each item is keyed by its action, e.g. `{"index": {"status": 429, ...}}`
+                let result = item.as_object()?.values().next()?;
+                let status = result["status"].as_u64()? as u16;
+                (status >= 300).then(|| Rejection {
+                    index,
+                    status,
+                    reason: result["error"]["reason"].as_str().unwrap_or("unknown error").to_string(),
+                })
+            })
+            .collect())
+    }
+
+    /// Returns the hits of a search, tolerating missing indices
+    async fn search(&self, index: &str, body: &Value) -> LogResult<Vec<Value>> {
+        let path = format!("{}/_search?ignore_unavailable=true&allow_no_indices=true", index);
+        let mut response = self.call_ok(Method::POST, &path, Some(body)).await?;
+        match response["hits"]["hits"].take() {
+            Value::Array(hits) => Ok(hits),
+            _ => Err(format!("invalid search response from {}", index).into()),
+        }
+    }
+
+    /// Returns the sources of all documents matching `query`, paging with `search_after`
+    async fn search_all<T: DeserializeOwned>(&self, index: &str, query: Value, sort: Value) -> LogResult<Vec<T>> {
+        let mut results = Vec::new();
+        let mut body = json!({ "query": query, "sort": sort, "size": PAGE_SIZE });
+        loop {
+            let hits = self.search(index, &body).await?;
+            let last_page = hits.len() < PAGE_SIZE;
+            for mut hit in hits {
+                body["search_after"] = hit["sort"].take();
+                results.push(serde_json::from_value(hit["_source"].take())?);
+            }
+            if last_page {
+                return Ok(results);
+            }
+        }
+    }
+
+    async fn units(&self, query: Value) -> LogResult<Vec<LogUnit>> {
+        let sort = json!([{ "timestamp": "asc" }, { "log_unit_id": "asc" }]);
+        self.search_all(&self.units_index(), query, sort).await
+    }
+
+    /// Filters restricting a search of entries to `options`; `None` if no entry can match
+    async fn entry_filters(&self, options: &SearchOptions) -> LogResult<Option<Vec<Value>>> {
+        let mut filters = Vec::new();
+        if let Some(level) = options.min_level {
+            filters.push(json!({ "range": { "level": { "lte": level as u8 } } }));
+        }
+        let mut timestamp = serde_json::Map::new();
+        if let Some(from) = options.from {
+            timestamp.insert("gte".to_string(), from.to_rfc3339_opts(SecondsFormat::Nanos, true).into());
+        }
+        if let Some(to) = options.to {
+            timestamp.insert("lt".to_string(), to.to_rfc3339_opts(SecondsFormat::Nanos, true).into());
+        }
+        if !timestamp.is_empty() {
+            filters.push(json!({ "range": { "timestamp": timestamp } }));
+        }
+        if let Some(module) = &options.module_path {
+            filters.push(json!({
+                "bool": {
+                    "should": [
+                        { "term": { "location.module_path": module } },
+                        { "prefix": { "location.module_path": format!("{}::", module) } }
+                    ],
+                    "minimum_should_match": 1
+                }
+            }));
+        }
+        if let Some(external_id) = &options.external_id {
+            let units = self.units(json!({ "term": { "external_id": external_id } })).await?;
+            if units.is_empty() {
+                return Ok(None);
+            }
+            let ids: Vec<Uuid> = units.iter().map(|unit| unit.log_unit_id).collect();
+            filters.push(json!({ "terms": { "log_unit_id": ids } }));
+        }
+        Ok(Some(filters))
+    }
+}
+
+/// Destination indexing entries into Elasticsearch (or OpenSearch).
+///
+/// Entries are batched into `_bulk` requests and indexed into daily indices
+/// named `{prefix}-entries-YYYY.MM.DD` after their timestamp, whose mappings
+/// come from an index template installed by `new`. Units are written to
+/// `{prefix}-units` right away. Reads are served by search queries, so entries
+/// are only visible once a batch has been sent and the index refreshed.
+///
+/// Entries whose bulk request fails, or that the cluster rejects, are dropped
+/// once retrying is pointless or the retries of `retry` are used up. The
+/// error is returned by the call that filled the batch, or printed to stderr
+/// when the batch was sent by the timer.
+pub struct ElasticDestination {
+    batcher: Batcher<Cluster>,
+}
+
+impl ElasticDestination {
+    /// Connects to the cluster, installing the index template and creating the units index
+    pub async fn new(config: ElasticConfig) -> LogResult<Self> {
+        let client = Client::builder().timeout(config.request_timeout).build()?;
+        let limits = BatchLimits {
+            max_items: config.max_batch_entries,
+            max_bytes: usize::MAX,
+            interval: config.flush_interval,
+        };
+        let cluster = Cluster { config, client };
+        cluster.setup().await?;
+        Ok(Self { batcher: Batcher::new(cluster, limits) })
+    }
+
+    fn cluster(&self) -> &Cluster {
+        self.batcher.sink()
+    }
+}
+
+#[async_trait]
+impl LogService for ElasticDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        let cluster = self.cluster();
+        let path = format!("{}/_doc/{}{}", cluster.units_index(), log_unit.log_unit_id, cluster.refresh_parameter());
+        cluster.call_ok(Method::PUT, &path, Some(&serde_json::to_value(&log_unit)?)).await?;
+        Ok(())
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        let index = format!("{}-entries-{}", self.cluster().config.index_prefix, entry.timestamp.format("%Y.%m.%d"));
+        let action = json!({ "index": { "_index": index, "_id": entry.message_id } });
+        let document = serde_json::to_string(&EntryDocument::from(entry))?;
+        self.batcher.push(format!("{}\n{}\n", action, document)).await
+    }
+
+    async fn get_log_entries(&self, log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        let cluster = self.cluster();
+        let query = json!({ "term": { "log_unit_id": log_unit_id } });
+        let sort = json!([{ "timestamp": "asc" }, { "message_id": "asc" }]);
+        let documents: Vec<EntryDocument> = cluster.search_all(&cluster.entries_pattern(), query, sort).await?;
+        Ok(documents.into_iter().map(LogEntry::from).collect())
+    }
+
+    async fn get_log_unit(&self, log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        let cluster = self.cluster();
+        let path = format!("{}/_doc/{}", cluster.units_index(), log_unit_id);
+        let (status, text) = cluster.call(Method::GET, &path, None).await?;
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(format!("HTTP {} from {}: {}", status, path, error_reason(&text)).into());
+        }
+        let mut json: Value = serde_json::from_str(&text)?;
+        Ok(Some(serde_json::from_value(json["_source"].take())?))
+    }
+
+    async fn get_log_units_by_external_id(&self, external_id: &str) -> LogResult<Vec<LogUnit>> {
+        self.cluster().units(json!({ "term": { "external_id": external_id } })).await
+    }
+
+    async fn list_log_units(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> LogResult<Vec<LogUnit>> {
+        let mut range = serde_json::Map::new();
+        if let Some(from) = from {
+            range.insert("gte".to_string(), from.to_rfc3339_opts(SecondsFormat::Nanos, true).into());
+        }
+        if let Some(to) = to {
+            range.insert("lt".to_string(), to.to_rfc3339_opts(SecondsFormat::Nanos, true).into());
+        }
+        self.cluster().units(json!({ "range": { "timestamp": range } })).await
+    }
+
+    async fn search_entries(&self, text: &str, options: &SearchOptions) -> LogResult<Vec<SearchHit>> {
+        let terms = search::tokenize(text);
+        if terms.is_empty() || options.limit == 0 {
+            return Ok(Vec::new());
+        }
+        let cluster = self.cluster();
+        let Some(filters) = cluster.entry_filters(options).await? else {
+            return Ok(Vec::new());
+        };
+
+        let body = json!({
+            "query": {
+                "bool": {
+                    "must": { "match": { "message": { "query": terms.join(" "), "operator": "and" } } },
+                    "filter": filters
+                }
+            },
+            "sort": ["_score", { "timestamp": "desc" }],
+            "track_scores": true,
+            "size": options.limit
+        });
+        let mut hits = Vec::new();
+        for mut hit in cluster.search(&cluster.entries_pattern(), &body).await? {
+            let score = hit["_score"].as_f64().unwrap_or_default() as f32;
+            let document: EntryDocument = serde_json::from_value(hit["_source"].take())?;
+            hits.push((LogEntry::from(document), score));
+        }
+        if hits.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<Uuid> = hits.iter().map(|(entry, _)| entry.log_unit_id).collect();
+        let mut response = cluster
+            .call_ok(Method::POST, &format!("{}/_mget", cluster.units_index()), Some(&json!({ "ids": ids })))
+            .await?;
+        let mut units = Vec::new();
+        if let Value::Array(docs) = response["docs"].take() {
+            for mut doc in docs.into_iter().filter(|doc| doc["found"] == true) {
+                units.push(serde_json::from_value::<LogUnit>(doc["_source"].take())?);
+            }
+        }
+
+        let terms = terms.into_iter().collect();
+        Ok(hits
+            .into_iter()
+            .map(|(entry, score)| SearchHit {
+                log_unit: units.iter().find(|unit| unit.log_unit_id == entry.log_unit_id).cloned(),
+                snippet: search::snippet(&entry.message, &terms, options),
+                entry,
+                score,
+            })
+            .collect())
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        self.batcher.flush().await
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        self.batcher.shutdown(timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::destinations::test_server::{fast_retries, MockServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn bulk_response(statuses: &[u16]) -> String {
+        let items: Vec<Value> = statuses
+            .iter()
+            .map(|&status| match status {
+                201 => json!({ "index": { "status": 201 } }),
+                _ => json!({ "index": { "status": status, "error": { "reason": format!("rejected with {}", status) } } }),
+            })
+            .collect();
+        json!({ "errors": statuses.iter().any(|&status| status != 201), "items": items }).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_bulk_indexing_retries_rejected_entries() {
+        let bulks = AtomicUsize::new(0);
+        let server = MockServer::start(move |request| match (request.method.as_str(), request.path.as_str()) {
+            ("PUT", "/logs-units") => (400, r#"{"error":{"type":"resource_already_exists_exception","reason":"exists"}}"#.to_string()),
+            ("POST", "/_bulk") => match bulks.fetch_add(1, Ordering::SeqCst) {
+                0 => (200, bulk_response(&[201, 429])),
+                1 => (200, bulk_response(&[201])),
+                _ => (200, bulk_response(&[400])),
+            },
+            _ => (200, r#"{"acknowledged":true}"#.to_string()),
+        })
+        .await;
+        let destination = ElasticDestination::new(ElasticConfig {
+            url: server.url.clone(),
+            index_prefix: "logs".to_string(),
+            flush_interval: Duration::from_secs(3600),
+            retry: fast_retries(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let unit = destination.create_log_unit("job-1".to_string()).await.unwrap();
+        let first = LogEntry::error(unit.log_unit_id, "first".to_string());
+        let second = LogEntry::info(unit.log_unit_id, "second".to_string());
+        destination.log(first.clone()).await.unwrap();
+        destination.log(second.clone()).await.unwrap();
+        destination.flush().await.unwrap();
+
+        destination.log(LogEntry::info(unit.log_unit_id, "third".to_string())).await.unwrap();
+        let error = destination.flush().await.unwrap_err();
+        assert!(error.to_string().contains("rejected with 400"));
+
+        let requests = server.requests.lock().await;
+        assert_eq!(requests[0].path, "/_index_template/logs-entries");
+        let template: Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(template["index_patterns"][0], "logs-entries-*");
+        assert_eq!(template["template"]["mappings"]["properties"]["level"]["type"], "byte");
+        assert_eq!(requests[2].path, format!("/logs-units/_doc/{}", unit.log_unit_id));
+
+        // The first bulk request holds both entries, the retry only the rejected one
+        let lines: Vec<Value> = String::from_utf8_lossy(&requests[3].body).lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 4);
+        let index = format!("logs-entries-{}", first.timestamp.format("%Y.%m.%d"));
+        assert_eq!(lines[0]["index"]["_index"], index.as_str());
+        assert_eq!(lines[0]["index"]["_id"], first.message_id.to_string());
+        assert_eq!(lines[1]["level"], 0);
+        assert_eq!(lines[1]["message_type"], "Error");
+        let retried: Vec<Value> = String::from_utf8_lossy(&requests[4].body).lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(retried.len(), 2);
+        assert_eq!(retried[0]["index"]["_id"], second.message_id.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_reads_use_search_queries() {
+        let unit = LogUnit::new("job-1".to_string());
+        let entry = LogEntry::warning(unit.log_unit_id, "payment 8812 declined".to_string())
+            .with_location(SourceLocation::new("src/pay.rs", 7, 1, "app::pay"));
+        let hit = json!({ "_score": 1.5, "sort": [1, "a"], "_source": EntryDocument::from(entry.clone()) });
+        let search = json!({ "hits": { "hits": [hit] } }).to_string();
+        let units = json!({ "hits": { "hits": [{ "sort": [1, "a"], "_source": unit }] } }).to_string();
+        let doc = json!({ "found": true, "_source": unit }).to_string();
+        let mget = json!({ "docs": [{ "found": true, "_source": unit }] }).to_string();
+        let unit_path = format!("/ironscribe-units/_doc/{}", unit.log_unit_id);
+
+        let server = MockServer::start(move |request| {
+            let path = request.path.split('?').next().unwrap_or_default();
+            match (request.method.as_str(), path) {
+                ("POST", "/ironscribe-entries-*/_search") => (200, search.clone()),
+                ("POST", "/ironscribe-units/_search") => (200, units.clone()),
+                ("POST", "/ironscribe-units/_mget") => (200, mget.clone()),
+                ("GET", path) if path == unit_path => (200, doc.clone()),
+                ("GET", _) => (404, r#"{"found":false}"#.to_string()),
+                _ => (200, r#"{"acknowledged":true}"#.to_string()),
+            }
+        })
+        .await;
+        let destination = ElasticDestination::new(ElasticConfig { url: server.url.clone(), ..Default::default() }).await.unwrap();
+
+        assert_eq!(destination.get_log_entries(unit.log_unit_id).await.unwrap(), vec![entry.clone()]);
+        assert_eq!(destination.get_log_unit(unit.log_unit_id).await.unwrap(), Some(unit.clone()));
+        assert_eq!(destination.get_log_unit(Uuid::new_v4()).await.unwrap(), None);
+        assert_eq!(destination.get_log_units_by_external_id("job-1").await.unwrap(), vec![unit.clone()]);
+
+        let options = SearchOptions {
+            min_level: Some(LogLevel::Warning),
+            external_id: Some("job-1".to_string()),
+            module_path: Some("app".to_string()),
+            ..Default::default()
+        };
+        let hits = destination.search_entries("8812 payment", &options).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entry, entry);
+        assert_eq!(hits[0].log_unit, Some(unit.clone()));
+        assert_eq!(hits[0].score, 1.5);
+
+        let requests = server.requests.lock().await;
+        let query: Value = serde_json::from_slice(&requests[2].body).unwrap();
+        assert_eq!(query["query"]["term"]["log_unit_id"], unit.log_unit_id.to_string());
+        let search: Value = serde_json::from_slice(&requests.iter().rev().nth(1).unwrap().body).unwrap();
+        let filters = &search["query"]["bool"]["filter"];
+        assert_eq!(filters[0]["range"]["level"]["lte"], 1);
+        assert_eq!(filters[2]["terms"]["log_unit_id"][0], unit.log_unit_id.to_string());
+        assert_eq!(search["query"]["bool"]["must"]["match"]["message"]["operator"], "and");
+    }
+}
@@ -0,0 +1,435 @@
+//! HTTP destination posting batches of entries to a webhook or ingestion endpoint
+
+use async_compression::tokio::write::GzipEncoder;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::core::{LogEntry, LogService, LogUnit};
+use crate::core::log_service::{unsupported, LogResult};
+use crate::destinations::batch::{BatchLimits, BatchSink, Batcher};
+use crate::destinations::retry::{Failure, RetryPolicy};
+use crate::destinations::unit_cache::ExternalIdCache;
+
+/// How a batch of entries is serialized into a request body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpBodyFormat {
+    /// A JSON array of entries
+    JsonArray,
+    /// One JSON entry per line
+    Ndjson,
+    /// A body rendered from templates, see `HttpTemplate`
+    Template(HttpTemplate),
+}
+
+/// Templates rendering a batch, e.g. for Loki's push API:
+///
+/// ```
+/// # use ironscribe::HttpTemplate;
+/// let loki = HttpTemplate {
+///     body: r#"{"streams":[{"stream":{"app":"billing"},"values":[{entries}]}]}"#.to_string(),
+///     entry: r#"["{timestamp_ns}","{message}"]"#.to_string(),
+///     separator: ",".to_string(),
+///     content_type: "application/json".to_string(),
+/// };
+/// ```
+///
+/// `entry` is rendered once per entry, replacing `{entry}` (the entry as a
+/// JSON object), `{log_unit_id}`, `{external_id}`, `{message_id}`, `{level}`,
+/// `{message}`, `{timestamp}` (RFC 3339) and `{timestamp_ns}`. Values are
+/// JSON-escaped so they can be placed inside JSON strings. The rendered entries,
+/// joined by `separator`, replace `{entries}` in `body`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpTemplate {
+    pub body: String,
+    pub entry: String,
+    pub separator: String,
+    pub content_type: String,
+}
+
+/// Credentials sent with every request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpAuth {
+    Bearer(String),
+    Basic { username: String, password: Option<String> },
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    pub url: String,
+    /// Headers sent with every request, e.g. `("X-Scope-OrgID", "tenant-1")`
+    pub headers: Vec<(String, String)>,
+    pub auth: Option<HttpAuth>,
+    pub format: HttpBodyFormat,
+    /// Compresses request bodies with gzip
+    pub gzip: bool,
+    /// Sends a batch once it holds this many entries
+    pub max_batch_entries: usize,
+    /// Sends a batch once its serialized entries take this many bytes
+    pub max_batch_bytes: usize,
+    /// Sends the pending entries at least this often
+    pub flush_interval: Duration,
+    /// Timeout of a single request
+    pub request_timeout: Duration,
+    /// Retries of requests failing with a connection error, a 5xx or a 429.
+    ///
+    /// A `Retry-After` header replaces the backoff, up to `max_backoff`.
+    pub retry: RetryPolicy,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:8080/logs".to_string(),
+            headers: Vec::new(),
+            auth: None,
+            format: HttpBodyFormat::JsonArray,
+            gzip: false,
+            max_batch_entries: 500,
+            max_batch_bytes: 1024 * 1024,
+            flush_interval: Duration::from_secs(1),
+            request_timeout: Duration::from_secs(10),
+            retry: RetryPolicy {
+                max_retries: 5,
+                initial_backoff: Duration::from_millis(500),
+                max_backoff: Duration::from_secs(30),
+                jitter: 0.5,
+            },
+        }
+    }
+}
+
+/// Posts batches of rendered entries
+struct Sender {
+    config: HttpConfig,
+    client: Client,
+}
+
+#[async_trait]
+impl BatchSink for Sender {
+    type Item = String;
+
+    fn size(entry: &String) -> usize {
+        entry.len()
+    }
+
+    async fn send(&self, entries: Vec<String>) -> LogResult<()> {
+        let count = entries.len();
+        self.post(self.body(entries).await?)
+            .await
+            .map_err(|e| format!("Failed to send {} log entries to {}: {}", count, self.config.url, e).into())
+    }
+}
+
+impl Sender {
+    async fn body(&self, entries: Vec<String>) -> LogResult<Vec<u8>> {
+        let body = match &self.config.format {
+            HttpBodyFormat::JsonArray => format!("[{}]", entries.join(",")),
+            HttpBodyFormat::Ndjson => entries.iter().map(|entry| format!("{}\n", entry)).collect(),
+            HttpBodyFormat::Template(template) => template.body.replace("{entries}", &entries.join(&template.separator)),
+        };
+        if !self.config.gzip {
+            return Ok(body.into_bytes());
+        }
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(body.as_bytes()).await?;
+        encoder.shutdown().await?;
+        Ok(encoder.into_inner())
+    }
+
+    /// Posts `body`, retrying transient failures
+    async fn post(&self, body: Vec<u8>) -> LogResult<()> {
+        send_with_retries(&self.config.retry, || self.request(body.clone())).await?;
+        Ok(())
+    }
+
+    fn request(&self, body: Vec<u8>) -> reqwest::RequestBuilder {
+        let content_type = match &self.config.format {
+            HttpBodyFormat::JsonArray => "application/json",
+            HttpBodyFormat::Ndjson => "application/x-ndjson",
+            HttpBodyFormat::Template(template) => template.content_type.as_str(),
+        };
+        let mut request = self.client.post(&self.config.url).header(CONTENT_TYPE, content_type).body(body);
+        if self.config.gzip {
+            request = request.header(CONTENT_ENCODING, "gzip");
+        }
+        match &self.config.auth {
+            Some(auth) => auth.apply(request),
+            None => request,
+        }
+    }
+}
+
+impl HttpAuth {
+    /// Adds the credentials to `request`
+    pub(crate) fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            HttpAuth::Bearer(token) => request.bearer_auth(token),
+            HttpAuth::Basic { username, password } => request.basic_auth(username, password.as_ref()),
+        }
+    }
+}
+
+/// Sends the request built by `request` until it succeeds, retrying connection
+/// errors, 5xx and 429 responses as `policy` allows
+pub(crate) async fn send_with_retries(policy: &RetryPolicy, request: impl Fn() -> RequestBuilder) -> LogResult<Response> {
+    policy.run(|| send(request()), HttpError::failure).await
+}
+
+/// Sends `request`, failing unless the response has a success status
+pub(crate) async fn send(request: RequestBuilder) -> Result<Response, HttpError> {
+    let response = request.send().await.map_err(|e| HttpError {
+        status: None,
+        retry_after: None,
+        message: e.to_string(),
+    })?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let retry_after = retry_after(response.headers());
+    let text = response.text().await.unwrap_or_default();
+    Err(HttpError {
+        status: Some(status),
+        retry_after,
+        message: format!("HTTP {}: {}", status, text.trim()),
+    })
+}
+
+/// A request that failed, either without a response or with an unsuccessful status
+#[derive(Debug)]
+pub(crate) struct HttpError {
+    pub(crate) status: Option<StatusCode>,
+    /// Delay requested by a `Retry-After` header
+    pub(crate) retry_after: Option<Duration>,
+    pub(crate) message: String,
+}
+
+impl HttpError {
+    /// Treats connection errors, 5xx and 429 responses as transient
+    pub(crate) fn failure(&self) -> Failure {
+        match self.status {
+            None => Failure::Transient(None),
+            Some(status) if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS => Failure::Transient(self.retry_after),
+            Some(_) => Failure::Permanent,
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for HttpError {}
+
+/// Returns the delay requested by a `Retry-After` header, in seconds or as an HTTP date
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or_default())
+}
+
+/// Renders `entry` the way it is placed in a batch
+fn render_entry(format: &HttpBodyFormat, entry: &LogEntry, external_id: Option<&str>) -> LogResult<String> {
+    let mut json = serde_json::to_value(entry)?;
+    if let (Some(external_id), Some(object)) = (external_id, json.as_object_mut()) {
+        object.insert("external_id".to_string(), external_id.into());
+    }
+    let HttpBodyFormat::Template(template) = format else {
+        return Ok(json.to_string());
+    };
+
+    let escape = |value: &str| {
+        let quoted = serde_json::Value::from(value).to_string();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    let timestamp_ns = entry.timestamp.timestamp_nanos_opt().unwrap_or_default();
+    let level = format!("{:?}", entry.level).to_lowercase();
+    Ok(template
+        .entry
+        .replace("{entry}", &json.to_string())
+        .replace("{log_unit_id}", &entry.log_unit_id.to_string())
+        .replace("{external_id}", &escape(external_id.unwrap_or_default()))
+        .replace("{message_id}", &entry.message_id.to_string())
+        .replace("{level}", &level)
+        .replace("{timestamp_ns}", &timestamp_ns.to_string())
+        .replace("{timestamp}", &entry.timestamp.to_rfc3339())
+        // Last, so placeholders within the message are left alone
+        .replace("{message}", &escape(&entry.message)))
+}
+
+/// Write-only destination posting batches of entries to an HTTP endpoint.
+///
+/// A batch is sent once it is full, when `flush_interval` has passed and on
+/// `flush`. Logging an entry that fills the batch waits for it to be sent.
+/// A batch still failing after the retries of `retry` is dropped: the error
+/// goes to the call that filled it, which may hold other callers' entries, or
+/// to stderr when the timer sent it. Reads are not supported.
+pub struct HttpDestination {
+    batcher: Batcher<Sender>,
+    external_ids: ExternalIdCache,
+}
+
+impl HttpDestination {
+    /// Creates an HTTP destination; nothing is sent until the first batch
+    pub fn new(config: HttpConfig) -> LogResult<Self> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            headers.insert(HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(value)?);
+        }
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(config.request_timeout)
+            .build()?;
+
+        let limits = BatchLimits {
+            max_items: config.max_batch_entries,
+            max_bytes: config.max_batch_bytes,
+            interval: config.flush_interval,
+        };
+        Ok(Self {
+            batcher: Batcher::new(Sender { config, client }, limits),
+            external_ids: ExternalIdCache::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl LogService for HttpDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        self.external_ids.insert(log_unit.log_unit_id, log_unit.external_id).await;
+        Ok(())
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        let external_id = self.external_ids.get(entry.log_unit_id).await;
+        let rendered = render_entry(&self.batcher.sink().config.format, &entry, external_id.as_deref())?;
+        self.batcher.push(rendered).await
+    }
+
+    async fn get_log_entries(&self, _log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        unsupported("get_log_entries")
+    }
+
+    async fn get_log_unit(&self, _log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        unsupported("get_log_unit")
+    }
+
+    async fn get_log_units_by_external_id(&self, _external_id: &str) -> LogResult<Vec<LogUnit>> {
+        unsupported("get_log_units_by_external_id")
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        self.batcher.flush().await
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        self.batcher.shutdown(timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::destinations::test_server::{fast_retries, MockServer};
+    use async_compression::tokio::bufread::GzipDecoder;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+    use tokio::io::AsyncReadExt;
+
+    /// Answers with the queued statuses, then 200
+    async fn start_server(statuses: Vec<u16>) -> MockServer {
+        let statuses = Mutex::new(VecDeque::from(statuses));
+        MockServer::start(move |_| (statuses.lock().unwrap().pop_front().unwrap_or(200), String::new())).await
+    }
+
+    #[tokio::test]
+    async fn test_batches_by_size_and_time() {
+        let server = start_server(Vec::new()).await;
+        let destination = HttpDestination::new(HttpConfig {
+            url: format!("{}/logs", server.url),
+            headers: vec![("X-Tenant".to_string(), "billing".to_string())],
+            auth: Some(HttpAuth::Bearer("secret".to_string())),
+            format: HttpBodyFormat::Ndjson,
+            gzip: true,
+            max_batch_entries: 2,
+            flush_interval: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .unwrap();
+        let unit = destination.create_log_unit("job-1".to_string()).await.unwrap();
+        for i in 0..3 {
+            destination.log(LogEntry::info(unit.log_unit_id, format!("entry {}", i))).await.unwrap();
+        }
+
+        // The third entry is sent by the ticker
+        for _ in 0..100 {
+            if server.requests.lock().await.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let requests = server.requests.lock().await;
+        assert_eq!(requests.len(), 2);
+        assert_eq!((requests[0].method.as_str(), requests[0].path.as_str()), ("POST", "/logs"));
+        assert_eq!(requests[0].header("authorization"), Some("Bearer secret"));
+        assert_eq!(requests[0].header("x-tenant"), Some("billing"));
+        assert_eq!(requests[0].header("content-encoding"), Some("gzip"));
+
+        let mut body = String::new();
+        GzipDecoder::new(&requests[0].body[..]).read_to_string(&mut body).await.unwrap();
+        let lines: Vec<serde_json::Value> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["message"], "entry 1");
+        assert_eq!(lines[1]["external_id"], "job-1");
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures_only() {
+        let server = start_server(vec![503, 429, 200, 400]).await;
+        let destination = HttpDestination::new(HttpConfig {
+            url: format!("{}/logs", server.url),
+            // Batches are only sent by `flush`
+            flush_interval: Duration::from_secs(3600),
+            retry: fast_retries(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        destination.log(LogEntry::info(Uuid::new_v4(), "retried".to_string())).await.unwrap();
+        destination.flush().await.unwrap();
+        assert_eq!(server.requests.lock().await.len(), 3);
+
+        destination.log(LogEntry::info(Uuid::new_v4(), "rejected".to_string())).await.unwrap();
+        let error = destination.flush().await.unwrap_err();
+        assert!(error.to_string().contains("400"));
+        assert_eq!(server.requests.lock().await.len(), 4);
+    }
+
+    #[test]
+    fn test_template_rendering() {
+        let template = HttpTemplate {
+            body: "{entries}".to_string(),
+            entry: r#"["{timestamp_ns}","{level} {external_id}: {message}"]"#.to_string(),
+            separator: ",".to_string(),
+            content_type: "application/json".to_string(),
+        };
+        let entry = LogEntry::warning(Uuid::new_v4(), "disk \"full\" {level}".to_string());
+
+        let rendered = render_entry(&HttpBodyFormat::Template(template), &entry, Some("job-1")).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(value[0], entry.timestamp.timestamp_nanos_opt().unwrap().to_string());
+        assert_eq!(value[1], "warning job-1: disk \"full\" {level}");
+    }
+}
@@ -0,0 +1,354 @@
+//! systemd journal destination using the journal's native socket protocol
+
+use async_trait::async_trait;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::io::Interest;
+use tokio::net::UnixDatagram;
+use uuid::Uuid;
+
+use crate::core::log_service::{unsupported, LogResult};
+use crate::core::{LogEntry, LogMessageType, LogService, LogUnit};
+use crate::destinations::unit_cache::ExternalIdCache;
+
+#[derive(Debug, Clone)]
+pub struct JournaldConfig {
+    /// Socket journald receives native messages on
+    pub socket_path: PathBuf,
+    /// Sent as `SYSLOG_IDENTIFIER`, which `journalctl -t` filters on
+    pub syslog_identifier: Option<String>,
+    /// Fields added to every entry, e.g. `("DEPLOYMENT", "blue")`.
+    ///
+    /// Names may only hold uppercase letters, digits and underscores and must start with a letter.
+    pub fields: Vec<(String, String)>,
+}
+
+impl Default for JournaldConfig {
+    fn default() -> Self {
+        Self {
+            socket_path: PathBuf::from("/run/systemd/journal/socket"),
+            syslog_identifier: Some("ironscribe".to_string()),
+            fields: Vec::new(),
+        }
+    }
+}
+
+/// Write-only destination sending every entry to the systemd journal.
+///
+/// Entries carry `MESSAGE`, `PRIORITY` (error 3, warning 4, success 5, info 6)
+/// and `IRONSCRIBE_*` fields with their IDs, location, error chain and trace,
+/// so `journalctl IRONSCRIBE_UNIT_ID=...` shows a unit. Entries too large for
+/// a datagram are written to a sealed memfd whose descriptor is sent instead.
+/// Reads are not supported.
+pub struct JournaldDestination {
+    config: JournaldConfig,
+    socket: UnixDatagram,
+    closed: AtomicBool,
+    external_ids: ExternalIdCache,
+}
+
+impl JournaldDestination {
+    /// Creates a journald destination within a Tokio runtime; the socket is only written to once entries are logged
+    pub fn new(config: JournaldConfig) -> LogResult<Self> {
+        if let Some((name, _)) = config.fields.iter().find(|(name, _)| !valid_field_name(name)) {
+            return Err(format!("Invalid journal field name: {:?}", name).into());
+        }
+        Ok(Self {
+            config,
+            socket: UnixDatagram::unbound()?,
+            closed: AtomicBool::new(false),
+            external_ids: ExternalIdCache::default(),
+        })
+    }
+
+    /// Serializes `entry` in the native journal format
+    async fn encode(&self, entry: &LogEntry) -> Vec<u8> {
+        let mut message = Vec::new();
+        let mut field = |name: &str, value: &str| append_field(&mut message, name, value);
+        field("MESSAGE", &entry.message);
+        field("PRIORITY", priority(entry.message_type));
+        if let Some(identifier) = &self.config.syslog_identifier {
+            field("SYSLOG_IDENTIFIER", identifier);
+        }
+        field("IRONSCRIBE_UNIT_ID", &entry.log_unit_id.to_string());
+        if let Some(external_id) = self.external_ids.get(entry.log_unit_id).await {
+            field("IRONSCRIBE_EXTERNAL_ID", &external_id);
+        }
+        field("IRONSCRIBE_MESSAGE_ID", &entry.message_id.to_string());
+        field("IRONSCRIBE_TIMESTAMP", &entry.timestamp.to_rfc3339());
+        if let Some(location) = &entry.location {
+            field("CODE_FILE", &location.file);
+            field("CODE_LINE", &location.line.to_string());
+            field("IRONSCRIBE_MODULE_PATH", &location.module_path);
+        }
+        if let Some(error) = &entry.error {
+            // Repeated fields keep every cause of the chain
+            for cause in &error.chain {
+                field("IRONSCRIBE_ERROR", cause);
+            }
+            if let Some(backtrace) = &error.backtrace {
+                field("IRONSCRIBE_BACKTRACE", backtrace);
+            }
+        }
+        if let Some(trace) = &entry.trace {
+            field("IRONSCRIBE_TRACE_ID", &trace.trace_id);
+            field("IRONSCRIBE_SPAN_ID", &trace.span_id);
+        }
+        for (name, value) in &self.config.fields {
+            field(name, value);
+        }
+        message
+    }
+
+    async fn send(&self, message: &[u8]) -> LogResult<()> {
+        let path = &self.config.socket_path;
+        match self.socket.send_to(message, path).await {
+            Ok(_) => return Ok(()),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EMSGSIZE) | Some(libc::ENOBUFS)) => {}
+            Err(e) => return Err(format!("Failed to send to journald at {}: {}", path.display(), e).into()),
+        }
+
+        // Too large for a datagram: pass a sealed memfd holding the message instead
+        let memfd = sealed_memfd(message)?;
+        self.socket
+            .async_io(Interest::WRITABLE, || send_fd(self.socket.as_raw_fd(), path, memfd.as_raw_fd()))
+            .await
+            .map_err(|e| format!("Failed to send to journald at {}: {}", path.display(), e).into())
+    }
+}
+
+#[async_trait]
+impl LogService for JournaldDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        self.external_ids.insert(log_unit.log_unit_id, log_unit.external_id).await;
+        Ok(())
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err("destination is shut down".into());
+        }
+        let message = self.encode(&entry).await;
+        self.send(&message).await
+    }
+
+    async fn get_log_entries(&self, _log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        unsupported("get_log_entries")
+    }
+
+    async fn get_log_unit(&self, _log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        unsupported("get_log_unit")
+    }
+
+    async fn get_log_units_by_external_id(&self, _external_id: &str) -> LogResult<Vec<LogUnit>> {
+        unsupported("get_log_units_by_external_id")
+    }
+
+    async fn shutdown(&self, _timeout: Duration) -> LogResult<()> {
+        // Entries are sent before `log` returns, so nothing is pending
+        self.closed.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Maps an entry type to its syslog priority
+fn priority(message_type: LogMessageType) -> &'static str {
+    match message_type {
+        LogMessageType::Error => "3",
+        LogMessageType::Warning => "4",
+        LogMessageType::Success => "5",
+        LogMessageType::Info => "6",
+    }
+}
+
+/// Whether journald accepts `name` for a field sent by a client
+fn valid_field_name(name: &str) -> bool {
+    name.len() <= 64
+        && name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Appends `NAME=value\n`, or the length-prefixed form for values spanning several lines
+fn append_field(message: &mut Vec<u8>, name: &str, value: &str) {
+    message.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        message.push(b'\n');
+        message.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        message.push(b'=');
+    }
+    message.extend_from_slice(value.as_bytes());
+    message.push(b'\n');
+}
+
+/// Writes `data` to a memfd sealed against further changes, as journald requires
+fn sealed_memfd(data: &[u8]) -> io::Result<File> {
+    // SAFETY: the name is a valid C string; the returned descriptor is checked and then owned by the `File`
+    let fd = unsafe { libc::memfd_create(c"ironscribe-journal".as_ptr(), libc::MFD_ALLOW_SEALING | libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(data)?;
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+    // SAFETY: `file` owns a valid descriptor
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+/// Sends an empty datagram to `path` carrying `fd` as `SCM_RIGHTS` ancillary data
+fn send_fd(socket: RawFd, path: &Path, fd: RawFd) -> io::Result<()> {
+    // SAFETY: every pointer handed to `sendmsg` refers to a local that outlives the call,
+    // and the control buffer is sized and aligned for one `cmsghdr` holding a descriptor
+    unsafe {
+        let mut address: libc::sockaddr_un = std::mem::zeroed();
+        address.sun_family = libc::AF_UNIX as libc::sa_family_t;
+        let path = path.as_os_str().as_bytes();
+        if path.len() >= address.sun_path.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "socket path is too long"));
+        }
+        for (target, byte) in address.sun_path.iter_mut().zip(path) {
+            *target = *byte as libc::c_char;
+        }
+
+        let space = libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) as usize;
+        let mut control = vec![0u64; space.div_ceil(8)];
+        let mut header: libc::msghdr = std::mem::zeroed();
+        header.msg_name = &mut address as *mut libc::sockaddr_un as *mut libc::c_void;
+        header.msg_namelen = std::mem::size_of::<libc::sockaddr_un>() as libc::socklen_t;
+        header.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        header.msg_controllen = space as _;
+
+        let message = libc::CMSG_FIRSTHDR(&header);
+        (*message).cmsg_level = libc::SOL_SOCKET;
+        (*message).cmsg_type = libc::SCM_RIGHTS;
+        (*message).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(message) as *mut RawFd, fd);
+
+        if libc::sendmsg(socket, &header, libc::MSG_NOSIGNAL) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SourceLocation;
+    use std::os::unix::fs::FileExt;
+
+    /// Parses a native journal message into its fields
+    fn parse_fields(mut message: &[u8]) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        while let Some(end) = message.iter().position(|b| *b == b'=' || *b == b'\n') {
+            let name = String::from_utf8_lossy(&message[..end]).to_string();
+            let value = if message[end] == b'=' {
+                let line = message[end..].iter().position(|b| *b == b'\n').unwrap() + end;
+                let value = &message[end + 1..line];
+                message = &message[line + 1..];
+                value
+            } else {
+                let len = u64::from_le_bytes(message[end + 1..end + 9].try_into().unwrap()) as usize;
+                let value = &message[end + 9..end + 9 + len];
+                message = &message[end + 10 + len..];
+                value
+            };
+            fields.push((name, String::from_utf8_lossy(value).to_string()));
+        }
+        fields
+    }
+
+    fn field<'a>(fields: &'a [(String, String)], name: &str) -> Vec<&'a str> {
+        fields.iter().filter(|(n, _)| n == name).map(|(_, value)| value.as_str()).collect()
+    }
+
+    /// Receives a datagram, reading the memfd it carries if it is empty
+    fn receive(socket: &std::os::unix::net::UnixDatagram) -> Vec<u8> {
+        let mut buffer = vec![0u8; 65536];
+        let mut control = [0u64; 8];
+        unsafe {
+            let mut iov = libc::iovec { iov_base: buffer.as_mut_ptr() as *mut libc::c_void, iov_len: buffer.len() };
+            let mut header: libc::msghdr = std::mem::zeroed();
+            header.msg_iov = &mut iov;
+            header.msg_iovlen = 1;
+            header.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            header.msg_controllen = std::mem::size_of_val(&control) as _;
+            let len = libc::recvmsg(socket.as_raw_fd(), &mut header, 0);
+            assert!(len >= 0, "{}", io::Error::last_os_error());
+            if len > 0 {
+                buffer.truncate(len as usize);
+                return buffer;
+            }
+            let message = libc::CMSG_FIRSTHDR(&header);
+            assert_eq!((*message).cmsg_type, libc::SCM_RIGHTS);
+            let file = File::from_raw_fd(std::ptr::read_unaligned(libc::CMSG_DATA(message) as *const RawFd));
+            let mut data = vec![0; file.metadata().unwrap().len() as usize];
+            file.read_exact_at(&mut data, 0).unwrap();
+            data
+        }
+    }
+
+    fn journal_socket() -> (PathBuf, std::os::unix::net::UnixDatagram) {
+        let path = std::env::temp_dir().join(format!("ironscribe-journal-{}.sock", Uuid::new_v4()));
+        let socket = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        (path, socket)
+    }
+
+    #[tokio::test]
+    async fn test_sends_native_fields() {
+        let (path, socket) = journal_socket();
+        let destination = JournaldDestination::new(JournaldConfig {
+            socket_path: path.clone(),
+            fields: vec![("DEPLOYMENT".to_string(), "blue".to_string())],
+            ..Default::default()
+        })
+        .unwrap();
+        let unit = destination.create_log_unit("job-1".to_string()).await.unwrap();
+        let entry = LogEntry::warning(unit.log_unit_id, "disk almost full\nat 95%".to_string())
+            .with_location(SourceLocation::new("src/disk.rs", 42, 9, "app::disk"));
+        destination.log(entry.clone()).await.unwrap();
+
+        let fields = parse_fields(&tokio::task::spawn_blocking(move || receive(&socket)).await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(field(&fields, "MESSAGE"), ["disk almost full\nat 95%"]);
+        assert_eq!(field(&fields, "PRIORITY"), ["4"]);
+        assert_eq!(field(&fields, "SYSLOG_IDENTIFIER"), ["ironscribe"]);
+        assert_eq!(field(&fields, "IRONSCRIBE_UNIT_ID"), [unit.log_unit_id.to_string()]);
+        assert_eq!(field(&fields, "IRONSCRIBE_EXTERNAL_ID"), ["job-1"]);
+        assert_eq!(field(&fields, "CODE_LINE"), ["42"]);
+        assert_eq!(field(&fields, "DEPLOYMENT"), ["blue"]);
+
+        destination.shutdown(Duration::from_secs(1)).await.unwrap();
+        assert!(destination.log(entry).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_large_entries_are_sent_via_memfd() {
+        let (path, socket) = journal_socket();
+        let destination = JournaldDestination::new(JournaldConfig { socket_path: path.clone(), ..Default::default() }).unwrap();
+        let message = "x".repeat(4 * 1024 * 1024);
+        destination.log(LogEntry::error(Uuid::new_v4(), message.clone())).await.unwrap();
+
+        let fields = parse_fields(&tokio::task::spawn_blocking(move || receive(&socket)).await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(field(&fields, "MESSAGE"), [message.as_str()]);
+        assert_eq!(field(&fields, "PRIORITY"), ["3"]);
+    }
+
+    #[test]
+    fn test_invalid_field_names_are_rejected() {
+        for name in ["lowercase", "_TRUSTED", "1ST", ""] {
+            let config = JournaldConfig { fields: vec![(name.to_string(), "x".to_string())], ..Default::default() };
+            assert!(JournaldDestination::new(config).is_err(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Kafka publisher for `MessageBusDestination`, built on librdkafka
+
+use async_trait::async_trait;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::ClientConfig;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::core::log_service::LogResult;
+use crate::destinations::bus::{BusMessage, MessagePublisher};
+
+/// How long publishing waits before enqueueing again when librdkafka's queue is full
+const QUEUE_FULL_BACKOFF: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone)]
+pub struct KafkaConfig {
+    /// Comma-separated `host:port` list of bootstrap brokers
+    pub brokers: String,
+    pub client_id: String,
+    /// How long a message may take to be acknowledged, retries included
+    pub delivery_timeout: Duration,
+    /// Publishing waits for a delivery once this many messages are unacknowledged
+    pub max_in_flight: usize,
+    /// Further librdkafka properties, e.g. `("security.protocol", "SASL_SSL")`
+    pub properties: Vec<(String, String)>,
+}
+
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        Self {
+            brokers: "localhost:9092".to_string(),
+            client_id: "ironscribe".to_string(),
+            delivery_timeout: Duration::from_secs(30),
+            max_in_flight: 10_000,
+            properties: Vec::new(),
+        }
+    }
+}
+
+/// Publishes messages to Kafka topics, using the message key as the record key.
+///
+/// Records with the same key go to the same partition and the producer is
+/// idempotent, so they keep their order even when sends are retried.
+/// `publish` only enqueues a record, waiting while `max_in_flight` records are
+/// unacknowledged; delivery failures are reported by `flush`.
+pub struct KafkaPublisher {
+    producer: FutureProducer,
+    /// One permit per record that may be unacknowledged
+    in_flight: Arc<Semaphore>,
+    max_in_flight: u32,
+    deliveries: Arc<Mutex<Deliveries>>,
+}
+
+/// Outcome of the records published since the last flush
+#[derive(Default)]
+struct Deliveries {
+    published: usize,
+    failed: usize,
+    first_error: Option<String>,
+}
+
+impl KafkaPublisher {
+    /// Creates the producer; brokers are only contacted once messages are published
+    pub fn new(config: &KafkaConfig) -> LogResult<Self> {
+        let mut client = ClientConfig::new();
+        client
+            .set("bootstrap.servers", &config.brokers)
+            .set("client.id", &config.client_id)
+            .set("enable.idempotence", "true")
+            .set("message.timeout.ms", config.delivery_timeout.as_millis().to_string());
+        for (name, value) in &config.properties {
+            client.set(name, value);
+        }
+        let max_in_flight = u32::try_from(config.max_in_flight).unwrap_or(u32::MAX).max(1);
+        Ok(Self {
+            producer: client.create()?,
+            in_flight: Arc::new(Semaphore::new(max_in_flight as usize)),
+            max_in_flight,
+            deliveries: Arc::new(Mutex::new(Deliveries::default())),
+        })
+    }
+}
+
+#[async_trait]
+impl MessagePublisher for KafkaPublisher {
+    async fn publish(&self, message: BusMessage) -> LogResult<()> {
+        let permit = self.in_flight.clone().acquire_owned().await?;
+        let headers = OwnedHeaders::new().insert(Header { key: "content-type", value: Some(message.content_type) });
+        let mut record = FutureRecord::to(&message.topic).key(&message.key).payload(&message.payload).headers(headers);
+        let delivery = loop {
+            match self.producer.send_result(record) {
+                Ok(delivery) => break delivery,
+                // The local queue is full: librdkafka frees room as the pending records are acknowledged
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), returned)) => {
+                    record = returned;
+                    tokio::time::sleep(QUEUE_FULL_BACKOFF).await;
+                }
+                Err((e, _)) => return Err(e.into()),
+            }
+        };
+        self.deliveries.lock().unwrap().published += 1;
+
+        let deliveries = self.deliveries.clone();
+        tokio::spawn(async move {
+            let error = match delivery.await {
+                Ok(Ok(_)) => None,
+                Ok(Err((e, _))) => Some(e.to_string()),
+                Err(_) => Some("producer was dropped".to_string()),
+            };
+            if let Some(error) = error {
+                let mut deliveries = deliveries.lock().unwrap();
+                deliveries.failed += 1;
+                deliveries.first_error.get_or_insert(error);
+            }
+            drop(permit);
+        });
+        Ok(())
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        // Holding every permit means no record is in flight
+        drop(self.in_flight.acquire_many(self.max_in_flight).await?);
+        let deliveries = std::mem::take(&mut *self.deliveries.lock().unwrap());
+        match deliveries.first_error {
+            Some(error) => Err(format!(
+                "Failed to deliver {} of {} messages to Kafka: {}",
+                deliveries.failed, deliveries.published, error
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_properties_are_rejected() {
+        let config = KafkaConfig {
+            properties: vec![("no.such.property".to_string(), "1".to_string())],
+            ..Default::default()
+        };
+        assert!(KafkaPublisher::new(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_undelivered_messages_fail_flush() {
+        let publisher = KafkaPublisher::new(&KafkaConfig {
+            brokers: "127.0.0.1:1".to_string(),
+            delivery_timeout: Duration::from_millis(200),
+            ..Default::default()
+        })
+        .unwrap();
+        let message = BusMessage {
+            topic: "entries".to_string(),
+            key: "unit".to_string(),
+            payload: b"{}".to_vec(),
+            content_type: "application/json",
+        };
+        publisher.publish(message).await.unwrap();
+        let error = publisher.flush().await.unwrap_err();
+        assert!(error.to_string().contains("Failed to deliver 1 of 1 messages"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_publish_waits_for_room_without_reporting_other_deliveries() {
+        let publisher = KafkaPublisher::new(&KafkaConfig {
+            brokers: "127.0.0.1:1".to_string(),
+            delivery_timeout: Duration::from_millis(200),
+            max_in_flight: 1,
+            ..Default::default()
+        })
+        .unwrap();
+        let message = || BusMessage {
+            topic: "entries".to_string(),
+            key: "unit".to_string(),
+            payload: b"{}".to_vec(),
+            content_type: "application/json",
+        };
+
+        // The second publish waits for the first delivery to fail, but does not report it
+        publisher.publish(message()).await.unwrap();
+        publisher.publish(message()).await.unwrap();
+        let error = publisher.flush().await.unwrap_err();
+        assert!(error.to_string().contains("Failed to deliver 2 of 2 messages"), "{}", error);
+        publisher.flush().await.unwrap();
+    }
+}
@@ -0,0 +1,584 @@
+//! Grafana Loki destination pushing entries through the Loki push API
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use prost::Message;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::{Client, Method, RequestBuilder};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::core::log_service::{unsupported, LogResult};
+use crate::core::{LogEntry, LogMessageType, LogService, LogUnit, SourceLocation, TraceContext};
+use crate::destinations::batch::{BatchLimits, BatchSink, Batcher};
+use crate::destinations::http::{send_with_retries, HttpAuth};
+use crate::destinations::retry::RetryPolicy;
+use crate::destinations::unit_cache::ExternalIdCache;
+
+/// Number of entries fetched per `query_range` request
+const QUERY_LIMIT: usize = 5000;
+
+/// How pushed batches are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LokiEncoding {
+    /// JSON, as accepted by `/loki/api/v1/push` with `application/json`
+    Json,
+    /// Snappy-compressed protobuf, Loki's native and most compact format
+    Protobuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct LokiConfig {
+    /// Base URL of Loki, e.g. `http://localhost:3100`
+    pub url: String,
+    /// Tenant sent as `X-Scope-OrgID` to a multi-tenant Loki
+    pub tenant_id: Option<String>,
+    pub auth: Option<HttpAuth>,
+    pub encoding: LokiEncoding,
+    /// Labels added to every stream, e.g. `("app", "billing")`
+    pub labels: Vec<(String, String)>,
+    /// Label holding the external ID of an entry's unit; `None` leaves it out
+    pub external_id_label: Option<String>,
+    /// Label holding the message type (`error`, `warning`, `info`, `success`).
+    ///
+    /// With `None` the message type is kept in structured metadata instead.
+    pub message_type_label: Option<String>,
+    /// Sends a batch once it holds this many entries
+    pub max_batch_entries: usize,
+    /// Sends a batch once its lines and metadata take this many bytes
+    pub max_batch_bytes: usize,
+    /// Sends the pending entries at least this often
+    pub flush_interval: Duration,
+    /// Timeout of a single request
+    pub request_timeout: Duration,
+    /// How far back `get_log_entries` looks; Loki rejects queries longer than its `max_query_length`
+    pub query_lookback: Duration,
+    /// Retries of pushes failing with a connection error, a 5xx or a 429
+    pub retry: RetryPolicy,
+}
+
+impl Default for LokiConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:3100".to_string(),
+            tenant_id: None,
+            auth: None,
+            encoding: LokiEncoding::Protobuf,
+            labels: Vec::new(),
+            external_id_label: Some("external_id".to_string()),
+            message_type_label: Some("level".to_string()),
+            max_batch_entries: 1000,
+            max_batch_bytes: 1024 * 1024,
+            flush_interval: Duration::from_secs(1),
+            request_timeout: Duration::from_secs(10),
+            query_lookback: Duration::from_secs(30 * 24 * 60 * 60),
+            retry: RetryPolicy {
+                max_retries: 5,
+                initial_backoff: Duration::from_millis(500),
+                max_backoff: Duration::from_secs(30),
+                jitter: 0.5,
+            },
+        }
+    }
+}
+
+/// `logproto.PushRequest`
+#[derive(Clone, PartialEq, Message)]
+struct PushRequest {
+    #[prost(message, repeated, tag = "1")]
+    streams: Vec<StreamAdapter>,
+}
+
+/// `logproto.StreamAdapter`
+#[derive(Clone, PartialEq, Message)]
+struct StreamAdapter {
+    /// Labels in selector syntax, e.g. `{app="billing"}`
+    #[prost(string, tag = "1")]
+    labels: String,
+    #[prost(message, repeated, tag = "2")]
+    entries: Vec<EntryAdapter>,
+}
+
+/// `logproto.EntryAdapter`
+#[derive(Clone, PartialEq, Message)]
+struct EntryAdapter {
+    #[prost(message, optional, tag = "1")]
+    timestamp: Option<Timestamp>,
+    #[prost(string, tag = "2")]
+    line: String,
+    #[prost(message, repeated, tag = "3")]
+    structured_metadata: Vec<LabelPairAdapter>,
+}
+
+/// `google.protobuf.Timestamp`
+#[derive(Clone, PartialEq, Message)]
+struct Timestamp {
+    #[prost(int64, tag = "1")]
+    seconds: i64,
+    #[prost(int32, tag = "2")]
+    nanos: i32,
+}
+
+/// `logproto.LabelPairAdapter`
+#[derive(Clone, PartialEq, Message)]
+struct LabelPairAdapter {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+/// Name and value pairs of stream labels or structured metadata
+type Labels = Vec<(String, String)>;
+
+/// A log line waiting to be pushed
+struct Line {
+    /// Stream labels, sorted by name
+    labels: Labels,
+    timestamp: DateTime<Utc>,
+    line: String,
+    metadata: Labels,
+}
+
+/// Renders labels in selector syntax, e.g. `{app="billing",level="info"}`
+fn selector(labels: &[(String, String)]) -> String {
+    let matchers: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", matchers.join(","))
+}
+
+fn message_type_name(message_type: LogMessageType) -> &'static str {
+    match message_type {
+        LogMessageType::Error => "error",
+        LogMessageType::Warning => "warning",
+        LogMessageType::Info => "info",
+        LogMessageType::Success => "success",
+    }
+}
+
+fn parse_message_type(name: &str) -> Option<LogMessageType> {
+    match name {
+        "error" => Some(LogMessageType::Error),
+        "warning" => Some(LogMessageType::Warning),
+        "info" => Some(LogMessageType::Info),
+        "success" => Some(LogMessageType::Success),
+        _ => None,
+    }
+}
+
+/// Pushes batches of lines, grouped into one stream per label set
+struct Pusher {
+    config: LokiConfig,
+    client: Client,
+}
+
+#[async_trait]
+impl BatchSink for Pusher {
+    type Item = Line;
+
+    fn size(line: &Line) -> usize {
+        line.line.len() + line.metadata.iter().map(|(name, value)| name.len() + value.len()).sum::<usize>()
+    }
+
+    async fn send(&self, lines: Vec<Line>) -> LogResult<()> {
+        let count = lines.len();
+        let (content_type, body) = self.body(lines)?;
+        let request = || self.request(Method::POST, "loki/api/v1/push").header(CONTENT_TYPE, content_type).body(body.clone());
+        send_with_retries(&self.config.retry, request)
+            .await
+            .map_err(|e| format!("Failed to push {} log entries to {}: {}", count, self.config.url, e))?;
+        Ok(())
+    }
+}
+
+impl Pusher {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.client.request(method, format!("{}/{}", self.config.url.trim_end_matches('/'), path));
+        match &self.config.auth {
+            Some(auth) => auth.apply(request),
+            None => request,
+        }
+    }
+
+    /// Encodes `lines` as a push request, returning its content type and body
+    fn body(&self, lines: Vec<Line>) -> LogResult<(&'static str, Vec<u8>)> {
+        // Streams in the order they first appear, each with its lines in timestamp order
+        let mut streams: Vec<(Labels, Vec<Line>)> = Vec::new();
+        let mut positions = HashMap::new();
+        for line in lines {
+            let position = *positions.entry(line.labels.clone()).or_insert_with(|| {
+                streams.push((line.labels.clone(), Vec::new()));
+                streams.len() - 1
+            });
+            streams[position].1.push(line);
+        }
+        for (_, lines) in &mut streams {
+            lines.sort_by_key(|line| line.timestamp);
+        }
+
+        match self.config.encoding {
+            LokiEncoding::Json => {
+                let streams: Vec<Value> = streams
+                    .into_iter()
+                    .map(|(labels, lines)| {
+                        let values: Vec<Value> = lines
+                            .into_iter()
+                            .map(|line| {
+                                let timestamp = line.timestamp.timestamp_nanos_opt().unwrap_or_default().to_string();
+                                let metadata: serde_json::Map<String, Value> = line.metadata.into_iter().map(|(name, value)| (name, value.into())).collect();
+                                json!([timestamp, line.line, metadata])
+                            })
+                            .collect();
+                        let labels: serde_json::Map<String, Value> = labels.into_iter().map(|(name, value)| (name, value.into())).collect();
+                        json!({ "stream": labels, "values": values })
+                    })
+                    .collect();
+                Ok(("application/json", serde_json::to_vec(&json!({ "streams": streams }))?))
+            }
+            LokiEncoding::Protobuf => {
+                let request = PushRequest {
+                    streams: streams
+                        .into_iter()
+                        .map(|(labels, lines)| StreamAdapter {
+                            labels: selector(&labels),
+                            entries: lines
+                                .into_iter()
+                                .map(|line| EntryAdapter {
+                                    timestamp: Some(Timestamp {
+                                        seconds: line.timestamp.timestamp(),
+                                        nanos: line.timestamp.timestamp_subsec_nanos() as i32,
+                                    }),
+                                    line: line.line,
+                                    structured_metadata: line
+                                        .metadata
+                                        .into_iter()
+                                        .map(|(name, value)| LabelPairAdapter { name, value })
+                                        .collect(),
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                };
+                let body = snap::raw::Encoder::new().compress_vec(&request.encode_to_vec())?;
+                Ok(("application/x-protobuf", body))
+            }
+        }
+    }
+
+    /// Label or metadata name holding the message type
+    fn message_type_key(&self) -> &str {
+        self.config.message_type_label.as_deref().unwrap_or("level")
+    }
+
+    /// Turns an entry into a line, keeping per-entry IDs out of the stream labels
+    fn line(&self, entry: LogEntry, external_id: Option<String>) -> Line {
+        let mut labels = self.config.labels.clone();
+        let mut metadata = vec![
+            ("log_unit_id".to_string(), entry.log_unit_id.to_string()),
+            ("message_id".to_string(), entry.message_id.to_string()),
+        ];
+        if let (Some(label), Some(external_id)) = (&self.config.external_id_label, external_id) {
+            labels.push((label.clone(), external_id));
+        }
+        let message_type = (self.message_type_key().to_string(), message_type_name(entry.message_type).to_string());
+        match self.config.message_type_label {
+            Some(_) => labels.push(message_type),
+            None => metadata.push(message_type),
+        }
+        labels.sort();
+
+        if let Some(location) = entry.location {
+            metadata.push(("code_filepath".to_string(), location.file));
+            metadata.push(("code_lineno".to_string(), location.line.to_string()));
+            metadata.push(("code_column".to_string(), location.column.to_string()));
+            metadata.push(("code_namespace".to_string(), location.module_path));
+        }
+        if let Some(trace) = entry.trace {
+            metadata.push(("trace_id".to_string(), trace.trace_id));
+            metadata.push(("span_id".to_string(), trace.span_id));
+        }
+        Line {
+            labels,
+            timestamp: entry.timestamp,
+            line: entry.message,
+            metadata,
+        }
+    }
+
+    /// Rebuilds an entry from a line returned by `query_range`, whose labels include its structured metadata
+    fn entry(&self, labels: &serde_json::Map<String, Value>, timestamp: &str, line: &str) -> LogResult<LogEntry> {
+        let label = |name: &str| labels.get(name).and_then(Value::as_str);
+        let id = |name: &str| -> LogResult<Uuid> {
+            Ok(label(name).ok_or_else(|| format!("Loki line without {}", name))?.parse()?)
+        };
+        let message_type = label(self.message_type_key())
+            .and_then(parse_message_type)
+            .ok_or("Loki line without a message type")?;
+        let timestamp: i64 = timestamp.parse()?;
+
+        let mut entry = LogEntry::new(id("log_unit_id")?, line.to_string(), message_type);
+        entry.message_id = id("message_id")?;
+        entry.timestamp = DateTime::from_timestamp_nanos(timestamp);
+        if let (Some(file), Some(line), Some(column), Some(module_path)) =
+            (label("code_filepath"), label("code_lineno"), label("code_column"), label("code_namespace"))
+        {
+            entry.location = Some(SourceLocation::new(file, line.parse()?, column.parse()?, module_path));
+        }
+        if let (Some(trace_id), Some(span_id)) = (label("trace_id"), label("span_id")) {
+            entry.trace = Some(TraceContext { trace_id: trace_id.to_string(), span_id: span_id.to_string() });
+        }
+        Ok(entry)
+    }
+}
+
+/// Destination pushing entries to Grafana Loki.
+///
+/// Each entry becomes a line whose stream labels are the configured static
+/// labels, the unit's external ID and the message type. High-cardinality IDs,
+/// source locations and trace context go to structured metadata, so each unit
+/// can still be selected with a LogQL filter such as
+/// `{external_id="job-1"} | log_unit_id="..."`. Error details are not sent.
+///
+/// Batches are pushed once full, when `flush_interval` has passed and on
+/// `flush`. A push still failing after the retries of `retry` drops its
+/// batch, reporting the error to the call that filled it, or to stderr for
+/// timed pushes. `get_log_entries` reads entries back with `query_range`; units
+/// themselves are not stored in Loki.
+pub struct LokiDestination {
+    batcher: Batcher<Pusher>,
+    external_ids: ExternalIdCache,
+}
+
+impl LokiDestination {
+    /// Creates a Loki destination; nothing is sent until the first batch
+    pub fn new(config: LokiConfig) -> LogResult<Self> {
+        let mut headers = HeaderMap::new();
+        if let Some(tenant_id) = &config.tenant_id {
+            headers.insert("X-Scope-OrgID", HeaderValue::from_str(tenant_id)?);
+        }
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(config.request_timeout)
+            .build()?;
+
+        let limits = BatchLimits {
+            max_items: config.max_batch_entries,
+            max_bytes: config.max_batch_bytes,
+            interval: config.flush_interval,
+        };
+        Ok(Self {
+            batcher: Batcher::new(Pusher { config, client }, limits),
+            external_ids: ExternalIdCache::default(),
+        })
+    }
+
+    /// LogQL query selecting the entries of a unit
+    async fn unit_query(&self, log_unit_id: Uuid) -> LogResult<String> {
+        let config = &self.batcher.sink().config;
+        let mut matchers = config.labels.clone();
+        if let (Some(label), Some(external_id)) = (&config.external_id_label, self.external_ids.get(log_unit_id).await) {
+            matchers.push((label.clone(), external_id));
+        }
+        let stream = if !matchers.is_empty() {
+            selector(&matchers)
+        } else if let Some(label) = &config.message_type_label {
+            // Loki needs at least one matcher that does not match an empty value
+            format!("{{{}=~\".+\"}}", label)
+        } else {
+            return Err("get_log_entries needs a static label, the external ID label or the message type label to select streams".into());
+        };
+        Ok(format!("{} | log_unit_id=\"{}\"", stream, log_unit_id))
+    }
+}
+
+#[async_trait]
+impl LogService for LokiDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        self.external_ids.insert(log_unit.log_unit_id, log_unit.external_id).await;
+        Ok(())
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        let external_id = self.external_ids.get(entry.log_unit_id).await;
+        let line = self.batcher.sink().line(entry, external_id);
+        self.batcher.push(line).await
+    }
+
+    /// Queries the entries of the last `query_lookback`, paging forward through the results
+    async fn get_log_entries(&self, log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        let pusher = self.batcher.sink();
+        let query = self.unit_query(log_unit_id).await?;
+        let end = Utc::now().timestamp_nanos_opt().unwrap_or(i64::MAX);
+        let mut start = end - pusher.config.query_lookback.as_nanos().min(end as u128) as i64;
+
+        let mut entries = Vec::new();
+        let mut seen = HashSet::new();
+        loop {
+            let parameters = [
+                ("query", query.clone()),
+                ("start", start.to_string()),
+                ("end", end.to_string()),
+                ("limit", QUERY_LIMIT.to_string()),
+                ("direction", "forward".to_string()),
+            ];
+            let response = send_with_retries(&pusher.config.retry, || {
+                pusher.request(Method::GET, "loki/api/v1/query_range").query(&parameters)
+            })
+            .await
+            .map_err(|e| format!("Failed to query {}: {}", pusher.config.url, e))?;
+            let json: Value = serde_json::from_str(&response.text().await?)?;
+
+            let mut count = 0;
+            let mut last = start;
+            for stream in json["data"]["result"].as_array().map(Vec::as_slice).unwrap_or_default() {
+                let Some(labels) = stream["stream"].as_object() else { continue };
+                for value in stream["values"].as_array().map(Vec::as_slice).unwrap_or_default() {
+                    let (Some(timestamp), Some(line)) = (value[0].as_str(), value[1].as_str()) else { continue };
+                    // Categorized responses carry structured metadata separately
+                    let mut labels = labels.clone();
+                    if let Some(metadata) = value[2]["structuredMetadata"].as_object() {
+                        labels.extend(metadata.clone());
+                    }
+                    let entry = pusher.entry(&labels, timestamp, line)?;
+                    count += 1;
+                    last = last.max(timestamp.parse()?);
+                    if seen.insert(entry.message_id) {
+                        entries.push(entry);
+                    }
+                }
+            }
+            if count < QUERY_LIMIT {
+                break;
+            }
+            // Lines sharing the last timestamp are fetched again and skipped as seen
+            start = if last > start { last } else { start + 1 };
+        }
+        entries.sort_by_key(|entry| entry.timestamp);
+        Ok(entries)
+    }
+
+    async fn get_log_unit(&self, _log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        unsupported("get_log_unit")
+    }
+
+    async fn get_log_units_by_external_id(&self, _external_id: &str) -> LogResult<Vec<LogUnit>> {
+        unsupported("get_log_units_by_external_id")
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        self.batcher.flush().await
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        self.batcher.shutdown(timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::destinations::test_server::MockServer;
+
+    fn config(url: &str, encoding: LokiEncoding) -> LokiConfig {
+        LokiConfig {
+            url: url.to_string(),
+            tenant_id: Some("tenant-1".to_string()),
+            encoding,
+            labels: vec![("app".to_string(), "billing".to_string())],
+            flush_interval: Duration::from_secs(3600),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pushes_one_stream_per_label_set() {
+        let server = MockServer::start(|_| (204, String::new())).await;
+        for encoding in [LokiEncoding::Protobuf, LokiEncoding::Json] {
+            let destination = LokiDestination::new(config(&server.url, encoding)).unwrap();
+            let first = destination.create_log_unit("job-1".to_string()).await.unwrap();
+            let second = destination.create_log_unit("job-2".to_string()).await.unwrap();
+            destination.log(LogEntry::info(first.log_unit_id, "one".to_string())).await.unwrap();
+            destination.log(LogEntry::info(second.log_unit_id, "two".to_string())).await.unwrap();
+            destination.log(LogEntry::info(first.log_unit_id, "three".to_string())).await.unwrap();
+            destination.flush().await.unwrap();
+        }
+
+        let requests = server.requests.lock().await;
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].path, "/loki/api/v1/push");
+        assert_eq!(requests[0].header("x-scope-orgid"), Some("tenant-1"));
+
+        assert_eq!(requests[0].header("content-type"), Some("application/x-protobuf"));
+        let decompressed = snap::raw::Decoder::new().decompress_vec(&requests[0].body).unwrap();
+        let push = PushRequest::decode(&decompressed[..]).unwrap();
+        assert_eq!(push.streams.len(), 2);
+        assert_eq!(push.streams[0].labels, r#"{app="billing",external_id="job-1",level="info"}"#);
+        let lines: Vec<&str> = push.streams[0].entries.iter().map(|entry| entry.line.as_str()).collect();
+        assert_eq!(lines, ["one", "three"]);
+        assert_eq!(push.streams[0].entries[0].structured_metadata[0].name, "log_unit_id");
+
+        assert_eq!(requests[1].header("content-type"), Some("application/json"));
+        let push: Value = serde_json::from_slice(&requests[1].body).unwrap();
+        let stream = &push["streams"][1];
+        assert_eq!(stream["stream"], json!({ "app": "billing", "external_id": "job-2", "level": "info" }));
+        assert_eq!(stream["values"][0][1], "two");
+        assert!(stream["values"][0][2]["log_unit_id"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_get_log_entries_queries_range() {
+        let log_unit_id = Uuid::new_v4();
+        let entry = LogEntry::error(log_unit_id, "payment declined".to_string())
+            .with_location(SourceLocation::new("src/pay.rs", 7, 1, "app::pay"));
+        let response = json!({
+            "status": "success",
+            "data": {
+                "resultType": "streams",
+                "result": [{
+                    "stream": {
+                        "app": "billing",
+                        "external_id": "job-1",
+                        "level": "error",
+                        "log_unit_id": log_unit_id.to_string(),
+                        "message_id": entry.message_id.to_string(),
+                        "code_filepath": "src/pay.rs",
+                        "code_lineno": "7",
+                        "code_column": "1",
+                        "code_namespace": "app::pay"
+                    },
+                    "values": [[entry.timestamp.timestamp_nanos_opt().unwrap().to_string(), "payment declined"]]
+                }]
+            }
+        })
+        .to_string();
+        let server = MockServer::start(move |_| (200, response.clone())).await;
+        let destination = LokiDestination::new(config(&server.url, LokiEncoding::Json)).unwrap();
+        destination.insert_log_unit(LogUnit { log_unit_id, external_id: "job-1".to_string(), timestamp: Utc::now() }).await.unwrap();
+
+        assert_eq!(destination.get_log_entries(log_unit_id).await.unwrap(), vec![entry]);
+        let requests = server.requests.lock().await;
+        assert!(requests[0].path.starts_with("/loki/api/v1/query_range?query="));
+        let query = format!(r#"{{app="billing",external_id="job-1"}} | log_unit_id="{}""#, log_unit_id);
+        let encoded: String = url_encode(&query);
+        assert!(requests[0].path.contains(&encoded), "{}", requests[0].path);
+    }
+
+    /// Form-encodes `value` the way `reqwest` encodes query parameters
+    fn url_encode(value: &str) -> String {
+        value
+            .bytes()
+            .map(|byte| match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'*' => (byte as char).to_string(),
+                b' ' => "+".to_string(),
+                _ => format!("%{:02X}", byte),
+            })
+            .collect()
+    }
+}
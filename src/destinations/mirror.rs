@@ -0,0 +1,174 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::core::{ExternalIdStats, HistogramBucket, LevelCounts, LogEntry, LogLevel, LogService, LogUnit, SearchHit, SearchOptions, StatsFilter, Subscription, SubscriptionFilter, TimeBucket, UnitRanking, UnitStats};
+use crate::core::log_service::LogResult;
+use crate::destinations::retry::RetryMetrics;
+
+/// Destination that forwards every write to a primary destination and a set of mirrors.
+///
+/// Log units are created once and then inserted into every destination, so all of
+/// them agree on the `log_unit_id`. Reads are answered by the primary destination.
+pub struct MirrorDestination {
+    primary: Arc<dyn LogService>,
+    mirrors: Vec<Arc<dyn LogService>>,
+}
+
+impl MirrorDestination {
+    /// Creates a mirror destination with the given primary and no mirrors
+    pub fn new(primary: Arc<dyn LogService>) -> Self {
+        Self {
+            primary,
+            mirrors: Vec::new(),
+        }
+    }
+
+    /// Adds a destination that receives a copy of every write
+    pub fn with_mirror(mut self, mirror: Arc<dyn LogService>) -> Self {
+        self.mirrors.push(mirror);
+        self
+    }
+}
+
+#[async_trait]
+impl LogService for MirrorDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        self.primary.insert_log_unit(log_unit.clone()).await?;
+        for mirror in &self.mirrors {
+            mirror.insert_log_unit(log_unit.clone()).await?;
+        }
+        Ok(())
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        self.primary.log(entry.clone()).await?;
+        for mirror in &self.mirrors {
+            mirror.log(entry.clone()).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_log_entries(&self, log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        self.primary.get_log_entries(log_unit_id).await
+    }
+
+    async fn get_log_unit(&self, log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        self.primary.get_log_unit(log_unit_id).await
+    }
+
+    async fn get_log_units_by_external_id(&self, external_id: &str) -> LogResult<Vec<LogUnit>> {
+        self.primary.get_log_units_by_external_id(external_id).await
+    }
+
+    async fn list_log_units(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> LogResult<Vec<LogUnit>> {
+        self.primary.list_log_units(from, to).await
+    }
+
+    async fn search_entries(&self, text: &str, options: &SearchOptions) -> LogResult<Vec<SearchHit>> {
+        self.primary.search_entries(text, options).await
+    }
+
+    async fn count_by_level(&self, filter: &StatsFilter) -> LogResult<LevelCounts> {
+        self.primary.count_by_level(filter).await
+    }
+
+    async fn entry_histogram(&self, filter: &StatsFilter, bucket: TimeBucket) -> LogResult<Vec<HistogramBucket>> {
+        self.primary.entry_histogram(filter, bucket).await
+    }
+
+    async fn top_units(&self, filter: &StatsFilter, ranking: UnitRanking, limit: usize) -> LogResult<Vec<UnitStats>> {
+        self.primary.top_units(filter, ranking, limit).await
+    }
+
+    async fn counts_by_external_id(&self, filter: &StatsFilter) -> LogResult<Vec<ExternalIdStats>> {
+        self.primary.counts_by_external_id(filter).await
+    }
+
+    async fn subscribe(&self, filter: SubscriptionFilter) -> LogResult<Subscription> {
+        self.primary.subscribe(filter).await
+    }
+
+    async fn delete_log_unit(&self, log_unit_id: Uuid) -> LogResult<bool> {
+        let deleted = self.primary.delete_log_unit(log_unit_id).await?;
+        for mirror in &self.mirrors {
+            mirror.delete_log_unit(log_unit_id).await?;
+        }
+        Ok(deleted)
+    }
+
+    async fn purge_entries(&self, before: DateTime<Utc>, level: Option<LogLevel>) -> LogResult<u64> {
+        let removed = self.primary.purge_entries(before, level).await?;
+        for mirror in &self.mirrors {
+            mirror.purge_entries(before, level).await?;
+        }
+        Ok(removed)
+    }
+
+    async fn purge_empty_log_units(&self, before: DateTime<Utc>) -> LogResult<u64> {
+        let removed = self.primary.purge_empty_log_units(before).await?;
+        for mirror in &self.mirrors {
+            mirror.purge_empty_log_units(before).await?;
+        }
+        Ok(removed)
+    }
+
+    async fn trim_log_units(&self, max_units: usize) -> LogResult<u64> {
+        let removed = self.primary.trim_log_units(max_units).await?;
+        for mirror in &self.mirrors {
+            mirror.trim_log_units(max_units).await?;
+        }
+        Ok(removed)
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        self.primary.flush().await?;
+        for mirror in &self.mirrors {
+            mirror.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Shuts every destination down, even if an earlier one fails, sharing `timeout` between them
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        let deadline = Instant::now() + timeout;
+        let mut result = self.primary.shutdown(timeout).await;
+        for mirror in &self.mirrors {
+            let shut_down = mirror.shutdown(deadline.saturating_duration_since(Instant::now())).await;
+            result = result.and(shut_down);
+        }
+        result
+    }
+
+    /// Returns the metrics of the primary destination
+    fn retry_metrics(&self) -> Option<RetryMetrics> {
+        self.primary.retry_metrics()
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "console")]
+mod tests {
+    use super::*;
+    use crate::destinations::ConsoleDestination;
+
+    #[tokio::test]
+    async fn test_mirror_preserves_unit_identity() {
+        let primary = Arc::new(ConsoleDestination::new());
+        let mirror = Arc::new(ConsoleDestination::new());
+        let destination = MirrorDestination::new(primary.clone()).with_mirror(mirror.clone());
+
+        let unit = destination.create_log_unit("mirrored".to_string()).await.unwrap();
+        destination
+            .log(LogEntry::info(unit.log_unit_id, "Mirrored message".to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(mirror.get_log_unit(unit.log_unit_id).await.unwrap(), Some(unit.clone()));
+        assert_eq!(primary.get_log_entries(unit.log_unit_id).await.unwrap().len(), 1);
+        assert_eq!(mirror.get_log_entries(unit.log_unit_id).await.unwrap().len(), 1);
+    }
+}
@@ -1,20 +1,107 @@
 //! Pluggable destination implementations for different log targets
 
+#[cfg(feature = "bus")]
+pub mod bus;
+
+#[cfg(feature = "clickhouse")]
+pub mod clickhouse;
+
 #[cfg(feature = "console")]
 pub mod console;
 
+#[cfg(feature = "elastic")]
+pub mod elastic;
+
+#[cfg(feature = "http")]
+pub mod http;
+
+pub mod mirror;
+pub mod retry;
+
+#[cfg(all(feature = "journald", target_os = "linux"))]
+pub mod journald;
+
+#[cfg(feature = "kafka")]
+pub mod kafka;
+
+#[cfg(feature = "loki")]
+pub mod loki;
+
 #[cfg(feature = "mongo")]
 pub mod mongodb;
 
+#[cfg(feature = "nats")]
+pub mod nats;
+
+#[cfg(feature = "otlp")]
+pub mod otlp;
+
 #[cfg(feature = "postgres")]
 pub mod postgres;
 
+#[cfg(feature = "redis-streams")]
+pub mod redis_streams;
+
+#[cfg(feature = "spool")]
+pub mod spool;
+
+#[cfg(feature = "syslog")]
+pub mod syslog;
+
+#[cfg(feature = "http")]
+mod batch;
+
+#[cfg(all(test, feature = "console"))]
+mod test_destination;
+
+#[cfg(all(test, feature = "http"))]
+mod test_server;
+
+#[cfg(any(feature = "syslog", feature = "http", all(feature = "journald", target_os = "linux")))]
+mod unit_cache;
+
+#[cfg(any(feature = "mongo", feature = "postgres"))]
+mod write_gate;
+
 // Re-export destination traits and types
+pub use mirror::MirrorDestination;
+pub use retry::RetryingDestination;
+
+#[cfg(feature = "bus")]
+pub use bus::MessageBusDestination;
+
+#[cfg(feature = "clickhouse")]
+pub use clickhouse::ClickHouseDestination;
+
 #[cfg(feature = "console")]
 pub use console::ConsoleDestination;
 
+#[cfg(feature = "elastic")]
+pub use elastic::ElasticDestination;
+
+#[cfg(feature = "http")]
+pub use http::HttpDestination;
+
+#[cfg(all(feature = "journald", target_os = "linux"))]
+pub use journald::JournaldDestination;
+
+#[cfg(feature = "loki")]
+pub use loki::LokiDestination;
+
 #[cfg(feature = "mongo")]
 pub use mongodb::MongoDestination;
 
+#[cfg(feature = "otlp")]
+pub use otlp::OtlpDestination;
+
 #[cfg(feature = "postgres")]
-pub use postgres::PostgresDestination;
\ No newline at end of file
+pub use postgres::PostgresDestination;
+
+#[cfg(feature = "redis-streams")]
+pub use redis_streams::RedisStreamsDestination;
+
+#[cfg(feature = "spool")]
+pub use spool::SpoolingDestination;
+
+#[cfg(feature = "syslog")]
+pub use syslog::SyslogDestination;
\ No newline at end of file
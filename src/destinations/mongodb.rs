@@ -1,19 +1,32 @@
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
 use uuid::Uuid;
 
 #[cfg(feature = "mongo")]
 use async_trait::async_trait;
 #[cfg(feature = "mongo")]
-use futures::TryStreamExt;
+use chrono::{DateTime, Utc};
 #[cfg(feature = "mongo")]
-use mongodb::{bson::doc, Client, Collection, Database};
+use futures::stream::{self, StreamExt, TryStreamExt};
 #[cfg(feature = "mongo")]
-use crate::core::{LogEntry, LogService, LogUnit};
+use std::collections::HashMap;
+#[cfg(feature = "mongo")]
+use std::time::Duration;
+#[cfg(feature = "mongo")]
+use tokio::time::Instant;
+#[cfg(feature = "mongo")]
+use mongodb::bson::{self, doc, Document};
+#[cfg(feature = "mongo")]
+use mongodb::options::IndexOptions;
+#[cfg(feature = "mongo")]
+use mongodb::{Client, Collection, Database, IndexModel};
+#[cfg(feature = "mongo")]
+use crate::core::{ErrorDetails, ExternalIdStats, HistogramBucket, LevelCounts, LogEntry, LogService, LogUnit, RetentionPolicy, SearchHit, SearchOptions, SourceLocation, StatsFilter, Subscription, SubscriptionFilter, TimeBucket, TraceContext, UnitRanking, UnitStats};
+#[cfg(feature = "mongo")]
+use crate::core::search;
 #[cfg(feature = "mongo")]
 use crate::core::log_service::LogResult;
 #[cfg(feature = "mongo")]
-use crate::destinations::console::ConsoleDestination;
+use crate::destinations::write_gate::WriteGate;
 use crate::{LogLevel, LogMessageType};
 
 #[cfg(feature = "mongo")]
@@ -23,6 +36,11 @@ pub struct MongoConfig {
     pub database_name: String,
     pub log_units_collection: String,
     pub log_entries_collection: String,
+    /// Retention enforced by the server through TTL indexes on the entries collection.
+    ///
+    /// Only the per-level maximum ages can be expressed as TTL indexes; the
+    /// maximum number of units per external ID is enforced by `trim_log_units`.
+    pub retention: Option<RetentionPolicy>,
 }
 #[cfg(feature = "mongo")]
 impl Default for MongoConfig {
@@ -32,16 +50,18 @@ impl Default for MongoConfig {
             database_name: "ironscribe".to_string(),
             log_units_collection: "log_units".to_string(),
             log_entries_collection: "log_entries".to_string(),
+            retention: None,
         }
     }
 }
 
 #[cfg(feature = "mongo")]
 pub struct MongoDestination {
+    client: Client,
+    writes: WriteGate,
     database: Database,
     log_units: Collection<LogUnitWrapper>,
     log_entries: Collection<LogEntryWrapper>,
-    console: ConsoleDestination,
 }
 
 #[cfg(feature = "mongo")]
@@ -53,44 +73,261 @@ impl MongoDestination {
         let database = client.database(&config.database_name);
         let log_units = database.collection::<LogUnitWrapper>(&config.log_units_collection);
         let log_entries = database.collection::<LogEntryWrapper>(&config.log_entries_collection);
-        Ok(Self {
+        let destination = Self {
+            client,
+            writes: WriteGate::new(),
             database,
             log_units,
             log_entries,
-            console: ConsoleDestination::new(),
-        })
+        };
+
+        destination.create_search_index().await?;
+        if let Some(retention) = &config.retention {
+            destination.create_ttl_indexes(retention).await?;
+        }
+
+        Ok(destination)
     }
 
     pub async fn with_default_config() -> LogResult<Self> {
         Self::new(MongoConfig::default()).await
     }
+
+    /// Converts timestamps stored as RFC 3339 strings, as written before they
+    /// became BSON dates, returning the number of converted documents.
+    ///
+    /// Such documents are still read, but time ranges, TTL indexes and
+    /// `purge` only match BSON dates, so existing collections should be
+    /// migrated once.
+    pub async fn migrate_timestamps(&self) -> LogResult<u64> {
+        let filter = doc! { "timestamp": { "$type": "string" } };
+        let update = vec![doc! { "$set": { "timestamp": { "$toDate": "$timestamp" } } }];
+        let units = self
+            .log_units
+            .update_many(filter.clone(), update.clone())
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let entries = self
+            .log_entries
+            .update_many(filter, update)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        Ok(units.modified_count + entries.modified_count)
+    }
+
+    /// Creates the text index on `message` used by `search_entries`.
+    ///
+    /// Stemming is disabled so that words are matched as written, like in the
+    /// other destinations.
+    async fn create_search_index(&self) -> LogResult<()> {
+        let index = IndexModel::builder()
+            .keys(doc! { "message": "text" })
+            .options(
+                IndexOptions::builder()
+                    .name("message_text".to_string())
+                    .default_language("none".to_string())
+                    .build(),
+            )
+            .build();
+
+        self.log_entries
+            .create_index(index)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        Ok(())
+    }
+
+    /// Creates one partial TTL index on `timestamp` per level with a maximum age.
+    ///
+    /// An index that already exists with a different expiry is updated in place.
+    async fn create_ttl_indexes(&self, retention: &RetentionPolicy) -> LogResult<()> {
+        for (level, max_age) in &retention.max_age {
+            let name = format!("ttl_{:?}", level).to_lowercase();
+            let expire_after = max_age
+                .to_std()
+                .map_err(|_| format!("Negative retention for {:?}", level))?;
+            let level = bson::to_bson(level)?;
+
+            let index = IndexModel::builder()
+                .keys(doc! { "timestamp": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .name(name.clone())
+                        .expire_after(expire_after)
+                        .partial_filter_expression(doc! { "level": level })
+                        .build(),
+                )
+                .build();
+
+            if let Err(e) = self.log_entries.create_index(index).await {
+                let update = doc! {
+                    "collMod": self.log_entries.name(),
+                    "index": { "name": name, "expireAfterSeconds": expire_after.as_secs() as i64 },
+                };
+                self.database
+                    .run_command(update)
+                    .await
+                    .map_err(|_| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Builds a filter for `level` matching entries at least as severe as `min_level`
+    fn min_level_filter(min_level: LogLevel) -> LogResult<Document> {
+        let levels = LogLevel::ALL
+            .into_iter()
+            .filter(|level| level.is_at_least(min_level))
+            .map(|level| bson::to_bson(&level))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(doc! { "$in": levels })
+    }
+
+    /// Builds a filter for entries by level, time range, module and external ID of their unit
+    async fn entries_filter(
+        &self,
+        min_level: Option<LogLevel>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        module_path: Option<&str>,
+        external_id: Option<&str>,
+    ) -> LogResult<Document> {
+        let mut filter = Document::new();
+        if let Some(min_level) = min_level {
+            filter.insert("level", Self::min_level_filter(min_level)?);
+        }
+        let mut timestamp = Document::new();
+        if let Some(from) = from {
+            timestamp.insert("$gte", bson::DateTime::from_chrono(from));
+        }
+        if let Some(to) = to {
+            timestamp.insert("$lt", bson::DateTime::from_chrono(to));
+        }
+        if !timestamp.is_empty() {
+            filter.insert("timestamp", timestamp);
+        }
+        if let Some(module_path) = module_path {
+            let submodules: String = module_path
+                .chars()
+                .flat_map(|c| {
+                    let escape = !c.is_alphanumeric() && c != '_' && c != ':';
+                    escape.then_some('\\').into_iter().chain(std::iter::once(c))
+                })
+                .collect();
+            filter.insert(
+                "$or",
+                vec![
+                    doc! { "location.module_path": module_path },
+                    doc! { "location.module_path": { "$regex": format!("^{}::", submodules) } },
+                ],
+            );
+        }
+        if let Some(external_id) = external_id {
+            let log_unit_ids: Vec<String> = self
+                .get_log_units_by_external_id(external_id)
+                .await?
+                .into_iter()
+                .map(|unit| unit.log_unit_id.to_string())
+                .collect();
+            filter.insert("log_unit_id", doc! { "$in": log_unit_ids });
+        }
+        Ok(filter)
+    }
+
+    /// Runs `stages` on the entries matching `filter`
+    async fn aggregate_entries(&self, filter: &StatsFilter, stages: Vec<Document>) -> LogResult<Vec<Document>> {
+        let matching = self
+            .entries_filter(filter.min_level, filter.from, filter.to, filter.module_path.as_deref(), filter.external_id.as_deref())
+            .await?;
+        let mut pipeline = vec![doc! { "$match": matching }];
+        pipeline.extend(stages);
+
+        let cursor = self.log_entries
+            .aggregate(pipeline)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let documents: Vec<Document> = cursor
+            .try_collect()
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        Ok(documents)
+    }
+
+    /// Name of the field holding the count of `level` in grouped documents
+    fn level_field(level: LogLevel) -> &'static str {
+        match level {
+            LogLevel::Error => "error",
+            LogLevel::Warning => "warning",
+            LogLevel::Info => "info",
+            LogLevel::Success => "success",
+        }
+    }
+
+    /// `$group` stage counting entries per level, grouped by `id`
+    fn level_counts_group(id: impl Into<bson::Bson>) -> LogResult<Document> {
+        let mut group = doc! { "_id": id.into(), "total": { "$sum": 1 } };
+        for level in LogLevel::ALL {
+            let is_level = doc! { "$eq": ["$level", bson::to_bson(&level)?] };
+            group.insert(Self::level_field(level), doc! { "$sum": { "$cond": [is_level, 1, 0] } });
+        }
+        Ok(doc! { "$group": group })
+    }
+
+    /// Reads the level counts of a document grouped by `level_counts_group`
+    fn level_counts(document: &Document) -> LevelCounts {
+        let mut counts = LevelCounts::default();
+        for level in LogLevel::ALL {
+            let count = match document.get(Self::level_field(level)) {
+                Some(bson::Bson::Int32(count)) => *count as u64,
+                Some(bson::Bson::Int64(count)) => *count as u64,
+                _ => 0,
+            };
+            counts.add(level, count);
+        }
+        counts
+    }
+
+    /// Deletes the given units and all of their entries, returning the number of deleted units
+    async fn delete_log_units(&self, log_unit_ids: Vec<String>) -> LogResult<u64> {
+        if log_unit_ids.is_empty() {
+            return Ok(0);
+        }
+
+        self.log_entries
+            .delete_many(doc! { "log_unit_id": { "$in": &log_unit_ids } })
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let result = self.log_units
+            .delete_many(doc! { "_id": { "$in": &log_unit_ids } })
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        Ok(result.deleted_count)
+    }
 }
 
 #[cfg(feature = "mongo")]
 #[async_trait]
 impl LogService for MongoDestination {
-    async fn create_log_unit(&self, external_id: String) -> LogResult<LogUnit> {
-        let log_unit = LogUnit::new(external_id);
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        let _write = self.writes.enter().await?;
         // Store in MongoDB using wrapper
-        let wrapper = LogUnitWrapper::from(log_unit.clone());
+        let wrapper = LogUnitWrapper::from(log_unit);
         self.log_units
             .insert_one(&wrapper)
             .await
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
-        // Also log to console
-        self.console.create_log_unit(log_unit.external_id.clone()).await?;
-        Ok(log_unit)
+        Ok(())
     }
 
     async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        let _write = self.writes.enter().await?;
         let wrapper = LogEntryWrapper::from(entry);
         // Store in MongoDB
         self.log_entries
             .insert_one(&wrapper)
             .await
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
-        // Also log to console
-        self.console.log(LogEntry::from(wrapper)).await?;
         Ok(())
     }
 
@@ -116,7 +353,7 @@ impl LogService for MongoDestination {
 
         let log_unit_id_string = log_unit_id.to_string();
 
-        let filter = doc! { "log_unit_id": log_unit_id_string };
+        let filter = doc! { "_id": log_unit_id_string };
         let unit = self.log_units
             .find_one(filter)
             .await
@@ -137,6 +374,335 @@ impl LogService for MongoDestination {
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
         Ok(units.into_iter().map(LogUnit::from).collect())
     }
+
+    async fn list_log_units(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> LogResult<Vec<LogUnit>> {
+        let mut timestamp = Document::new();
+        if let Some(from) = from {
+            timestamp.insert("$gte", bson::DateTime::from_chrono(from));
+        }
+        if let Some(to) = to {
+            timestamp.insert("$lt", bson::DateTime::from_chrono(to));
+        }
+        let filter = if timestamp.is_empty() { doc! {} } else { doc! { "timestamp": timestamp } };
+
+        let cursor = self.log_units
+            .find(filter)
+            .sort(doc! { "timestamp": 1 })
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let units: Vec<LogUnitWrapper> = cursor
+            .try_collect()
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        Ok(units.into_iter().map(LogUnit::from).collect())
+    }
+
+    async fn search_entries(&self, text: &str, options: &SearchOptions) -> LogResult<Vec<SearchHit>> {
+        let terms = search::tokenize(text);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Quoted terms are all required, matching the other destinations
+        let quoted_terms: Vec<String> = terms.iter().map(|term| format!("\"{}\"", term)).collect();
+        let mut filter = self
+            .entries_filter(options.min_level, options.from, options.to, options.module_path.as_deref(), options.external_id.as_deref())
+            .await?;
+        filter.insert("$text", doc! { "$search": quoted_terms.join(" ") });
+
+        let cursor = self.log_entries
+            .clone_with_type::<Document>()
+            .find(filter)
+            .projection(doc! { "score": { "$meta": "textScore" } })
+            .sort(doc! { "score": { "$meta": "textScore" }, "timestamp": -1 })
+            .limit(options.limit as i64)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let documents: Vec<Document> = cursor
+            .try_collect()
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+
+        let mut hits = Vec::with_capacity(documents.len());
+        for document in documents {
+            let score = document.get_f64("score")? as f32;
+            let entry = LogEntry::from(bson::from_document::<LogEntryWrapper>(document)?);
+            hits.push((entry, score));
+        }
+
+        let log_unit_ids: Vec<String> = hits.iter().map(|(entry, _)| entry.log_unit_id.to_string()).collect();
+        let cursor = self.log_units
+            .find(doc! { "_id": { "$in": log_unit_ids } })
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let units: Vec<LogUnitWrapper> = cursor
+            .try_collect()
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let units: Vec<LogUnit> = units.into_iter().map(LogUnit::from).collect();
+
+        let terms = terms.into_iter().collect();
+        Ok(hits
+            .into_iter()
+            .map(|(entry, score)| SearchHit {
+                log_unit: units.iter().find(|unit| unit.log_unit_id == entry.log_unit_id).cloned(),
+                snippet: search::snippet(&entry.message, &terms, options),
+                entry,
+                score,
+            })
+            .collect())
+    }
+
+    /// Counts the matching entries in a single aggregation grouping on the level
+    async fn count_by_level(&self, filter: &StatsFilter) -> LogResult<LevelCounts> {
+        let stages = vec![Self::level_counts_group(bson::Bson::Null)?];
+        let groups = self.aggregate_entries(filter, stages).await?;
+        Ok(groups.first().map(Self::level_counts).unwrap_or_default())
+    }
+
+    async fn entry_histogram(&self, filter: &StatsFilter, bucket: TimeBucket) -> LogResult<Vec<HistogramBucket>> {
+        let millis = doc! { "$toLong": "$timestamp" };
+        let bucket_start = doc! { "$toDate": {
+            "$subtract": [&millis, { "$mod": [&millis, bucket.seconds() * 1000] }],
+        } };
+        let stages = vec![
+            Self::level_counts_group(bucket_start)?,
+            doc! { "$sort": { "_id": 1 } },
+        ];
+
+        let groups = self.aggregate_entries(filter, stages).await?;
+        groups
+            .iter()
+            .map(|group| {
+                Ok(HistogramBucket {
+                    start: group.get_datetime("_id")?.to_chrono(),
+                    counts: Self::level_counts(group),
+                })
+            })
+            .collect()
+    }
+
+    async fn top_units(&self, filter: &StatsFilter, ranking: UnitRanking, limit: usize) -> LogResult<Vec<UnitStats>> {
+        let rank_field = match ranking {
+            UnitRanking::Entries => "total",
+            UnitRanking::Errors => Self::level_field(LogLevel::Error),
+        };
+        let stages = vec![
+            Self::level_counts_group("$log_unit_id")?,
+            doc! { "$sort": { rank_field: -1, "total": -1 } },
+            doc! { "$limit": limit as i64 },
+            doc! { "$lookup": {
+                "from": self.log_units.name(),
+                "localField": "_id",
+                "foreignField": "_id",
+                "as": "log_unit",
+            } },
+        ];
+
+        let groups = self.aggregate_entries(filter, stages).await?;
+        let mut units = Vec::new();
+        for group in &groups {
+            let log_unit = match group.get_array("log_unit")?.first() {
+                Some(bson::Bson::Document(unit)) => {
+                    Some(LogUnit::from(bson::from_document::<LogUnitWrapper>(unit.clone())?))
+                }
+                _ => None,
+            };
+            units.push(UnitStats {
+                log_unit_id: group.get_str("_id")?.parse()?,
+                log_unit,
+                counts: Self::level_counts(group),
+            });
+        }
+        Ok(units)
+    }
+
+    async fn counts_by_external_id(&self, filter: &StatsFilter) -> LogResult<Vec<ExternalIdStats>> {
+        let mut by_external_id = doc! { "_id": "$log_unit.external_id" };
+        for level in LogLevel::ALL {
+            let field = Self::level_field(level);
+            by_external_id.insert(field, doc! { "$sum": format!("${}", field) });
+        }
+        let stages = vec![
+            Self::level_counts_group("$log_unit_id")?,
+            doc! { "$lookup": {
+                "from": self.log_units.name(),
+                "localField": "_id",
+                "foreignField": "_id",
+                "as": "log_unit",
+            } },
+            doc! { "$unwind": "$log_unit" },
+            doc! { "$group": by_external_id },
+            doc! { "$sort": { "_id": 1 } },
+        ];
+
+        let groups = self.aggregate_entries(filter, stages).await?;
+        groups
+            .iter()
+            .map(|group| {
+                Ok(ExternalIdStats {
+                    external_id: group.get_str("_id")?.to_string(),
+                    counts: Self::level_counts(group),
+                })
+            })
+            .collect()
+    }
+
+    /// Subscribes to entries inserted by any writer, using a change stream.
+    ///
+    /// Change streams require MongoDB to run as a replica set or sharded cluster.
+    async fn subscribe(&self, filter: SubscriptionFilter) -> LogResult<Subscription> {
+        let mut inserted = doc! { "operationType": "insert" };
+        if let Some(log_unit_id) = filter.log_unit_id {
+            inserted.insert("fullDocument.log_unit_id", log_unit_id.to_string());
+        }
+        if let Some(min_level) = filter.min_level {
+            inserted.insert("fullDocument.level", Self::min_level_filter(min_level)?);
+        }
+
+        let change_stream = self.log_entries
+            .watch()
+            .pipeline(vec![doc! { "$match": inserted }])
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let log_units = self.log_units.clone();
+        let external_id = filter.external_id;
+
+        // Whether each seen unit belongs to the filtered external ID
+        let known: HashMap<String, bool> = HashMap::new();
+        let entries = stream::unfold((change_stream, known), move |(mut change_stream, mut known)| {
+            let (log_units, external_id) = (log_units.clone(), external_id.clone());
+            async move {
+                loop {
+                    let event = match change_stream.next().await? {
+                        Ok(event) => event,
+                        Err(e) => {
+                            eprintln!("MongoDB change stream error: {}", e);
+                            return None;
+                        }
+                    };
+                    let Some(wrapper) = event.full_document else {
+                        continue;
+                    };
+
+                    if let Some(external_id) = &external_id {
+                        let matches = match known.get(&wrapper.log_unit_id) {
+                            Some(matches) => *matches,
+                            None => {
+                                // Failed lookups are not cached and retried for the next entry
+                                match log_units.find_one(doc! { "_id": &wrapper.log_unit_id }).await {
+                                    Ok(unit) => {
+                                        let matches = unit.is_some_and(|unit| &unit.external_id == external_id);
+                                        known.insert(wrapper.log_unit_id.clone(), matches);
+                                        matches
+                                    }
+                                    Err(_) => false,
+                                }
+                            }
+                        };
+                        if !matches {
+                            continue;
+                        }
+                    }
+
+                    return Some((LogEntry::from(wrapper), (change_stream, known)));
+                }
+            }
+        });
+
+        Ok(Subscription::new(entries))
+    }
+
+    async fn delete_log_unit(&self, log_unit_id: Uuid) -> LogResult<bool> {
+        let deleted = self.delete_log_units(vec![log_unit_id.to_string()]).await?;
+        Ok(deleted > 0)
+    }
+
+    async fn purge_entries(&self, before: DateTime<Utc>, level: Option<LogLevel>) -> LogResult<u64> {
+        let mut filter = doc! { "timestamp": { "$lt": bson::DateTime::from_chrono(before) } };
+        if let Some(level) = level {
+            filter.insert("level", bson::to_bson(&level)?);
+        }
+
+        let result = self.log_entries
+            .delete_many(filter)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        Ok(result.deleted_count)
+    }
+
+    async fn purge_empty_log_units(&self, before: DateTime<Utc>) -> LogResult<u64> {
+        let pipeline = vec![
+            doc! { "$match": { "timestamp": { "$lt": bson::DateTime::from_chrono(before) } } },
+            doc! { "$lookup": {
+                "from": self.log_entries.name(),
+                "let": { "unit_id": "$_id" },
+                "pipeline": [
+                    { "$match": { "$expr": { "$eq": ["$log_unit_id", "$$unit_id"] } } },
+                    { "$limit": 1 },
+                ],
+                "as": "entries",
+            } },
+            doc! { "$match": { "entries": { "$size": 0 } } },
+            doc! { "$project": { "_id": 1 } },
+        ];
+
+        let cursor = self.log_units
+            .aggregate(pipeline)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let empty_units: Vec<Document> = cursor
+            .try_collect()
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let log_unit_ids = empty_units
+            .iter()
+            .map(|unit| unit.get_str("_id").map(str::to_string))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.delete_log_units(log_unit_ids).await
+    }
+
+    async fn trim_log_units(&self, max_units: usize) -> LogResult<u64> {
+        let pipeline = vec![
+            doc! { "$sort": { "timestamp": -1 } },
+            doc! { "$group": { "_id": "$external_id", "log_unit_ids": { "$push": "$_id" } } },
+        ];
+
+        let cursor = self.log_units
+            .aggregate(pipeline)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+        let groups: Vec<Document> = cursor
+            .try_collect()
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+
+        let mut expired = Vec::new();
+        for group in &groups {
+            for log_unit_id in group.get_array("log_unit_ids")?.iter().skip(max_units) {
+                if let Some(log_unit_id) = log_unit_id.as_str() {
+                    expired.push(log_unit_id.to_string());
+                }
+            }
+        }
+
+        self.delete_log_units(expired).await
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        self.writes.drain().await;
+        Ok(())
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        let deadline = Instant::now() + timeout;
+        self.writes.close(timeout).await?;
+        tokio::time::timeout(deadline.saturating_duration_since(Instant::now()), self.client.clone().shutdown())
+            .await
+            .map_err(|_| format!("timed out after {:?} closing the MongoDB client", timeout))?;
+        Ok(())
+    }
 }
 
 #[cfg(feature = "mongo")]
@@ -148,7 +714,14 @@ pub struct LogEntryWrapper {
     pub message: String,
     message_type: LogMessageType,
     level: LogLevel,
+    #[serde(with = "bson_timestamp")]
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<SourceLocation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetails>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<TraceContext>,
 }
 
 impl From<LogEntry> for LogEntryWrapper {
@@ -160,6 +733,9 @@ impl From<LogEntry> for LogEntryWrapper {
             message_type: entry.message_type,
             level: entry.level,
             timestamp: entry.timestamp,
+            location: entry.location,
+            error: entry.error,
+            trace: entry.trace,
         }
     }
 }
@@ -173,6 +749,9 @@ impl From<LogEntryWrapper> for LogEntry {
             message_type: wrapper.message_type,
             level: wrapper.level,
             timestamp: wrapper.timestamp,
+            location: wrapper.location,
+            error: wrapper.error,
+            trace: wrapper.trace,
         }
     }
 }
@@ -183,6 +762,7 @@ pub struct LogUnitWrapper {
     #[serde(rename = "_id")]
     pub log_unit_id: String,
     pub external_id: String,
+    #[serde(with = "bson_timestamp")]
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
@@ -208,6 +788,32 @@ impl From<LogUnitWrapper> for LogUnit {
     }
 }
 
+/// Writes timestamps as BSON dates, reading the RFC 3339 strings stored before as well
+#[cfg(feature = "mongo")]
+mod bson_timestamp {
+    use chrono::{DateTime, Utc};
+    use mongodb::bson::{self, serde_helpers::chrono_datetime_as_bson_datetime};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Date(bson::DateTime),
+        Text(DateTime<Utc>),
+    }
+
+    pub fn serialize<S: Serializer>(timestamp: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        chrono_datetime_as_bson_datetime::serialize(timestamp, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+        Ok(match Stored::deserialize(deserializer)? {
+            Stored::Date(date) => date.to_chrono(),
+            Stored::Text(timestamp) => timestamp,
+        })
+    }
+}
+
 // Provide stub implementation when mongodb feature is not enabled
 #[cfg(not(feature = "mongo"))]
 pub struct MongoDestination;
@@ -217,4 +823,24 @@ impl MongoDestination {
     pub fn new() -> Self {
         panic!("MongoDB destination requires 'mongodb' feature to be enabled");
     }
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, feature = "mongo"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_date_and_string_timestamps() {
+        let unit = LogUnit::new("job-1".to_string());
+        let stored = bson::to_document(&LogUnitWrapper::from(unit.clone())).unwrap();
+        assert!(matches!(stored.get("timestamp"), Some(bson::Bson::DateTime(_))));
+
+        let mut legacy = stored.clone();
+        legacy.insert("timestamp", unit.timestamp.to_rfc3339());
+        for document in [stored, legacy] {
+            let read = LogUnit::from(bson::from_document::<LogUnitWrapper>(document).unwrap());
+            // BSON dates have millisecond precision
+            assert_eq!(read.timestamp.timestamp_millis(), unit.timestamp.timestamp_millis());
+        }
+    }
+}
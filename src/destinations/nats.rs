@@ -0,0 +1,103 @@
+//! NATS publisher for `MessageBusDestination`
+
+use async_nats::{Client, HeaderMap};
+use async_trait::async_trait;
+
+use crate::core::log_service::LogResult;
+use crate::destinations::bus::{BusMessage, MessagePublisher};
+
+/// Publishes messages to NATS subjects named `{topic}.{key}`.
+///
+/// Consumers subscribe to `{topic}.>` for all units or to a single unit's
+/// subject. Messages from one connection arrive in order, and JetStream
+/// streams can limit or order messages per subject, i.e. per unit. The
+/// payload's content type is sent in a `Content-Type` header.
+pub struct NatsPublisher {
+    client: Client,
+}
+
+impl NatsPublisher {
+    /// Connects to a NATS server, e.g. `nats://localhost:4222`
+    pub async fn connect(url: &str) -> LogResult<Self> {
+        Ok(Self::with_client(async_nats::connect(url).await?))
+    }
+
+    /// Publishes through an existing client, e.g. one connected with credentials
+    pub fn with_client(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl MessagePublisher for NatsPublisher {
+    async fn publish(&self, message: BusMessage) -> LogResult<()> {
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", message.content_type);
+        let subject = format!("{}.{}", message.topic, message.key);
+        self.client.publish_with_headers(subject, headers, message.payload.into()).await?;
+        Ok(())
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        self.client.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::destinations::bus::{MessageBusConfig, MessageBusDestination};
+    use crate::core::{LogEntry, LogService};
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// Speaks enough of the NATS protocol to accept a connection, answer pings
+    /// and forward the subject and payload of published messages
+    async fn nats_server() -> (String, mpsc::UnboundedReceiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("nats://{}", listener.local_addr().unwrap());
+        let (published, received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let info = r#"INFO {"server_id":"test","server_name":"test","version":"2.10.0","go":"go1.22","host":"127.0.0.1","port":4222,"headers":true,"max_payload":1048576,"proto":1}"#;
+            stream.get_mut().write_all(format!("{}\r\n", info).as_bytes()).await.unwrap();
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 0 {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                match words.first().copied() {
+                    Some("PING") => stream.get_mut().write_all(b"PONG\r\n").await.unwrap(),
+                    Some("HPUB") => {
+                        let length: usize = words.last().unwrap().parse().unwrap();
+                        let header_length: usize = words[words.len() - 2].parse().unwrap();
+                        let mut message = vec![0; length + 2];
+                        stream.read_exact(&mut message).await.unwrap();
+                        let headers = String::from_utf8_lossy(&message[..header_length]).to_string();
+                        assert!(headers.contains("Content-Type: application/json"), "{}", headers);
+                        published.send((words[1].to_string(), message[header_length..length].to_vec())).unwrap();
+                    }
+                    _ => {}
+                }
+                line.clear();
+            }
+        });
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn test_publishes_to_per_unit_subjects() {
+        let (url, mut received) = nats_server().await;
+        let publisher = Arc::new(NatsPublisher::connect(&url).await.unwrap());
+        let destination = MessageBusDestination::new(publisher, MessageBusConfig { units_topic: None, ..Default::default() });
+        let entry = LogEntry::info(uuid::Uuid::new_v4(), "started".to_string());
+        destination.log(entry.clone()).await.unwrap();
+        destination.flush().await.unwrap();
+
+        let (subject, payload) = received.recv().await.unwrap();
+        assert_eq!(subject, format!("ironscribe.entries.{}", entry.log_unit_id));
+        assert_eq!(serde_json::from_slice::<LogEntry>(&payload).unwrap(), entry);
+    }
+}
@@ -0,0 +1,453 @@
+//! OpenTelemetry destination exporting entries as OTLP log records
+
+use async_trait::async_trait;
+use opentelemetry_proto::tonic::collector::logs::v1::logs_service_client::LogsServiceClient;
+use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceRequest;
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, ArrayValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::logs::v1::{LogRecord, ResourceLogs, ScopeLogs, SeverityNumber};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use prost::Message;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::StatusCode;
+use std::time::Duration;
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tonic::{Code, Status};
+use uuid::Uuid;
+
+use crate::core::{LogEntry, LogLevel, LogService, LogUnit};
+use crate::core::log_service::{unsupported, LogResult};
+use crate::destinations::batch::{BatchLimits, BatchSink, Batcher};
+use crate::destinations::http::{self, HttpError};
+use crate::destinations::retry::{Failure, RetryPolicy};
+use crate::destinations::unit_cache::ExternalIdCache;
+
+/// Protocol used to reach the collector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtlpProtocol {
+    /// Protobuf over HTTP, posted to the logs endpoint, e.g. `http://localhost:4318/v1/logs`
+    HttpProtobuf,
+    /// gRPC, sent to the collector address, e.g. `http://localhost:4317`
+    Grpc,
+}
+
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    pub endpoint: String,
+    pub protocol: OtlpProtocol,
+    /// Headers, or gRPC metadata, sent with every export, e.g. `("api-key", "...")`
+    pub headers: Vec<(String, String)>,
+    /// `service.name` resource attribute
+    pub service_name: String,
+    /// Further resource attributes, e.g. `("deployment.environment", "production")`
+    pub resource_attributes: Vec<(String, String)>,
+    /// Exports a batch once it holds this many records
+    pub max_batch_entries: usize,
+    /// Exports the pending records at least this often
+    pub flush_interval: Duration,
+    /// Timeout of a single export
+    pub timeout: Duration,
+    /// Retries of exports failing with a transient error.
+    ///
+    /// An HTTP `Retry-After` header replaces the backoff, up to `max_backoff`.
+    pub retry: RetryPolicy,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:4318/v1/logs".to_string(),
+            protocol: OtlpProtocol::HttpProtobuf,
+            headers: Vec::new(),
+            service_name: "unknown_service".to_string(),
+            resource_attributes: Vec::new(),
+            max_batch_entries: 512,
+            flush_interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(10),
+            retry: RetryPolicy {
+                max_retries: 5,
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(30),
+                jitter: 0.5,
+            },
+        }
+    }
+}
+
+struct GrpcTransport {
+    client: LogsServiceClient<Channel>,
+    metadata: MetadataMap,
+}
+
+enum Transport {
+    Http(reqwest::Client),
+    Grpc(Box<GrpcTransport>),
+}
+
+/// Exports batches of log records
+struct Exporter {
+    config: OtlpConfig,
+    transport: Transport,
+    resource: Resource,
+}
+
+#[async_trait]
+impl BatchSink for Exporter {
+    type Item = LogRecord;
+
+    async fn send(&self, records: Vec<LogRecord>) -> LogResult<()> {
+        let count = records.len();
+        self.export(self.request(records))
+            .await
+            .map_err(|e| format!("Failed to export {} log records to {}: {}", count, self.config.endpoint, e).into())
+    }
+}
+
+impl Exporter {
+    fn request(&self, log_records: Vec<LogRecord>) -> ExportLogsServiceRequest {
+        let scope = InstrumentationScope {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            ..Default::default()
+        };
+        ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: Some(self.resource.clone()),
+                scope_logs: vec![ScopeLogs {
+                    scope: Some(scope),
+                    log_records,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+    }
+
+    /// Exports `request`, retrying transient failures
+    async fn export(&self, request: ExportLogsServiceRequest) -> LogResult<()> {
+        let policy = &self.config.retry;
+        match &self.transport {
+            Transport::Http(client) => {
+                let body = request.encode_to_vec();
+                let export = || async {
+                    let request = client.post(&self.config.endpoint).header(CONTENT_TYPE, "application/x-protobuf").body(body.clone());
+                    http::send(request).await.map(drop)
+                };
+                policy.run(export, http_failure).await
+            }
+            Transport::Grpc(grpc) => {
+                let export = || async {
+                    let mut grpc_request = tonic::Request::new(request.clone());
+                    *grpc_request.metadata_mut() = grpc.metadata.clone();
+                    grpc_request.set_timeout(self.config.timeout);
+                    grpc.client.clone().export(grpc_request).await.map(drop)
+                };
+                policy.run(export, grpc_failure).await
+            }
+        }
+    }
+}
+
+/// Treats connection errors and the status codes OTLP/HTTP defines as retryable as transient
+fn http_failure(error: &HttpError) -> Failure {
+    match error.status {
+        None | Some(StatusCode::TOO_MANY_REQUESTS | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT) => {
+            Failure::Transient(error.retry_after)
+        }
+        Some(_) => Failure::Permanent,
+    }
+}
+
+/// Treats the status codes OTLP/gRPC defines as retryable as transient
+fn grpc_failure(status: &Status) -> Failure {
+    match status.code() {
+        Code::Cancelled | Code::DeadlineExceeded | Code::ResourceExhausted | Code::Aborted | Code::OutOfRange | Code::Unavailable | Code::DataLoss => {
+            Failure::Transient(None)
+        }
+        _ => Failure::Permanent,
+    }
+}
+
+fn string_value(value: impl Into<String>) -> Option<AnyValue> {
+    Some(AnyValue {
+        value: Some(any_value::Value::StringValue(value.into())),
+    })
+}
+
+fn attribute(key: &str, value: Option<AnyValue>) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value,
+        ..Default::default()
+    }
+}
+
+fn severity(level: LogLevel) -> (SeverityNumber, &'static str) {
+    match level {
+        LogLevel::Error => (SeverityNumber::Error, "ERROR"),
+        LogLevel::Warning => (SeverityNumber::Warn, "WARN"),
+        LogLevel::Info => (SeverityNumber::Info, "INFO"),
+        // Above plain info, so success can be told apart
+        LogLevel::Success => (SeverityNumber::Info2, "SUCCESS"),
+    }
+}
+
+/// Converts `entry` into an OTLP log record.
+///
+/// IDs, the source location and a recorded error become attributes, named
+/// after the OpenTelemetry semantic conventions where one applies.
+fn log_record(entry: &LogEntry, external_id: Option<&str>) -> LogRecord {
+    let mut attributes = vec![
+        attribute("log.record.uid", string_value(entry.message_id.to_string())),
+        attribute("ironscribe.log_unit_id", string_value(entry.log_unit_id.to_string())),
+    ];
+    if let Some(external_id) = external_id {
+        attributes.push(attribute("ironscribe.external_id", string_value(external_id)));
+    }
+    if let Some(location) = &entry.location {
+        let number = |value: u32| Some(AnyValue { value: Some(any_value::Value::IntValue(value.into())) });
+        attributes.push(attribute("code.file.path", string_value(location.file.as_str())));
+        attributes.push(attribute("code.line.number", number(location.line)));
+        attributes.push(attribute("code.column.number", number(location.column)));
+        if !location.module_path.is_empty() {
+            attributes.push(attribute("code.namespace", string_value(location.module_path.as_str())));
+        }
+    }
+    if let Some(error) = &entry.error {
+        if let Some(message) = error.chain.first() {
+            attributes.push(attribute("exception.message", string_value(message.as_str())));
+        }
+        let chain = error.chain.iter().map(|cause| string_value(cause.as_str()).unwrap_or_default()).collect();
+        attributes.push(attribute(
+            "ironscribe.error.chain",
+            Some(AnyValue { value: Some(any_value::Value::ArrayValue(ArrayValue { values: chain })) }),
+        ));
+        if let Some(backtrace) = &error.backtrace {
+            attributes.push(attribute("exception.stacktrace", string_value(backtrace.as_str())));
+        }
+    }
+
+    let (severity_number, severity_text) = severity(entry.level);
+    let trace = entry.trace.as_ref();
+    LogRecord {
+        time_unix_nano: entry.timestamp.timestamp_nanos_opt().unwrap_or_default() as u64,
+        observed_time_unix_nano: chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64,
+        severity_number: severity_number as i32,
+        severity_text: severity_text.to_string(),
+        body: string_value(entry.message.as_str()),
+        attributes,
+        trace_id: trace.and_then(|trace| trace.trace_id_bytes()).map(Vec::from).unwrap_or_default(),
+        span_id: trace.and_then(|trace| trace.span_id_bytes()).map(Vec::from).unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+/// Write-only destination exporting entries to an OpenTelemetry collector.
+///
+/// Records are exported in batches, once a batch is full, when
+/// `flush_interval` has passed and on `flush`. Logging an entry that fills the
+/// batch waits for the export. Records whose export still fails after the
+/// retries of `retry` are dropped; the error is returned by the call that
+/// filled the batch, or printed to stderr for timed exports. Reads are not
+/// supported.
+pub struct OtlpDestination {
+    batcher: Batcher<Exporter>,
+    external_ids: ExternalIdCache,
+}
+
+impl OtlpDestination {
+    /// Creates an OTLP destination; the collector is connected to on the first export
+    pub fn new(config: OtlpConfig) -> LogResult<Self> {
+        let transport = match config.protocol {
+            OtlpProtocol::HttpProtobuf => {
+                let mut headers = HeaderMap::new();
+                for (name, value) in &config.headers {
+                    headers.insert(HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(value)?);
+                }
+                Transport::Http(reqwest::Client::builder().default_headers(headers).timeout(config.timeout).build()?)
+            }
+            OtlpProtocol::Grpc => {
+                let mut metadata = MetadataMap::new();
+                for (name, value) in &config.headers {
+                    metadata.insert(MetadataKey::from_bytes(name.to_lowercase().as_bytes())?, MetadataValue::try_from(value.as_str())?);
+                }
+                let mut endpoint = Endpoint::from_shared(config.endpoint.clone())?.timeout(config.timeout);
+                if config.endpoint.starts_with("https://") {
+                    endpoint = endpoint.tls_config(ClientTlsConfig::new().with_webpki_roots())?;
+                }
+                Transport::Grpc(Box::new(GrpcTransport {
+                    client: LogsServiceClient::new(endpoint.connect_lazy()),
+                    metadata,
+                }))
+            }
+        };
+
+        let mut attributes = vec![attribute("service.name", string_value(config.service_name.as_str()))];
+        attributes.extend(config.resource_attributes.iter().map(|(key, value)| attribute(key, string_value(value.as_str()))));
+        let limits = BatchLimits {
+            max_items: config.max_batch_entries,
+            max_bytes: usize::MAX,
+            interval: config.flush_interval,
+        };
+        let exporter = Exporter {
+            resource: Resource {
+                attributes,
+                ..Default::default()
+            },
+            config,
+            transport,
+        };
+        Ok(Self {
+            batcher: Batcher::new(exporter, limits),
+            external_ids: ExternalIdCache::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl LogService for OtlpDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        self.external_ids.insert(log_unit.log_unit_id, log_unit.external_id).await;
+        Ok(())
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        let external_id = self.external_ids.get(entry.log_unit_id).await;
+        self.batcher.push(log_record(&entry, external_id.as_deref())).await
+    }
+
+    async fn get_log_entries(&self, _log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        unsupported("get_log_entries")
+    }
+
+    async fn get_log_unit(&self, _log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        unsupported("get_log_unit")
+    }
+
+    async fn get_log_units_by_external_id(&self, _external_id: &str) -> LogResult<Vec<LogUnit>> {
+        unsupported("get_log_units_by_external_id")
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        self.batcher.flush().await
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        self.batcher.shutdown(timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{SourceLocation, TraceContext};
+    use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceResponse;
+    use prost::bytes::Bytes;
+    use crate::destinations::test_server::{fast_retries, MockServer};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio::sync::Mutex;
+    use tonic::codegen::http;
+
+    type Received = Arc<Mutex<Vec<ExportLogsServiceRequest>>>;
+
+    /// OTLP/HTTP collector answering the first request with 503, then 200
+    async fn http_collector() -> MockServer {
+        let answered = AtomicBool::new(false);
+        MockServer::start(move |_| (if answered.swap(true, Ordering::SeqCst) { 200 } else { 503 }, String::new())).await
+    }
+
+    /// OTLP/gRPC collector accepting every export
+    async fn grpc_collector() -> (String, Received) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let received = Received::default();
+        let recorded = Arc::clone(&received);
+        tokio::spawn(async move {
+            let mut connection = h2::server::handshake(listener.accept().await.unwrap().0).await.unwrap();
+            while let Some(Ok((request, mut respond))) = connection.accept().await {
+                let recorded = Arc::clone(&recorded);
+                tokio::spawn(async move {
+                    assert_eq!(request.uri().path(), "/opentelemetry.proto.collector.logs.v1.LogsService/Export");
+                    let mut body = request.into_body();
+                    let mut data = Vec::new();
+                    while let Some(chunk) = body.data().await {
+                        let chunk = chunk.unwrap();
+                        body.flow_control().release_capacity(chunk.len()).unwrap();
+                        data.extend_from_slice(&chunk);
+                    }
+                    // gRPC messages are prefixed with a compression flag and a 4-byte length
+                    recorded.lock().await.push(ExportLogsServiceRequest::decode(&data[5..]).unwrap());
+
+                    let response = http::Response::builder().header("content-type", "application/grpc").body(()).unwrap();
+                    let mut stream = respond.send_response(response, false).unwrap();
+                    let message = ExportLogsServiceResponse::default().encode_to_vec();
+                    let mut frame = vec![0];
+                    frame.extend((message.len() as u32).to_be_bytes());
+                    frame.extend(message);
+                    stream.send_data(Bytes::from(frame), false).unwrap();
+                    let mut trailers = http::HeaderMap::new();
+                    trailers.insert("grpc-status", http::HeaderValue::from_static("0"));
+                    stream.send_trailers(trailers).unwrap();
+                });
+            }
+        });
+        (endpoint, received)
+    }
+
+    #[test]
+    fn test_log_record_conversion() {
+        let trace = TraceContext::from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        let entry = LogEntry::warning(Uuid::new_v4(), "disk almost full".to_string())
+            .with_location(SourceLocation::new("src/disk.rs", 42, 9, "app::disk"))
+            .with_trace_context(trace.clone());
+
+        let record = log_record(&entry, Some("job-1"));
+        assert_eq!(record.severity_number, SeverityNumber::Warn as i32);
+        assert_eq!(record.severity_text, "WARN");
+        assert_eq!(record.body, string_value("disk almost full"));
+        assert_eq!(record.trace_id, trace.trace_id_bytes().unwrap().to_vec());
+        assert_eq!(record.span_id, trace.span_id_bytes().unwrap().to_vec());
+        let value = |key: &str| record.attributes.iter().find(|kv| kv.key == key).and_then(|kv| kv.value.clone());
+        assert_eq!(value("ironscribe.external_id"), string_value("job-1"));
+        assert_eq!(value("ironscribe.log_unit_id"), string_value(entry.log_unit_id.to_string()));
+        assert_eq!(value("code.namespace"), string_value("app::disk"));
+    }
+
+    #[tokio::test]
+    async fn test_exports_over_http_and_grpc() {
+        let http_server = http_collector().await;
+        let (grpc_endpoint, grpc_received) = grpc_collector().await;
+        let http_endpoint = format!("{}/v1/logs", http_server.url);
+        for (endpoint, protocol) in [(http_endpoint, OtlpProtocol::HttpProtobuf), (grpc_endpoint, OtlpProtocol::Grpc)] {
+            let destination = OtlpDestination::new(OtlpConfig {
+                endpoint,
+                protocol,
+                service_name: "billing".to_string(),
+                retry: fast_retries(),
+                ..Default::default()
+            })
+            .unwrap();
+            let unit = destination.create_log_unit("job-1".to_string()).await.unwrap();
+            destination.log(LogEntry::info(unit.log_unit_id, "first".to_string())).await.unwrap();
+            destination.log(LogEntry::error(unit.log_unit_id, "second".to_string())).await.unwrap();
+            destination.shutdown(Duration::from_secs(5)).await.unwrap();
+        }
+
+        // The HTTP collector failed the first export, which was retried
+        let http_received: Received = Received::default();
+        for request in http_server.requests.lock().await.iter() {
+            http_received.lock().await.push(ExportLogsServiceRequest::decode(&request.body[..]).unwrap());
+        }
+        assert_eq!(http_received.lock().await.len(), 2);
+        for received in [http_received, grpc_received] {
+            let requests = received.lock().await;
+            let resource_logs = &requests.last().unwrap().resource_logs[0];
+            assert_eq!(resource_logs.resource.as_ref().unwrap().attributes[0].value, string_value("billing"));
+            let records = &resource_logs.scope_logs[0].log_records;
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[1].severity_number, SeverityNumber::Error as i32);
+        }
+    }
+}
@@ -1,18 +1,34 @@
 #[cfg(feature = "postgres")]
 use async_trait::async_trait;
 #[cfg(feature = "postgres")]
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, TimeZone, Utc};
+#[cfg(feature = "postgres")]
 use std::sync::Arc;
 #[cfg(feature = "postgres")]
-use tokio_postgres::{Client, NoTls};
+use std::time::Duration;
+#[cfg(feature = "postgres")]
+use futures::stream::{self, StreamExt};
+#[cfg(feature = "postgres")]
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+#[cfg(feature = "postgres")]
+use tokio::sync::{broadcast, Mutex};
+#[cfg(feature = "postgres")]
+use tokio::task::JoinHandle;
+#[cfg(feature = "postgres")]
+use tokio_postgres::error::SqlState;
+#[cfg(feature = "postgres")]
+use tokio_postgres::types::ToSql;
+#[cfg(feature = "postgres")]
+use tokio_postgres::{AsyncMessage, Client, NoTls};
 #[cfg(feature = "postgres")]
 use uuid::Uuid;
 
 #[cfg(feature = "postgres")]
-use crate::core::{LogEntry, LogService, LogUnit, LogLevel, LogMessageType};
+use crate::core::{retention, ErrorDetails, ExternalIdStats, HistogramBucket, LevelCounts, LogEntry, LogService, LogUnit, LogLevel, LogMessageType, PurgeReport, RetentionPolicy, SearchHit, SearchOptions, SourceLocation, StatsFilter, Subscription, SubscriptionFilter, TimeBucket, TraceContext, UnitRanking, UnitStats};
 #[cfg(feature = "postgres")]
 use crate::core::log_service::LogResult;
 #[cfg(feature = "postgres")]
-use crate::destinations::console::ConsoleDestination;
+use crate::destinations::write_gate::WriteGate;
 
 #[cfg(feature = "postgres")]
 #[derive(Debug, Clone)]
@@ -20,6 +36,19 @@ pub struct PostgresConfig {
     pub connection_string: String,
     pub log_units_table: String,
     pub log_entries_table: String,
+    /// Maximum number of rows removed by a single `DELETE` when purging; must be positive
+    pub purge_batch_size: i64,
+    /// Creates the entries table range-partitioned on `timestamp` when set
+    pub partitioning: Option<PartitionConfig>,
+    /// Text search configuration used to index messages, e.g. `simple` or `english`.
+    ///
+    /// It is applied to stored entries by `PostgresDestination::create_search_index`.
+    pub text_search_config: String,
+    /// Installs a trigger announcing every inserted entry with `NOTIFY`.
+    ///
+    /// `subscribe` needs the trigger, so this has to be enabled on a destination
+    /// created with `new`; it adds the cost of a notification to every insert.
+    pub notify_on_insert: bool,
 }
 
 #[cfg(feature = "postgres")]
@@ -29,24 +58,187 @@ impl Default for PostgresConfig {
             connection_string: "postgresql://localhost/ironscribe".to_string(),
             log_units_table: "log_units".to_string(),
             log_entries_table: "log_entries".to_string(),
+            purge_batch_size: 10_000,
+            partitioning: None,
+            text_search_config: "simple".to_string(),
+            notify_on_insert: false,
+        }
+    }
+}
+
+/// Time range covered by a single partition of the entries table
+#[cfg(feature = "postgres")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionInterval {
+    Daily,
+    /// ISO weeks, starting on Monday
+    Weekly,
+    Monthly,
+}
+
+#[cfg(feature = "postgres")]
+impl PartitionInterval {
+    /// Returns the start of the partition containing `timestamp`
+    pub fn start_of(self, timestamp: DateTime<Utc>) -> DateTime<Utc> {
+        let date = timestamp.date_naive();
+        let start = match self {
+            PartitionInterval::Daily => date,
+            PartitionInterval::Weekly => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            PartitionInterval::Monthly => date.with_day(1).unwrap_or(date),
+        };
+        Self::midnight(start)
+    }
+
+    /// Returns the start of the partition following the one starting at `start`
+    pub fn next(self, start: DateTime<Utc>) -> DateTime<Utc> {
+        let date = start.date_naive();
+        let next = match self {
+            PartitionInterval::Daily => date + Days::new(1),
+            PartitionInterval::Weekly => date + Days::new(7),
+            PartitionInterval::Monthly => date + Months::new(1),
+        };
+        Self::midnight(next)
+    }
+
+    fn midnight(date: NaiveDate) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&date.and_time(chrono::NaiveTime::MIN))
+    }
+}
+
+/// Settings for a range-partitioned entries table.
+///
+/// Partitions are created up to `premake` intervals ahead of the current time.
+/// Entries outside of them, such as imported old entries or entries with a
+/// far-future timestamp, are stored in the `<table>_default` partition.
+/// Retention deletes them row by row instead of dropping a partition.
+#[cfg(feature = "postgres")]
+#[derive(Debug, Clone)]
+pub struct PartitionConfig {
+    pub interval: PartitionInterval,
+    /// Number of partitions created ahead of the one receiving current entries
+    pub premake: u32,
+    /// Drops expired partitions after detaching them; when `false` they are kept
+    /// as standalone tables, e.g. for archiving
+    pub drop_expired: bool,
+}
+
+#[cfg(feature = "postgres")]
+impl PartitionConfig {
+    pub fn new(interval: PartitionInterval) -> Self {
+        Self {
+            interval,
+            premake: 3,
+            drop_expired: true,
+        }
+    }
+}
+
+/// Number of notifications buffered per subscriber before it starts missing entries
+#[cfg(feature = "postgres")]
+const NOTIFICATION_CAPACITY: usize = 1024;
+
+/// Query parameters `$1` to `$5` of `PostgresDestination::stats_source`
+#[cfg(feature = "postgres")]
+struct StatsParams {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    min_level: Option<i32>,
+    external_id: Option<String>,
+    module_path: Option<String>,
+}
+
+#[cfg(feature = "postgres")]
+impl StatsParams {
+    fn as_params(&self) -> [&(dyn ToSql + Sync); 5] {
+        [&self.from, &self.to, &self.min_level, &self.external_id, &self.module_path]
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<&StatsFilter> for StatsParams {
+    fn from(filter: &StatsFilter) -> Self {
+        Self {
+            from: filter.from,
+            to: filter.to,
+            min_level: filter.min_level.map(|level| level as i32),
+            external_id: filter.external_id.clone(),
+            module_path: filter.module_path.clone(),
         }
     }
 }
 
+/// Announcement of an inserted entry, as sent by the notify trigger
+#[cfg(feature = "postgres")]
+#[derive(Debug, Clone, Copy)]
+struct EntryNotification {
+    message_id: Uuid,
+    log_unit_id: Uuid,
+    level: LogLevel,
+}
+
+#[cfg(feature = "postgres")]
+impl EntryNotification {
+    /// Parses a `message_id,log_unit_id,level` payload
+    fn parse(payload: &str) -> Option<Self> {
+        let mut fields = payload.split(',');
+        let message_id = fields.next()?.parse().ok()?;
+        let log_unit_id = fields.next()?.parse().ok()?;
+        let level = match fields.next()?.parse::<i32>().ok()? {
+            0 => LogLevel::Error,
+            1 => LogLevel::Warning,
+            2 => LogLevel::Info,
+            3 => LogLevel::Success,
+            _ => return None,
+        };
+        Some(Self {
+            message_id,
+            log_unit_id,
+            level,
+        })
+    }
+}
+
+/// Dedicated connection listening for entry notifications
+#[cfg(feature = "postgres")]
+struct Listener {
+    /// Keeps the connection open
+    _client: Client,
+    /// The task holds the only strong sender, so subscriptions end when the connection does
+    sender: broadcast::WeakSender<EntryNotification>,
+    task: JoinHandle<()>,
+}
+
+/// Stores log units and entries in PostgreSQL.
+///
+/// `notify_on_insert` requires PostgreSQL 14 or newer, which added
+/// `CREATE OR REPLACE TRIGGER`.
 #[cfg(feature = "postgres")]
 pub struct PostgresDestination {
     client: Arc<Client>,
+    /// Drives `client`; aborted on shutdown
+    connection: JoinHandle<()>,
+    writes: WriteGate,
     config: PostgresConfig,
-    console: ConsoleDestination,
+    /// End of the newest partition known to exist as Unix seconds, or
+    /// `i64::MIN` before partitions were first checked
+    partitions_until: AtomicI64,
+    /// Serializes the creation of partitions
+    partition_creation: Mutex<()>,
+    /// Started by the first subscription
+    listener: Mutex<Option<Listener>>,
 }
 
 #[cfg(feature = "postgres")]
 impl PostgresDestination {
     pub async fn new(config: PostgresConfig) -> LogResult<Self> {
+        if config.purge_batch_size <= 0 {
+            return Err(format!("purge_batch_size must be positive, got {}", config.purge_batch_size).into());
+        }
+
         let (client, connection) = tokio_postgres::connect(&config.connection_string, NoTls).await?;
 
         // Spawn the connection task
-        tokio::spawn(async move {
+        let connection = tokio::spawn(async move {
             if let Err(e) = connection.await {
                 eprintln!("PostgreSQL connection error: {}", e);
             }
@@ -54,8 +246,12 @@ impl PostgresDestination {
 
         let destination = Self {
             client: Arc::new(client),
+            connection,
+            writes: WriteGate::new(),
             config: config.clone(),
-            console: ConsoleDestination::new(),
+            partitions_until: AtomicI64::new(i64::MIN),
+            partition_creation: Mutex::new(()),
+            listener: Mutex::new(None),
         };
 
         // Create tables if they don't exist
@@ -64,6 +260,40 @@ impl PostgresDestination {
         Ok(destination)
     }
 
+    /// Connects to tables created before, without changing the schema.
+    ///
+    /// Unlike `new`, no table, column, partition or trigger is created, so
+    /// readers such as the `ironscribe` CLI need no DDL privileges. The tables
+    /// must exist with the columns of this version, including `trace_id` and
+    /// `span_id` (see `add_optional_columns`), and `subscribe` only works if a
+    /// writer installed the trigger with `notify_on_insert`.
+    pub async fn connect_existing(config: PostgresConfig) -> LogResult<Self> {
+        let (client, connection) = tokio_postgres::connect(&config.connection_string, NoTls).await?;
+        let connection = tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                eprintln!("PostgreSQL connection error: {}", e);
+            }
+        });
+
+        for table in [&config.log_units_table, &config.log_entries_table] {
+            let exists: bool = client.query_one("SELECT to_regclass($1) IS NOT NULL", &[table]).await?.get(0);
+            if !exists {
+                connection.abort();
+                return Err(format!("Table {} does not exist", table).into());
+            }
+        }
+
+        Ok(Self {
+            client: Arc::new(client),
+            connection,
+            writes: WriteGate::new(),
+            config,
+            partitions_until: AtomicI64::new(i64::MIN),
+            partition_creation: Mutex::new(()),
+            listener: Mutex::new(None),
+        })
+    }
+
     pub async fn with_default_config() -> LogResult<Self> {
         Self::new(PostgresConfig::default()).await
     }
@@ -81,7 +311,171 @@ impl PostgresDestination {
             self.config.log_units_table
         );
 
-        // Create log_entries table
+        self.client.execute(&create_units_table, &[]).await?;
+
+        match &self.config.partitioning {
+            Some(partitioning) => self.create_partitioned_entries_table(partitioning).await?,
+            None => self.create_entries_table().await?,
+        }
+
+        self.add_optional_columns().await?;
+        if self.config.notify_on_insert {
+            self.create_notify_trigger().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Name for an object belonging to the entries table, without schema.
+    ///
+    /// Index and trigger names cannot be schema-qualified.
+    fn entries_object_name(&self, suffix: &str) -> String {
+        let table = &self.config.log_entries_table;
+        let table = table.rsplit('.').next().unwrap_or(table);
+        format!("{}_{}", table, suffix)
+    }
+
+    /// Channel notified with `message_id,log_unit_id,level` for every inserted entry
+    fn notify_channel(&self) -> String {
+        format!("{}_inserted", self.config.log_entries_table.replace('.', "_"))
+    }
+
+    /// Returns whether the trigger installed by `create_notify_trigger` exists
+    async fn notify_trigger_exists(&self) -> LogResult<bool> {
+        let query = "SELECT EXISTS (SELECT 1 FROM pg_trigger WHERE tgrelid = to_regclass($1) AND tgname = $2)";
+        let row = self.client
+            .query_one(query, &[&self.config.log_entries_table, &self.entries_object_name("notify")])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    /// Installs the trigger that announces inserted entries on `notify_channel`
+    async fn create_notify_trigger(&self) -> LogResult<()> {
+        let create_function = format!(
+            r#"
+            CREATE OR REPLACE FUNCTION {0}_notify() RETURNS TRIGGER AS $$
+            BEGIN
+                PERFORM pg_notify('{1}', NEW.message_id || ',' || NEW.log_unit_id || ',' || NEW.level);
+                RETURN NEW;
+            END;
+            $$ LANGUAGE plpgsql
+            "#,
+            self.config.log_entries_table,
+            self.notify_channel()
+        );
+        let create_trigger = format!(
+            "CREATE OR REPLACE TRIGGER {1} AFTER INSERT ON {0} FOR EACH ROW EXECUTE FUNCTION {0}_notify()",
+            self.config.log_entries_table,
+            self.entries_object_name("notify")
+        );
+
+        self.client.execute(&create_function, &[]).await?;
+        self.client.execute(&create_trigger, &[]).await?;
+
+        Ok(())
+    }
+
+    /// Returns a receiver for entry notifications, starting the listener connection if needed
+    async fn notifications(&self) -> LogResult<broadcast::Receiver<EntryNotification>> {
+        let mut listener = self.listener.lock().await;
+        if let Some(sender) = listener.as_ref().and_then(|listener| listener.sender.upgrade()) {
+            return Ok(sender.subscribe());
+        }
+
+        let (client, mut connection) = tokio_postgres::connect(&self.config.connection_string, NoTls).await?;
+        let (sender, receiver) = broadcast::channel(NOTIFICATION_CAPACITY);
+
+        let weak_sender = sender.downgrade();
+        let task = tokio::spawn(async move {
+            let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                match message {
+                    Ok(AsyncMessage::Notification(notification)) => {
+                        if let Some(notification) = EntryNotification::parse(notification.payload()) {
+                            let _ = sender.send(notification);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        eprintln!("PostgreSQL listener connection error: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        client.batch_execute(&format!("LISTEN \"{}\"", self.notify_channel())).await?;
+        *listener = Some(Listener {
+            _client: client,
+            sender: weak_sender,
+            task,
+        });
+
+        Ok(receiver)
+    }
+
+    /// Adds the generated `search_vector` column used by `search_entries` and its
+    /// GIN index to the entries table, replacing the column if it exists.
+    ///
+    /// This is a migration step that `new` does not run: adding a stored
+    /// generated column rewrites every row of the table while holding an
+    /// `ACCESS EXCLUSIVE` lock on it. Run it once before searching, and again
+    /// after changing `text_search_config`.
+    pub async fn create_search_index(&self) -> LogResult<()> {
+        // Resolving the configuration validates it; its OID keeps the name out of the SQL
+        let text_search_config: u32 = self.client
+            .query_one("SELECT $1::TEXT::regconfig::oid", &[&self.config.text_search_config])
+            .await?
+            .get(0);
+        let add_search_column = format!(
+            r#"
+            ALTER TABLE {}
+                DROP COLUMN IF EXISTS search_vector,
+                ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (to_tsvector({}::oid::regconfig, message)) STORED
+            "#,
+            self.config.log_entries_table,
+            text_search_config
+        );
+        let create_search_index = format!(
+            "CREATE INDEX IF NOT EXISTS {} ON {} USING GIN (search_vector)",
+            self.entries_object_name("search_idx"),
+            self.config.log_entries_table
+        );
+
+        self.client.execute(&add_search_column, &[]).await?;
+        self.client.execute(&create_search_index, &[]).await?;
+
+        Ok(())
+    }
+
+    /// Adds the source location, error and trace columns, which tables created by older versions lack.
+    ///
+    /// Tables created before entries carried a trace context gain the nullable
+    /// `trace_id` and `span_id` columns here. Where the writer may not alter
+    /// the table, run `ALTER TABLE log_entries ADD COLUMN trace_id TEXT, ADD
+    /// COLUMN span_id TEXT` once before upgrading.
+    async fn add_optional_columns(&self) -> LogResult<()> {
+        let add_optional_columns = format!(
+            r#"
+            ALTER TABLE {}
+                ADD COLUMN IF NOT EXISTS source_file TEXT,
+                ADD COLUMN IF NOT EXISTS source_line INTEGER,
+                ADD COLUMN IF NOT EXISTS source_column INTEGER,
+                ADD COLUMN IF NOT EXISTS module_path TEXT,
+                ADD COLUMN IF NOT EXISTS error_chain TEXT[],
+                ADD COLUMN IF NOT EXISTS backtrace TEXT,
+                ADD COLUMN IF NOT EXISTS trace_id TEXT,
+                ADD COLUMN IF NOT EXISTS span_id TEXT
+            "#,
+            self.config.log_entries_table
+        );
+
+        self.client.execute(&add_optional_columns, &[]).await?;
+
+        Ok(())
+    }
+
+    async fn create_entries_table(&self) -> LogResult<()> {
         let create_entries_table = format!(
             r#"
             CREATE TABLE IF NOT EXISTS {} (
@@ -98,12 +492,254 @@ impl PostgresDestination {
             self.config.log_units_table
         );
 
-        self.client.execute(&create_units_table, &[]).await?;
         self.client.execute(&create_entries_table, &[]).await?;
 
         Ok(())
     }
 
+    async fn create_partitioned_entries_table(&self, partitioning: &PartitionConfig) -> LogResult<()> {
+        let table = &self.config.log_entries_table;
+
+        // An existing plain table cannot be turned into a partitioned one in place
+        let relkind = self.client
+            .query_opt("SELECT relkind::TEXT FROM pg_class WHERE oid = to_regclass($1)", &[table])
+            .await?
+            .map(|row| row.get::<_, String>(0));
+        if relkind.as_deref().is_some_and(|relkind| relkind != "p") {
+            return Err(format!("Table {} exists but is not partitioned", table).into());
+        }
+
+        let create_entries_table = format!(
+            r#"
+            CREATE TABLE IF NOT EXISTS {0} (
+                log_unit_id UUID NOT NULL,
+                message_id UUID NOT NULL,
+                level INTEGER NOT NULL,
+                message TEXT NOT NULL,
+                message_type VARCHAR NOT NULL,
+                timestamp TIMESTAMPTZ NOT NULL,
+                PRIMARY KEY (message_id, timestamp),
+                FOREIGN KEY (log_unit_id) REFERENCES {1} (id)
+            ) PARTITION BY RANGE (timestamp)
+            "#,
+            table,
+            self.config.log_units_table
+        );
+        let create_unit_index = format!(
+            "CREATE INDEX IF NOT EXISTS {} ON {} (log_unit_id, timestamp)",
+            self.entries_object_name("log_unit_id_idx"),
+            table
+        );
+        // Catches entries outside of the pre-created range so writes never fail
+        let create_default_partition = format!(
+            "CREATE TABLE IF NOT EXISTS {0}_default PARTITION OF {0} DEFAULT",
+            table
+        );
+
+        self.client.execute(&create_entries_table, &[]).await?;
+        self.client.execute(&create_unit_index, &[]).await?;
+        self.client.execute(&create_default_partition, &[]).await?;
+
+        self.ensure_partitions(partitioning, Utc::now()).await
+    }
+
+    /// Makes sure the partition containing `timestamp` and the configured number
+    /// of partitions after it exist.
+    ///
+    /// Only queries the database once `timestamp` reaches the newest known partition.
+    async fn ensure_partitions(&self, partitioning: &PartitionConfig, timestamp: DateTime<Utc>) -> LogResult<()> {
+        let interval = partitioning.interval;
+        let current = interval.start_of(timestamp);
+        if current.timestamp() < self.partitions_until.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let _creating = self.partition_creation.lock().await;
+        let known_until = self.partitions_until.load(Ordering::Acquire);
+        if current.timestamp() < known_until {
+            return Ok(());
+        }
+        let partitions_until = DateTime::from_timestamp(known_until, 0).filter(|_| known_until != i64::MIN);
+
+        let mut end = interval.next(current);
+        for _ in 0..partitioning.premake {
+            end = interval.next(end);
+        }
+
+        let mut start = partitions_until.unwrap_or(current);
+        while start < end {
+            let next = interval.next(start);
+            let query = format!(
+                "CREATE TABLE IF NOT EXISTS {0}_p{1} PARTITION OF {0} FOR VALUES FROM ('{2}') TO ('{3}')",
+                self.config.log_entries_table,
+                start.format("%Y%m%d"),
+                start.to_rfc3339(),
+                next.to_rfc3339()
+            );
+            self.client.execute(&query, &[]).await?;
+            start = next;
+        }
+
+        self.partitions_until.store(end.timestamp(), Ordering::Release);
+        Ok(())
+    }
+
+    /// Detaches, and unless configured otherwise drops, every partition that
+    /// only holds entries older than `before`.
+    ///
+    /// Returns the number of affected partitions.
+    async fn expire_partitions(&self, partitioning: &PartitionConfig, before: DateTime<Utc>) -> LogResult<u64> {
+        let rows = self.client
+            .query(
+                "SELECT inhrelid::regclass::TEXT FROM pg_inherits WHERE inhparent = to_regclass($1)",
+                &[&self.config.log_entries_table],
+            )
+            .await?;
+
+        let mut expired = 0;
+        for row in rows {
+            let partition: String = row.get(0);
+            let Some(start) = Self::partition_start(&partition) else {
+                continue;
+            };
+            if partitioning.interval.next(start) > before {
+                continue;
+            }
+
+            let detach = format!(
+                "ALTER TABLE {} DETACH PARTITION {}",
+                self.config.log_entries_table,
+                partition
+            );
+            self.client.execute(&detach, &[]).await?;
+            if partitioning.drop_expired {
+                self.client.execute(&format!("DROP TABLE {}", partition), &[]).await?;
+            }
+            expired += 1;
+        }
+
+        Ok(expired)
+    }
+
+    /// Deletes the entries older than `before` from the default partition,
+    /// which `expire_partitions` never detaches.
+    ///
+    /// Returns the number of deleted entries.
+    async fn purge_default_partition(&self, before: DateTime<Utc>) -> LogResult<u64> {
+        let query = format!(
+            "DELETE FROM {0}_default WHERE message_id IN (SELECT message_id FROM {0}_default WHERE timestamp < $2 LIMIT $1)",
+            self.config.log_entries_table
+        );
+
+        self.delete_in_batches(&query, &[&before]).await
+    }
+
+    /// Parses the start of a partition from a `<table>_pYYYYMMDD` name
+    fn partition_start(partition: &str) -> Option<DateTime<Utc>> {
+        let (_, suffix) = partition.rsplit_once("_p")?;
+        let date = NaiveDate::parse_from_str(suffix, "%Y%m%d").ok()?;
+        Some(PartitionInterval::midnight(date))
+    }
+
+    /// Runs a batched `DELETE` until no more rows match, returning the total row count.
+    ///
+    /// `query` must delete at most `$1` rows per execution; `params` are bound from `$2` on.
+    async fn delete_in_batches(
+        &self,
+        query: &str,
+        params: &[&(dyn tokio_postgres::types::ToSql + Sync)],
+    ) -> LogResult<u64> {
+        let mut all_params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> =
+            vec![&self.config.purge_batch_size];
+        all_params.extend_from_slice(params);
+
+        let mut total = 0;
+        loop {
+            let deleted = self.client.execute(query, &all_params).await?;
+            total += deleted;
+            if deleted < self.config.purge_batch_size as u64 {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Columns read by `entry_from_row`, prefixed with the table alias `e`
+    const ENTRY_COLUMNS: &'static str = "e.log_unit_id, e.message_id, e.level, e.message, e.message_type, e.timestamp, \
+        e.source_file, e.source_line, e.source_column, e.module_path, e.error_chain, e.backtrace, e.trace_id, e.span_id";
+
+    /// Number of columns in `ENTRY_COLUMNS`
+    const ENTRY_COLUMN_COUNT: usize = 14;
+
+    /// Reads an entry from the first `ENTRY_COLUMN_COUNT` columns of `row`
+    fn entry_from_row(row: &tokio_postgres::Row) -> LogResult<LogEntry> {
+        let level = Self::int_to_level(row.get(2));
+
+        let message_type_str: String = row.get(4);
+        let message_type = Self::string_to_message_type(&message_type_str)?;
+
+        let source_file: Option<String> = row.get(6);
+        let location = source_file.map(|file| SourceLocation {
+            file,
+            line: row.get::<_, Option<i32>>(7).unwrap_or_default() as u32,
+            column: row.get::<_, Option<i32>>(8).unwrap_or_default() as u32,
+            module_path: row.get::<_, Option<String>>(9).unwrap_or_default(),
+        });
+
+        let error_chain: Option<Vec<String>> = row.get(10);
+        let error = error_chain.map(|chain| ErrorDetails {
+            chain,
+            backtrace: row.get(11),
+        });
+
+        let trace_id: Option<String> = row.get(12);
+        let trace = trace_id.map(|trace_id| TraceContext {
+            trace_id,
+            span_id: row.get::<_, Option<String>>(13).unwrap_or_default(),
+        });
+
+        Ok(LogEntry {
+            log_unit_id: row.get(0),
+            message_id: row.get(1),
+            level,
+            message: row.get(3),
+            message_type,
+            timestamp: row.get(5),
+            location,
+            error,
+            trace,
+        })
+    }
+
+    fn int_to_level(level: i32) -> LogLevel {
+        match level {
+            0 => LogLevel::Error,
+            1 => LogLevel::Warning,
+            2 => LogLevel::Info,
+            3 => LogLevel::Success,
+            _ => LogLevel::Info, // Default fallback
+        }
+    }
+
+    /// `FROM` and `WHERE` clauses selecting the entries matching a `StatsFilter`.
+    ///
+    /// The entries table is aliased `e` and the units table `u`; the filter is
+    /// bound through `StatsParams` as `$1` to `$5`.
+    fn stats_source(&self) -> String {
+        format!(
+            r#"
+            FROM {} e
+            LEFT JOIN {} u ON u.id = e.log_unit_id
+            WHERE ($1::timestamptz IS NULL OR e.timestamp >= $1)
+              AND ($2::timestamptz IS NULL OR e.timestamp < $2)
+              AND ($3::int IS NULL OR e.level <= $3)
+              AND ($4::varchar IS NULL OR u.external_id = $4)
+              AND ($5::varchar IS NULL OR e.module_path = $5 OR starts_with(e.module_path, $5 || '::'))
+            "#,
+            self.config.log_entries_table,
+            self.config.log_units_table
+        )
+    }
+
     fn message_type_to_string(msg_type: LogMessageType) -> &'static str {
         match msg_type {
             LogMessageType::Error => "Error",
@@ -127,9 +763,8 @@ impl PostgresDestination {
 #[cfg(feature = "postgres")]
 #[async_trait]
 impl LogService for PostgresDestination {
-    async fn create_log_unit(&self, external_id: String) -> LogResult<LogUnit> {
-        let log_unit = LogUnit::new(external_id);
-
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        let _write = self.writes.enter().await?;
         // Store in PostgreSQL
         let query = format!(
             "INSERT INTO {} (id, external_id, timestamp) VALUES ($1, $2, $3)",
@@ -141,19 +776,31 @@ impl LogService for PostgresDestination {
             &[&log_unit.log_unit_id, &log_unit.external_id, &log_unit.timestamp]
         ).await?;
 
-        // Also log to console
-        self.console.create_log_unit(log_unit.external_id.clone()).await?;
-
-        Ok(log_unit)
+        Ok(())
     }
 
     async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        let _write = self.writes.enter().await?;
         // Store in PostgreSQL
         let query = format!(
-            "INSERT INTO {} (log_unit_id, message_id, level, message, message_type, timestamp) VALUES ($1, $2, $3, $4, $5, $6)",
+            r#"
+            INSERT INTO {} (log_unit_id, message_id, level, message, message_type, timestamp,
+                            source_file, source_line, source_column, module_path, error_chain, backtrace,
+                            trace_id, span_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            "#,
             self.config.log_entries_table
         );
 
+        // Partitions are only made up to `premake` ahead of now, whatever the
+        // entry's timestamp; outliers go to the default partition
+        if let Some(partitioning) = &self.config.partitioning {
+            self.ensure_partitions(partitioning, Utc::now()).await?;
+        }
+
+        let location = entry.location.as_ref();
+        let error = entry.error.as_ref();
+        let trace = entry.trace.as_ref();
         self.client.execute(
             &query,
             &[
@@ -162,49 +809,30 @@ impl LogService for PostgresDestination {
                 &(entry.level as i32),
                 &entry.message,
                 &Self::message_type_to_string(entry.message_type),
-                &entry.timestamp
+                &entry.timestamp,
+                &location.map(|location| location.file.as_str()),
+                &location.map(|location| location.line as i32),
+                &location.map(|location| location.column as i32),
+                &location.map(|location| location.module_path.as_str()),
+                &error.map(|error| &error.chain),
+                &error.and_then(|error| error.backtrace.as_deref()),
+                &trace.map(|trace| trace.trace_id.as_str()),
+                &trace.map(|trace| trace.span_id.as_str()),
             ]
         ).await?;
 
-        // Also log to console
-        self.console.log(entry).await?;
-
         Ok(())
     }
 
     async fn get_log_entries(&self, log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
         let query = format!(
-            "SELECT log_unit_id, message_id, level, message, message_type, timestamp FROM {} WHERE log_unit_id = $1 ORDER BY timestamp",
+            "SELECT {} FROM {} e WHERE e.log_unit_id = $1 ORDER BY e.timestamp",
+            Self::ENTRY_COLUMNS,
             self.config.log_entries_table
         );
 
         let rows = self.client.query(&query, &[&log_unit_id]).await?;
-        let mut entries = Vec::new();
-
-        for row in rows {
-            let level_int: i32 = row.get(2);
-            let level = match level_int {
-                0 => LogLevel::Error,
-                1 => LogLevel::Warning,
-                2 => LogLevel::Info,
-                3 => LogLevel::Success,
-                _ => LogLevel::Info, // Default fallback
-            };
-
-            let message_type_str: String = row.get(4);
-            let message_type = Self::string_to_message_type(&message_type_str)?;
-
-            entries.push(LogEntry {
-                log_unit_id: row.get(0),
-                message_id: row.get(1),
-                level,
-                message: row.get(3),
-                message_type,
-                timestamp: row.get(5),
-            });
-        }
-
-        Ok(entries)
+        rows.iter().map(Self::entry_from_row).collect()
     }
 
     async fn get_log_unit(&self, log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
@@ -245,6 +873,475 @@ impl LogService for PostgresDestination {
 
         Ok(units)
     }
+
+    async fn list_log_units(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> LogResult<Vec<LogUnit>> {
+        let query = format!(
+            r#"
+            SELECT id, external_id, timestamp FROM {}
+            WHERE ($1::timestamptz IS NULL OR timestamp >= $1)
+              AND ($2::timestamptz IS NULL OR timestamp < $2)
+            ORDER BY timestamp
+            "#,
+            self.config.log_units_table
+        );
+
+        let rows = self.client.query(&query, &[&from, &to]).await?;
+        Ok(rows
+            .iter()
+            .map(|row| LogUnit {
+                log_unit_id: row.get(0),
+                external_id: row.get(1),
+                timestamp: row.get(2),
+            })
+            .collect())
+    }
+
+    async fn search_entries(&self, text: &str, options: &SearchOptions) -> LogResult<Vec<SearchHit>> {
+        let query = format!(
+            r#"
+            SELECT {2},
+                   u.id, u.external_id, u.timestamp,
+                   ts_headline($9::TEXT::regconfig, e.message, q, $2) AS snippet,
+                   ts_rank(e.search_vector, q) AS score
+            FROM {0} e
+            CROSS JOIN websearch_to_tsquery($9::TEXT::regconfig, $1) q
+            LEFT JOIN {1} u ON u.id = e.log_unit_id
+            WHERE e.search_vector @@ q
+              AND ($3::INTEGER IS NULL OR e.level <= $3)
+              AND ($4::VARCHAR IS NULL OR u.external_id = $4)
+              AND ($5::TIMESTAMPTZ IS NULL OR e.timestamp >= $5)
+              AND ($6::TIMESTAMPTZ IS NULL OR e.timestamp < $6)
+              AND ($8::VARCHAR IS NULL OR e.module_path = $8 OR starts_with(e.module_path, $8 || '::'))
+            ORDER BY score DESC, e.timestamp DESC
+            LIMIT $7
+            "#,
+            self.config.log_entries_table,
+            self.config.log_units_table,
+            Self::ENTRY_COLUMNS
+        );
+        let headline_options = format!(
+            "StartSel=\"{}\", StopSel=\"{}\"",
+            options.highlight_start,
+            options.highlight_end
+        );
+        let min_level = options.min_level.map(|level| level as i32);
+        let limit = options.limit as i64;
+
+        let rows = self.client.query(
+            &query,
+            &[
+                &text,
+                &headline_options,
+                &min_level,
+                &options.external_id,
+                &options.from,
+                &options.to,
+                &limit,
+                &options.module_path,
+                &self.config.text_search_config,
+            ]
+        ).await.map_err(|e| match e.code() {
+            Some(&SqlState::UNDEFINED_COLUMN) => {
+                format!("{} has no search_vector column, see PostgresDestination::create_search_index", self.config.log_entries_table).into()
+            }
+            _ => Box::new(e) as Box<dyn std::error::Error + Send + Sync>,
+        })?;
+
+        rows.iter()
+            .map(|row| {
+                let columns = Self::ENTRY_COLUMN_COUNT;
+                let log_unit_id: Option<Uuid> = row.get(columns);
+                Ok(SearchHit {
+                    entry: Self::entry_from_row(row)?,
+                    log_unit: log_unit_id.map(|log_unit_id| LogUnit {
+                        log_unit_id,
+                        external_id: row.get(columns + 1),
+                        timestamp: row.get(columns + 2),
+                    }),
+                    snippet: row.get(columns + 3),
+                    score: row.get(columns + 4),
+                })
+            })
+            .collect()
+    }
+
+    /// Counts the matching entries with a single `GROUP BY` on the level
+    async fn count_by_level(&self, filter: &StatsFilter) -> LogResult<LevelCounts> {
+        let query = format!("SELECT e.level, count(*) {} GROUP BY e.level", self.stats_source());
+        let params = StatsParams::from(filter);
+
+        let rows = self.client.query(&query, &params.as_params()).await?;
+        let mut counts = LevelCounts::default();
+        for row in rows {
+            counts.add(Self::int_to_level(row.get(0)), row.get::<_, i64>(1) as u64);
+        }
+        Ok(counts)
+    }
+
+    async fn entry_histogram(&self, filter: &StatsFilter, bucket: TimeBucket) -> LogResult<Vec<HistogramBucket>> {
+        let query = format!(
+            r#"
+            SELECT to_timestamp(floor(extract(epoch FROM e.timestamp) / {0}) * {0}) AS bucket, e.level, count(*)
+            {1}
+            GROUP BY bucket, e.level
+            ORDER BY bucket
+            "#,
+            bucket.seconds(),
+            self.stats_source()
+        );
+        let params = StatsParams::from(filter);
+
+        let rows = self.client.query(&query, &params.as_params()).await?;
+        let mut buckets: Vec<HistogramBucket> = Vec::new();
+        for row in rows {
+            let start: DateTime<Utc> = row.get(0);
+            if buckets.last().is_none_or(|last| last.start != start) {
+                buckets.push(HistogramBucket { start, counts: LevelCounts::default() });
+            }
+            if let Some(last) = buckets.last_mut() {
+                last.counts.add(Self::int_to_level(row.get(1)), row.get::<_, i64>(2) as u64);
+            }
+        }
+        Ok(buckets)
+    }
+
+    async fn top_units(&self, filter: &StatsFilter, ranking: UnitRanking, limit: usize) -> LogResult<Vec<UnitStats>> {
+        let order = match ranking {
+            UnitRanking::Entries => "count(*)",
+            UnitRanking::Errors => "count(*) FILTER (WHERE e.level = 0)",
+        };
+        let query = format!(
+            r#"
+            SELECT e.log_unit_id, u.id, u.external_id, u.timestamp,
+                   count(*) FILTER (WHERE e.level = 0),
+                   count(*) FILTER (WHERE e.level = 1),
+                   count(*) FILTER (WHERE e.level = 2),
+                   count(*) FILTER (WHERE e.level = 3)
+            {}
+            GROUP BY e.log_unit_id, u.id, u.external_id, u.timestamp
+            ORDER BY {} DESC, count(*) DESC
+            LIMIT $6
+            "#,
+            self.stats_source(),
+            order
+        );
+        let params = StatsParams::from(filter);
+        let limit = limit as i64;
+
+        let mut query_params = params.as_params().to_vec();
+        query_params.push(&limit);
+        let rows = self.client.query(&query, &query_params).await?;
+        Ok(rows
+            .iter()
+            .map(|row| UnitStats {
+                log_unit_id: row.get(0),
+                log_unit: row.get::<_, Option<Uuid>>(1).map(|log_unit_id| LogUnit {
+                    log_unit_id,
+                    external_id: row.get(2),
+                    timestamp: row.get(3),
+                }),
+                counts: LevelCounts {
+                    error: row.get::<_, i64>(4) as u64,
+                    warning: row.get::<_, i64>(5) as u64,
+                    info: row.get::<_, i64>(6) as u64,
+                    success: row.get::<_, i64>(7) as u64,
+                },
+            })
+            .collect())
+    }
+
+    async fn counts_by_external_id(&self, filter: &StatsFilter) -> LogResult<Vec<ExternalIdStats>> {
+        let query = format!(
+            r#"
+            SELECT u.external_id, e.level, count(*)
+            {} AND u.external_id IS NOT NULL
+            GROUP BY u.external_id, e.level
+            ORDER BY u.external_id
+            "#,
+            self.stats_source()
+        );
+        let params = StatsParams::from(filter);
+
+        let rows = self.client.query(&query, &params.as_params()).await?;
+        let mut groups: Vec<ExternalIdStats> = Vec::new();
+        for row in rows {
+            let external_id: String = row.get(0);
+            if groups.last().is_none_or(|last| last.external_id != external_id) {
+                groups.push(ExternalIdStats { external_id, counts: LevelCounts::default() });
+            }
+            if let Some(last) = groups.last_mut() {
+                last.counts.add(Self::int_to_level(row.get(1)), row.get::<_, i64>(2) as u64);
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Subscribes to entries inserted into the entries table by any writer,
+    /// using `LISTEN`/`NOTIFY`.
+    ///
+    /// Requires the trigger installed by a destination with `notify_on_insert`.
+    async fn subscribe(&self, filter: SubscriptionFilter) -> LogResult<Subscription> {
+        if !self.notify_trigger_exists().await? {
+            return Err(format!(
+                "{} has no notify trigger; subscribe requires a writer with notify_on_insert enabled in its PostgresConfig",
+                self.config.log_entries_table
+            ).into());
+        }
+        let receiver = self.notifications().await?;
+        let query = format!(
+            r#"
+            SELECT {}
+            FROM {} e
+            JOIN {} u ON u.id = e.log_unit_id
+            WHERE e.message_id = $1 AND ($2::VARCHAR IS NULL OR u.external_id = $2)
+            "#,
+            Self::ENTRY_COLUMNS,
+            self.config.log_entries_table,
+            self.config.log_units_table
+        );
+        let client = Arc::clone(&self.client);
+        let missed = Arc::new(AtomicU64::new(0));
+
+        let entries = stream::unfold(
+            (receiver, Arc::clone(&missed)),
+            move |(mut receiver, missed)| {
+                let (client, query, filter) = (Arc::clone(&client), query.clone(), filter.clone());
+                async move {
+                    loop {
+                        match receiver.recv().await {
+                            Ok(notification) => {
+                                if !filter.matches_entry(notification.log_unit_id, notification.level) {
+                                    continue;
+                                }
+                                let row = client
+                                    .query_opt(&query, &[&notification.message_id, &filter.external_id])
+                                    .await;
+                                match row.map(|row| row.map(|row| Self::entry_from_row(&row))) {
+                                    Ok(Some(Ok(entry))) => return Some((entry, (receiver, missed))),
+                                    Ok(_) => {}
+                                    Err(e) => eprintln!("Failed to load notified log entry: {}", e),
+                                }
+                            }
+                            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                                missed.fetch_add(skipped, Ordering::Relaxed);
+                            }
+                            Err(broadcast::error::RecvError::Closed) => return None,
+                        }
+                    }
+                }
+            },
+        );
+
+        Ok(Subscription::with_missed_counter(entries, missed))
+    }
+
+    async fn delete_log_unit(&self, log_unit_id: Uuid) -> LogResult<bool> {
+        let query = format!(
+            "WITH deleted_entries AS (DELETE FROM {} WHERE log_unit_id = $1) DELETE FROM {} WHERE id = $1",
+            self.config.log_entries_table,
+            self.config.log_units_table
+        );
+
+        let deleted = self.client.execute(&query, &[&log_unit_id]).await?;
+        Ok(deleted > 0)
+    }
+
+    async fn purge_entries(&self, before: DateTime<Utc>, level: Option<LogLevel>) -> LogResult<u64> {
+        let level = level.map(|level| level as i32);
+        let query = format!(
+            "DELETE FROM {0} WHERE message_id IN (SELECT message_id FROM {0} WHERE timestamp < $2 AND ($3::INTEGER IS NULL OR level = $3) LIMIT $1)",
+            self.config.log_entries_table
+        );
+
+        self.delete_in_batches(&query, &[&before, &level]).await
+    }
+
+    async fn purge_empty_log_units(&self, before: DateTime<Utc>) -> LogResult<u64> {
+        let query = format!(
+            "DELETE FROM {0} WHERE id IN (SELECT u.id FROM {0} u WHERE u.timestamp < $2 AND NOT EXISTS (SELECT 1 FROM {1} e WHERE e.log_unit_id = u.id) LIMIT $1)",
+            self.config.log_units_table,
+            self.config.log_entries_table
+        );
+
+        self.delete_in_batches(&query, &[&before]).await
+    }
+
+    async fn trim_log_units(&self, max_units: usize) -> LogResult<u64> {
+        let max_units = max_units as i64;
+        let query = format!(
+            r#"
+            WITH expired AS (
+                SELECT id FROM (
+                    SELECT id, ROW_NUMBER() OVER (PARTITION BY external_id ORDER BY timestamp DESC) AS rank
+                    FROM {0}
+                ) ranked
+                WHERE rank > $2
+                LIMIT $1
+            ),
+            deleted_entries AS (
+                DELETE FROM {1} WHERE log_unit_id IN (SELECT id FROM expired)
+            )
+            DELETE FROM {0} WHERE id IN (SELECT id FROM expired)
+            "#,
+            self.config.log_units_table,
+            self.config.log_entries_table
+        );
+
+        self.delete_in_batches(&query, &[&max_units]).await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> LogResult<PurgeReport> {
+        let mut report = PurgeReport::default();
+        if let Some(partitioning) = &self.config.partitioning {
+            report.partitions = self.expire_partitions(partitioning, before).await?;
+            report.entries = self.purge_default_partition(before).await?;
+        }
+
+        report.entries += self.purge_entries(before, None).await?;
+        report.units = self.purge_empty_log_units(before).await?;
+        Ok(report)
+    }
+
+    async fn apply_retention(&self, policy: &RetentionPolicy) -> LogResult<PurgeReport> {
+        let now = Utc::now();
+        let mut expired = PurgeReport::default();
+
+        if let Some(partitioning) = &self.config.partitioning {
+            self.ensure_partitions(partitioning, now).await?;
+            if let Some(before) = policy.full_cutoff(now) {
+                expired.partitions = self.expire_partitions(partitioning, before).await?;
+                expired.entries = self.purge_default_partition(before).await?;
+            }
+        }
+
+        let report = retention::apply(self, policy, now).await?;
+        Ok(PurgeReport {
+            entries: expired.entries + report.entries,
+            partitions: expired.partitions,
+            ..report
+        })
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        self.writes.drain().await;
+        Ok(())
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        let closed = self.writes.close(timeout).await;
+        if let Some(listener) = self.listener.lock().await.take() {
+            listener.task.abort();
+        }
+        self.connection.abort();
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> DateTime<Utc> {
+        PartitionInterval::midnight(NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap())
+    }
+
+    #[test]
+    fn test_partition_boundaries() {
+        let timestamp = at("2026-10-15") + chrono::Duration::hours(13);
+
+        assert_eq!(PartitionInterval::Daily.start_of(timestamp), at("2026-10-15"));
+        assert_eq!(PartitionInterval::Weekly.start_of(timestamp), at("2026-10-12"));
+        assert_eq!(PartitionInterval::Monthly.start_of(timestamp), at("2026-10-01"));
+
+        assert_eq!(PartitionInterval::Daily.next(at("2026-10-31")), at("2026-11-01"));
+        assert_eq!(PartitionInterval::Weekly.next(at("2026-12-28")), at("2027-01-04"));
+        assert_eq!(PartitionInterval::Monthly.next(at("2026-12-01")), at("2027-01-01"));
+    }
+
+    #[test]
+    fn test_partition_start_from_name() {
+        assert_eq!(
+            PostgresDestination::partition_start("public.log_entries_p20261012"),
+            Some(at("2026-10-12"))
+        );
+        assert_eq!(PostgresDestination::partition_start("log_entries_default"), None);
+    }
+
+    #[tokio::test]
+    async fn test_non_positive_purge_batch_size_is_rejected() {
+        for purge_batch_size in [0, -1] {
+            let config = PostgresConfig {
+                purge_batch_size,
+                ..Default::default()
+            };
+            let error = PostgresDestination::new(config).await.err().unwrap();
+            assert!(error.to_string().contains("purge_batch_size"));
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server at POSTGRES_URL or on localhost; run with --ignored"]
+    async fn test_subscriptions_end_when_the_listener_connection_fails() {
+        use futures::StreamExt;
+
+        let connection_string =
+            std::env::var("POSTGRES_URL").unwrap_or_else(|_| "host=localhost user=postgres dbname=ironscribe".to_string());
+        let config = PostgresConfig {
+            connection_string,
+            notify_on_insert: true,
+            ..Default::default()
+        };
+        let destination = PostgresDestination::new(config).await.unwrap();
+        let mut subscription = destination.subscribe(SubscriptionFilter::all()).await.unwrap();
+
+        let channel = destination.notify_channel();
+        destination
+            .client
+            .execute(
+                r#"SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE query = format('LISTEN "%s"', $1::TEXT)"#,
+                &[&channel],
+            )
+            .await
+            .unwrap();
+
+        let ended = tokio::time::timeout(Duration::from_secs(5), subscription.next()).await;
+        assert!(matches!(ended, Ok(None)));
+        // The next subscription connects again
+        assert!(destination.subscribe(SubscriptionFilter::all()).await.is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server at POSTGRES_URL or on localhost; run with --ignored"]
+    async fn test_connect_existing_changes_no_schema() {
+        let connection_string =
+            std::env::var("POSTGRES_URL").unwrap_or_else(|_| "host=localhost user=postgres dbname=ironscribe".to_string());
+        let suffix = Uuid::new_v4().simple();
+        let config = PostgresConfig {
+            connection_string,
+            log_units_table: format!("units_{}", suffix),
+            log_entries_table: format!("entries_{}", suffix),
+            ..Default::default()
+        };
+
+        let error = PostgresDestination::connect_existing(config.clone()).await.err().unwrap();
+        assert!(error.to_string().contains("does not exist"));
+
+        PostgresDestination::new(config.clone()).await.unwrap();
+        let reader = PostgresDestination::connect_existing(config.clone()).await.unwrap();
+        let error = reader.subscribe(SubscriptionFilter::all()).await.err().unwrap();
+        assert!(error.to_string().contains("no notify trigger"));
+
+        let writer_with_trigger = PostgresDestination::new(PostgresConfig {
+            notify_on_insert: true,
+            ..config.clone()
+        })
+        .await
+        .unwrap();
+        assert!(reader.subscribe(SubscriptionFilter::all()).await.is_ok());
+
+        let drop_tables = format!("DROP TABLE {}, {}", config.log_entries_table, config.log_units_table);
+        writer_with_trigger.client.batch_execute(&drop_tables).await.unwrap();
+    }
 }
 
 // Provide stub implementation when postgres feature is not enabled
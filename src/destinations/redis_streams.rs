@@ -0,0 +1,379 @@
+//! Redis Streams destination for lightweight live pipelines
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use redis::aio::ConnectionManager;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use uuid::Uuid;
+
+use crate::core::log_service::LogResult;
+use crate::core::{LogEntry, LogService, LogUnit};
+
+/// Number of stream entries fetched per `XRANGE`
+const PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct RedisStreamsConfig {
+    /// Connection URL, e.g. `redis://127.0.0.1:6379/0`
+    pub url: String,
+    /// Prefix of every key written
+    pub key_prefix: String,
+    /// Approximate maximum length of each unit's stream (`MAXLEN ~`); `None` keeps every entry
+    pub unit_stream_max_len: Option<usize>,
+    /// Approximate maximum length of the global stream; `None` keeps every entry
+    pub global_stream_max_len: Option<usize>,
+}
+
+impl Default for RedisStreamsConfig {
+    fn default() -> Self {
+        Self {
+            url: "redis://127.0.0.1:6379".to_string(),
+            key_prefix: "ironscribe".to_string(),
+            unit_stream_max_len: Some(10_000),
+            global_stream_max_len: Some(100_000),
+        }
+    }
+}
+
+/// Names of the keys written for a prefix
+struct Keys {
+    prefix: String,
+}
+
+impl Keys {
+    /// Stream of all entries
+    fn entries(&self) -> String {
+        format!("{}:entries", self.prefix)
+    }
+
+    /// Stream of the entries of one unit
+    fn unit_entries(&self, log_unit_id: Uuid) -> String {
+        format!("{}:unit:{}:entries", self.prefix, log_unit_id)
+    }
+
+    /// Hash holding a unit's fields
+    fn unit(&self, log_unit_id: impl std::fmt::Display) -> String {
+        format!("{}:unit:{}", self.prefix, log_unit_id)
+    }
+
+    /// Set of the IDs of the units with an external ID
+    fn external_id(&self, external_id: &str) -> String {
+        format!("{}:external_id:{}", self.prefix, external_id)
+    }
+
+    /// Sorted set of all unit IDs, scored by creation time in milliseconds
+    fn units(&self) -> String {
+        format!("{}:units", self.prefix)
+    }
+}
+
+/// Encodes a value the way serde names it, e.g. `Warning` for a level
+fn encode<T: Serialize>(value: &T) -> LogResult<String> {
+    Ok(match serde_json::to_value(value)? {
+        serde_json::Value::String(text) => text,
+        json => json.to_string(),
+    })
+}
+
+fn decode<T: DeserializeOwned>(fields: &HashMap<String, String>, name: &str) -> LogResult<T> {
+    let text = fields.get(name).ok_or_else(|| format!("Redis stream entry without {}", name))?;
+    Ok(serde_json::from_value(serde_json::Value::String(text.clone())).or_else(|_| serde_json::from_str(text))?)
+}
+
+fn decode_optional<T: DeserializeOwned>(fields: &HashMap<String, String>, name: &str) -> LogResult<Option<T>> {
+    fields.contains_key(name).then(|| decode(fields, name)).transpose()
+}
+
+fn timestamp(value: DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+fn parse_timestamp(fields: &HashMap<String, String>) -> LogResult<DateTime<Utc>> {
+    let text = fields.get("timestamp").ok_or("Redis record without timestamp")?;
+    Ok(DateTime::parse_from_rfc3339(text)?.with_timezone(&Utc))
+}
+
+/// Flat stream fields of an entry; location, error and trace context are JSON objects
+fn entry_fields(entry: &LogEntry) -> LogResult<Vec<(&'static str, String)>> {
+    let mut fields = vec![
+        ("log_unit_id", entry.log_unit_id.to_string()),
+        ("message_id", entry.message_id.to_string()),
+        ("level", encode(&entry.level)?),
+        ("message_type", encode(&entry.message_type)?),
+        ("message", entry.message.clone()),
+        ("timestamp", timestamp(entry.timestamp)),
+    ];
+    if let Some(location) = &entry.location {
+        fields.push(("location", encode(location)?));
+    }
+    if let Some(error) = &entry.error {
+        fields.push(("error", encode(error)?));
+    }
+    if let Some(trace) = &entry.trace {
+        fields.push(("trace", encode(trace)?));
+    }
+    Ok(fields)
+}
+
+fn entry_from_fields(fields: &HashMap<String, String>) -> LogResult<LogEntry> {
+    Ok(LogEntry {
+        log_unit_id: decode(fields, "log_unit_id")?,
+        message_id: decode(fields, "message_id")?,
+        level: decode(fields, "level")?,
+        message: fields.get("message").cloned().unwrap_or_default(),
+        message_type: decode(fields, "message_type")?,
+        timestamp: parse_timestamp(fields)?,
+        location: decode_optional(fields, "location")?,
+        error: decode_optional(fields, "error")?,
+        trace: decode_optional(fields, "trace")?,
+    })
+}
+
+fn unit_from_fields(fields: &HashMap<String, String>) -> LogResult<LogUnit> {
+    Ok(LogUnit {
+        log_unit_id: decode(fields, "log_unit_id")?,
+        external_id: fields.get("external_id").cloned().unwrap_or_default(),
+        timestamp: parse_timestamp(fields)?,
+    })
+}
+
+/// Destination writing entries to Redis Streams.
+///
+/// Every entry is appended to the stream of its unit and to a global stream,
+/// both trimmed with `MAXLEN ~`, as flat fields consumers can read without
+/// decoding (`level`, `message`, `timestamp`, ...). Units are hashes keyed by
+/// `log_unit_id`, indexed by external ID in sets and by creation time in a
+/// sorted set. With the default prefix the layout is:
+///
+/// - `ironscribe:entries`: stream of all entries
+/// - `ironscribe:unit:{log_unit_id}:entries`: stream of a unit's entries
+/// - `ironscribe:unit:{log_unit_id}`: hash of a unit
+/// - `ironscribe:external_id:{external_id}`: set of unit IDs
+/// - `ironscribe:units`: sorted set of unit IDs by creation time
+///
+/// Reads only see the entries still left in a unit's stream after trimming.
+pub struct RedisStreamsDestination {
+    connection: ConnectionManager,
+    config: RedisStreamsConfig,
+    keys: Keys,
+    closed: AtomicBool,
+}
+
+impl RedisStreamsDestination {
+    /// Connects to Redis; the connection is re-established when it drops
+    pub async fn new(config: RedisStreamsConfig) -> LogResult<Self> {
+        let client = redis::Client::open(config.url.as_str())?;
+        let connection = client.get_connection_manager().await?;
+        let keys = Keys { prefix: config.key_prefix.clone() };
+        Ok(Self { connection, config, keys, closed: AtomicBool::new(false) })
+    }
+
+    fn check_open(&self) -> LogResult<()> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err("destination is shut down".into());
+        }
+        Ok(())
+    }
+
+    /// Fetches the units with the given IDs, skipping missing ones, oldest first
+    async fn units(&self, log_unit_ids: Vec<String>) -> LogResult<Vec<LogUnit>> {
+        if log_unit_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut pipe = redis::pipe();
+        for id in &log_unit_ids {
+            pipe.hgetall(self.keys.unit(id));
+        }
+        let hashes: Vec<HashMap<String, String>> = pipe.query_async(&mut self.connection.clone()).await?;
+        let mut units = hashes
+            .iter()
+            .filter(|fields| !fields.is_empty())
+            .map(unit_from_fields)
+            .collect::<LogResult<Vec<_>>>()?;
+        units.sort_by_key(|unit| (unit.timestamp, unit.log_unit_id));
+        Ok(units)
+    }
+}
+
+/// Appends `XADD key [MAXLEN ~ n] * fields...` to `pipe`
+fn xadd(pipe: &mut redis::Pipeline, key: String, max_len: Option<usize>, fields: &[(&str, String)]) {
+    let command = pipe.cmd("XADD").arg(key);
+    if let Some(max_len) = max_len {
+        command.arg("MAXLEN").arg("~").arg(max_len);
+    }
+    command.arg("*");
+    for (name, value) in fields {
+        command.arg(*name).arg(value);
+    }
+    command.ignore();
+}
+
+#[async_trait]
+impl LogService for RedisStreamsDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        self.check_open()?;
+        let id = log_unit.log_unit_id;
+        let fields = [
+            ("log_unit_id", id.to_string()),
+            ("external_id", log_unit.external_id.clone()),
+            ("timestamp", timestamp(log_unit.timestamp)),
+        ];
+        redis::pipe()
+            .atomic()
+            .cmd("HSET")
+            .arg(self.keys.unit(id))
+            .arg(&fields)
+            .ignore()
+            .sadd(self.keys.external_id(&log_unit.external_id), id.to_string())
+            .ignore()
+            .zadd(self.keys.units(), id.to_string(), log_unit.timestamp.timestamp_millis())
+            .ignore()
+            .query_async::<()>(&mut self.connection.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        self.check_open()?;
+        let fields = entry_fields(&entry)?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        xadd(&mut pipe, self.keys.unit_entries(entry.log_unit_id), self.config.unit_stream_max_len, &fields);
+        xadd(&mut pipe, self.keys.entries(), self.config.global_stream_max_len, &fields);
+        pipe.query_async::<()>(&mut self.connection.clone()).await?;
+        Ok(())
+    }
+
+    async fn get_log_entries(&self, log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        let key = self.keys.unit_entries(log_unit_id);
+        let mut connection = self.connection.clone();
+        let mut entries = Vec::new();
+        let mut start = "-".to_string();
+        loop {
+            let page: Vec<(String, HashMap<String, String>)> = redis::cmd("XRANGE")
+                .arg(&key)
+                .arg(&start)
+                .arg("+")
+                .arg("COUNT")
+                .arg(PAGE_SIZE)
+                .query_async(&mut connection)
+                .await?;
+            for (_, fields) in &page {
+                entries.push(entry_from_fields(fields)?);
+            }
+            match page.last() {
+                // Exclusive range start, supported since Redis 6.2
+                Some((id, _)) if page.len() == PAGE_SIZE => start = format!("({}", id),
+                _ => return Ok(entries),
+            }
+        }
+    }
+
+    async fn get_log_unit(&self, log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        let fields: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(self.keys.unit(log_unit_id))
+            .query_async(&mut self.connection.clone())
+            .await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(unit_from_fields(&fields)?))
+    }
+
+    async fn get_log_units_by_external_id(&self, external_id: &str) -> LogResult<Vec<LogUnit>> {
+        let ids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(self.keys.external_id(external_id))
+            .query_async(&mut self.connection.clone())
+            .await?;
+        self.units(ids).await
+    }
+
+    async fn list_log_units(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> LogResult<Vec<LogUnit>> {
+        let min = from.map_or("-inf".to_string(), |from| from.timestamp_millis().to_string());
+        let max = to.map_or("+inf".to_string(), |to| to.timestamp_millis().to_string());
+        let ids: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(self.keys.units())
+            .arg(min)
+            .arg(max)
+            .query_async(&mut self.connection.clone())
+            .await?;
+        // Scores have millisecond precision, so the exact bounds are checked again
+        let units = self.units(ids).await?;
+        Ok(units
+            .into_iter()
+            .filter(|unit| from.is_none_or(|from| unit.timestamp >= from) && to.is_none_or(|to| unit.timestamp < to))
+            .collect())
+    }
+
+    async fn shutdown(&self, _timeout: std::time::Duration) -> LogResult<()> {
+        self.closed.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{SourceLocation, TraceContext};
+
+    /// Connects to the Redis at `REDIS_URL` or on localhost, under a fresh key prefix
+    async fn connect() -> RedisStreamsDestination {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        let config = RedisStreamsConfig {
+            url,
+            key_prefix: format!("ironscribe-test-{}", Uuid::new_v4()),
+            ..Default::default()
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(2), RedisStreamsDestination::new(config))
+            .await
+            .expect("timed out connecting to redis-server")
+            .expect("failed to connect to redis-server")
+    }
+
+    #[test]
+    fn test_entry_fields_round_trip() {
+        let trace = TraceContext::from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        let entry = LogEntry::warning(Uuid::new_v4(), "disk almost full".to_string())
+            .with_location(SourceLocation::new("src/disk.rs", 42, 9, "app::disk"))
+            .with_trace_context(trace);
+        let fields: HashMap<String, String> = entry_fields(&entry)
+            .unwrap()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        assert_eq!(fields["level"], "Warning");
+        assert_eq!(fields["message"], "disk almost full");
+        assert_eq!(entry_from_fields(&fields).unwrap(), entry);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a redis-server at REDIS_URL or on localhost; run with --ignored"]
+    async fn test_against_redis_server() {
+        let destination = connect().await;
+        let unit = destination.create_log_unit("job-1".to_string()).await.unwrap();
+        let other = destination.create_log_unit("job-1".to_string()).await.unwrap();
+        let mut entries = Vec::new();
+        for i in 0..3 {
+            let entry = LogEntry::info(unit.log_unit_id, format!("entry {}", i));
+            destination.log(entry.clone()).await.unwrap();
+            entries.push(entry);
+        }
+
+        assert_eq!(destination.get_log_entries(unit.log_unit_id).await.unwrap(), entries);
+        assert_eq!(destination.get_log_unit(unit.log_unit_id).await.unwrap(), Some(unit.clone()));
+        assert_eq!(destination.get_log_unit(Uuid::new_v4()).await.unwrap(), None);
+        let units = destination.get_log_units_by_external_id("job-1").await.unwrap();
+        assert_eq!(units, vec![unit.clone(), other.clone()]);
+        assert_eq!(destination.list_log_units(None, Some(other.timestamp)).await.unwrap(), vec![unit.clone()]);
+
+        let global: usize = redis::cmd("XLEN")
+            .arg(destination.keys.entries())
+            .query_async(&mut destination.connection.clone())
+            .await
+            .unwrap();
+        assert_eq!(global, 3);
+    }
+}
@@ -0,0 +1,510 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::core::{ExternalIdStats, HistogramBucket, LevelCounts, LogEntry, LogLevel, LogService, LogUnit, SearchHit, SearchOptions, StatsFilter, Subscription, SubscriptionFilter, TimeBucket, UnitRanking, UnitStats};
+use crate::core::log_service::LogResult;
+
+/// How often and how long a failed write is retried
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay, which doubles after each retry
+    pub max_backoff: Duration,
+    /// Fraction of each delay that is random, between 0 and 1
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Returns the delay before retry number `retry`, counting from 0
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let delay = self.initial_backoff.saturating_mul(2u32.saturating_pow(retry)).min(self.max_backoff);
+        delay.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * rand::random::<f64>())
+    }
+
+    /// Runs `attempt` until it succeeds, retrying the errors `classify` marks as
+    /// transient as often as the policy allows.
+    ///
+    /// A delay that comes with a transient failure, e.g. from a `Retry-After`
+    /// header, replaces the backoff, up to `max_backoff`.
+    pub async fn run<T, E, F, Fut>(&self, mut attempt: F, classify: impl Fn(&E) -> Failure) -> LogResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let mut retry = 0;
+        loop {
+            let error = match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            let retry_after = match classify(&error) {
+                Failure::Transient(retry_after) if retry < self.max_retries => retry_after,
+                _ => return Err(error.into()),
+            };
+            let delay = retry_after.map_or_else(|| self.backoff(retry), |delay| delay.min(self.max_backoff));
+            tokio::time::sleep(delay).await;
+            retry += 1;
+        }
+    }
+}
+
+/// How `RetryPolicy::run` handles a failed attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Retrying does not help
+    Permanent,
+    /// Worth retrying, after the given delay instead of the backoff if any
+    Transient(Option<Duration>),
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: 0.5,
+        }
+    }
+}
+
+/// When the circuit breaker of a `RetryingDestination` opens and how long it stays open
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failed writes after which writes are short-circuited
+    pub failure_threshold: u32,
+    /// How long writes are short-circuited before one write probes the destination
+    pub open_duration: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(30),
+        }
+    }
+}
+
+/// State of the circuit breaker of a `RetryingDestination`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Writes go to the destination
+    Closed,
+    /// Writes fail immediately
+    Open,
+    /// The next write, or the one in flight, probes whether the destination recovered
+    HalfOpen,
+}
+
+/// Counters of a `RetryingDestination`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryMetrics {
+    pub circuit_state: CircuitState,
+    /// Failed writes since the last successful one
+    pub consecutive_failures: u32,
+    /// Retries made so far
+    pub retries: u64,
+    /// Writes rejected because the circuit was open
+    pub short_circuited: u64,
+}
+
+/// Decides whether an error is transient and worth retrying
+pub type ErrorClassifier = Arc<dyn Fn(&(dyn Error + 'static)) -> bool + Send + Sync>;
+
+/// Returns whether `error`, or one of its sources, is a transient failure.
+///
+/// Connection and timeout errors are transient, as are database errors that
+/// report a lost connection, a server shutting down or a conflict with
+/// another transaction. Everything else, such as constraint violations, is
+/// permanent.
+pub fn is_transient(error: &(dyn Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if let Some(io_error) = error.downcast_ref::<io::Error>() {
+            return matches!(
+                io_error.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted
+                    | io::ErrorKind::UnexpectedEof
+            );
+        }
+        #[cfg(feature = "postgres")]
+        if let Some(postgres_error) = error.downcast_ref::<tokio_postgres::Error>() {
+            if postgres_error.is_closed() {
+                return true;
+            }
+            if let Some(code) = postgres_error.code() {
+                let code = code.code();
+                // Connection exceptions, insufficient resources, operator intervention
+                return ["08", "53", "57"].iter().any(|class| code.starts_with(class))
+                    // Serialization failure and deadlock
+                    || code == "40001"
+                    || code == "40P01";
+            }
+        }
+        #[cfg(feature = "mongo")]
+        if let Some(mongo_error) = error.downcast_ref::<mongodb::error::Error>() {
+            use mongodb::error::ErrorKind;
+            return mongo_error.contains_label(mongodb::error::RETRYABLE_WRITE_ERROR)
+                || matches!(
+                    mongo_error.kind.as_ref(),
+                    ErrorKind::Io(_) | ErrorKind::ConnectionPoolCleared { .. } | ErrorKind::ServerSelection { .. }
+                );
+        }
+        current = error.source();
+    }
+    false
+}
+
+struct BreakerState {
+    consecutive_failures: u32,
+    /// When the circuit opened, if it is open
+    opened_at: Option<Instant>,
+    /// When the write probing the destination started, if one is in flight
+    probe_started: Option<Instant>,
+}
+
+/// Destination that retries failed writes and stops writing to a destination that keeps failing.
+///
+/// Transient write errors, as decided by the classifier (`is_transient` by
+/// default), are retried with exponential backoff and jitter. After
+/// `failure_threshold` writes in a row failed, the circuit opens and writes
+/// fail immediately; once `open_duration` has passed, the next write probes
+/// the destination and closes the circuit again if it succeeds.
+///
+/// Reads and deletes go to the destination directly. Its state can be
+/// observed through `metrics`, or through `LogService::retry_metrics` of a
+/// service writing to it.
+pub struct RetryingDestination {
+    destination: Arc<dyn LogService>,
+    policy: RetryPolicy,
+    breaker: CircuitBreakerConfig,
+    classifier: ErrorClassifier,
+    state: Mutex<BreakerState>,
+    retries: AtomicU64,
+    short_circuited: AtomicU64,
+}
+
+impl RetryingDestination {
+    /// Wraps `destination` with the default retry policy and circuit breaker
+    pub fn new(destination: Arc<dyn LogService>) -> Self {
+        Self {
+            destination,
+            policy: RetryPolicy::default(),
+            breaker: CircuitBreakerConfig::default(),
+            classifier: Arc::new(is_transient),
+            state: Mutex::new(BreakerState {
+                consecutive_failures: 0,
+                opened_at: None,
+                probe_started: None,
+            }),
+            retries: AtomicU64::new(0),
+            short_circuited: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets how failed writes are retried
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets when the circuit opens and for how long
+    pub fn with_circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.breaker = config;
+        self
+    }
+
+    /// Sets which errors are retried; other errors are returned right away
+    pub fn with_classifier(mut self, classifier: impl Fn(&(dyn Error + 'static)) -> bool + Send + Sync + 'static) -> Self {
+        self.classifier = Arc::new(classifier);
+        self
+    }
+
+    /// Returns the current state of the circuit breaker
+    pub fn circuit_state(&self) -> CircuitState {
+        let state = self.state.lock().unwrap();
+        match state.opened_at {
+            None => CircuitState::Closed,
+            Some(_) if state.probe_started.is_some() => CircuitState::HalfOpen,
+            Some(opened_at) if opened_at.elapsed() >= self.breaker.open_duration => CircuitState::HalfOpen,
+            Some(_) => CircuitState::Open,
+        }
+    }
+
+    /// Returns the state of the circuit breaker and the retry counters
+    pub fn metrics(&self) -> RetryMetrics {
+        RetryMetrics {
+            circuit_state: self.circuit_state(),
+            consecutive_failures: self.state.lock().unwrap().consecutive_failures,
+            retries: self.retries.load(Ordering::Relaxed),
+            short_circuited: self.short_circuited.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Lets a write through unless the circuit is open; returns whether the write is a probe
+    fn admit(&self) -> LogResult<bool> {
+        let mut state = self.state.lock().unwrap();
+        let Some(opened_at) = state.opened_at else {
+            return Ok(false);
+        };
+        // A probe that never finished, e.g. because its future was dropped, does not block forever
+        let probing = state
+            .probe_started
+            .is_some_and(|started| started.elapsed() < self.breaker.open_duration);
+        if probing || opened_at.elapsed() < self.breaker.open_duration {
+            self.short_circuited.fetch_add(1, Ordering::Relaxed);
+            return Err("circuit breaker is open: the destination keeps failing".into());
+        }
+        state.probe_started = Some(Instant::now());
+        Ok(true)
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.opened_at = None;
+        state.probe_started = None;
+    }
+
+    fn record_failure(&self, probe: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if probe || state.consecutive_failures >= self.breaker.failure_threshold {
+            state.opened_at = Some(Instant::now());
+            state.probe_started = None;
+        }
+    }
+
+    /// Runs `write`, retrying transient failures and updating the circuit breaker
+    async fn write<F, Fut>(&self, mut write: F) -> LogResult<()>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = LogResult<()>> + Send,
+    {
+        let probe = self.admit()?;
+        let mut retry = 0;
+        loop {
+            let error = match write().await {
+                Ok(()) => {
+                    self.record_success();
+                    return Ok(());
+                }
+                Err(e) => e,
+            };
+            if !(self.classifier)(error.as_ref()) {
+                // The destination answered, so it is not down
+                self.record_success();
+                return Err(error);
+            }
+            // A probe is not retried, so the circuit reopens quickly if the destination is still down
+            if probe || retry >= self.policy.max_retries {
+                self.record_failure(probe);
+                return Err(error);
+            }
+            tokio::time::sleep(self.policy.backoff(retry)).await;
+            retry += 1;
+            self.retries.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[async_trait]
+impl LogService for RetryingDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        self.write(|| self.destination.insert_log_unit(log_unit.clone())).await
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        self.write(|| self.destination.log(entry.clone())).await
+    }
+
+    async fn get_log_entries(&self, log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        self.destination.get_log_entries(log_unit_id).await
+    }
+
+    async fn get_log_unit(&self, log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        self.destination.get_log_unit(log_unit_id).await
+    }
+
+    async fn get_log_units_by_external_id(&self, external_id: &str) -> LogResult<Vec<LogUnit>> {
+        self.destination.get_log_units_by_external_id(external_id).await
+    }
+
+    async fn list_log_units(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> LogResult<Vec<LogUnit>> {
+        self.destination.list_log_units(from, to).await
+    }
+
+    async fn search_entries(&self, text: &str, options: &SearchOptions) -> LogResult<Vec<SearchHit>> {
+        self.destination.search_entries(text, options).await
+    }
+
+    async fn count_by_level(&self, filter: &StatsFilter) -> LogResult<LevelCounts> {
+        self.destination.count_by_level(filter).await
+    }
+
+    async fn entry_histogram(&self, filter: &StatsFilter, bucket: TimeBucket) -> LogResult<Vec<HistogramBucket>> {
+        self.destination.entry_histogram(filter, bucket).await
+    }
+
+    async fn top_units(&self, filter: &StatsFilter, ranking: UnitRanking, limit: usize) -> LogResult<Vec<UnitStats>> {
+        self.destination.top_units(filter, ranking, limit).await
+    }
+
+    async fn counts_by_external_id(&self, filter: &StatsFilter) -> LogResult<Vec<ExternalIdStats>> {
+        self.destination.counts_by_external_id(filter).await
+    }
+
+    async fn subscribe(&self, filter: SubscriptionFilter) -> LogResult<Subscription> {
+        self.destination.subscribe(filter).await
+    }
+
+    async fn delete_log_unit(&self, log_unit_id: Uuid) -> LogResult<bool> {
+        self.destination.delete_log_unit(log_unit_id).await
+    }
+
+    async fn purge_entries(&self, before: DateTime<Utc>, level: Option<LogLevel>) -> LogResult<u64> {
+        self.destination.purge_entries(before, level).await
+    }
+
+    async fn purge_empty_log_units(&self, before: DateTime<Utc>) -> LogResult<u64> {
+        self.destination.purge_empty_log_units(before).await
+    }
+
+    async fn trim_log_units(&self, max_units: usize) -> LogResult<u64> {
+        self.destination.trim_log_units(max_units).await
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        self.destination.flush().await
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        self.destination.shutdown(timeout).await
+    }
+
+    fn retry_metrics(&self) -> Option<RetryMetrics> {
+        Some(self.metrics())
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "console")]
+mod tests {
+    use super::*;
+    use crate::destinations::test_destination::FailingDestination;
+    use crate::DefaultLogService;
+    use std::sync::atomic::AtomicU32;
+
+    fn retrying(failures: u32, kind: io::ErrorKind) -> RetryingDestination {
+        let destination = FailingDestination::new();
+        destination.fail_next(failures, kind);
+        RetryingDestination::new(Arc::new(destination))
+            .with_retry_policy(RetryPolicy {
+                max_retries: 2,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(2),
+                jitter: 0.5,
+            })
+            .with_circuit_breaker(CircuitBreakerConfig {
+                failure_threshold: 2,
+                open_duration: Duration::from_millis(50),
+            })
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors_are_retried() {
+        let destination = retrying(2, io::ErrorKind::ConnectionReset);
+        let unit_id = Uuid::new_v4();
+
+        destination.log(LogEntry::info(unit_id, "Retried".to_string())).await.unwrap();
+        assert_eq!(destination.metrics().retries, 2);
+        assert_eq!(destination.get_log_entries(unit_id).await.unwrap().len(), 1);
+
+        let permanent = retrying(1, io::ErrorKind::PermissionDenied);
+        assert!(permanent.log(LogEntry::info(unit_id, "Rejected".to_string())).await.is_err());
+        assert_eq!(permanent.metrics().retries, 0);
+        assert_eq!(permanent.circuit_state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_and_recovers() {
+        // Two writes with three attempts each open the circuit, the probe finds the destination healthy
+        let destination = retrying(6, io::ErrorKind::ConnectionRefused);
+        let unit_id = Uuid::new_v4();
+        let entry = || LogEntry::info(unit_id, "Entry".to_string());
+
+        assert!(destination.log(entry()).await.is_err());
+        assert!(destination.log(entry()).await.is_err());
+        assert_eq!(destination.circuit_state(), CircuitState::Open);
+        assert!(destination.log(entry()).await.is_err());
+        assert_eq!(destination.metrics().short_circuited, 1);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(destination.circuit_state(), CircuitState::HalfOpen);
+        destination.log(entry()).await.unwrap();
+        assert_eq!(destination.circuit_state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_are_observable_through_the_service() {
+        let service = DefaultLogService::with_destination(Arc::new(retrying(u32::MAX, io::ErrorKind::ConnectionRefused)));
+        let unit_id = Uuid::new_v4();
+
+        for _ in 0..2 {
+            let _ = service.log(LogEntry::info(unit_id, "Entry".to_string())).await;
+        }
+        let metrics = service.retry_metrics().unwrap();
+        assert_eq!(metrics.circuit_state, CircuitState::Open);
+        assert_eq!(metrics.retries, 4);
+        assert!(DefaultLogService::new_console().retry_metrics().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_run_retries_transient_failures_only() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            jitter: 0.0,
+        };
+        let classify = |e: &io::Error| match e.kind() {
+            io::ErrorKind::ConnectionReset => Failure::Transient(Some(Duration::from_secs(60))),
+            _ => Failure::Permanent,
+        };
+        let attempts = AtomicU32::new(0);
+        let attempt = |kind| {
+            let attempts = &attempts;
+            move || async move {
+                match attempts.fetch_add(1, Ordering::Relaxed) {
+                    0 | 1 => Err(io::Error::from(kind)),
+                    n => Ok(n),
+                }
+            }
+        };
+
+        // The requested delay is capped at `max_backoff`
+        assert_eq!(policy.run(attempt(io::ErrorKind::ConnectionReset), classify).await.unwrap(), 2);
+        attempts.store(0, Ordering::Relaxed);
+        assert!(policy.run(attempt(io::ErrorKind::PermissionDenied), classify).await.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+}
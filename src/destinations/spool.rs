@@ -0,0 +1,576 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::core::{ExternalIdStats, HistogramBucket, LevelCounts, LogEntry, LogLevel, LogService, LogUnit, SearchHit, SearchOptions, StatsFilter, Subscription, SubscriptionFilter, TimeBucket, UnitRanking, UnitStats};
+use crate::core::log_service::LogResult;
+use crate::destinations::retry::{self, RetryMetrics};
+
+/// Extension of the files holding spooled records
+const SEGMENT_EXTENSION: &str = "spool";
+/// File recording how many records of the oldest segment have been delivered
+const CURSOR_FILE: &str = "cursor";
+/// File collecting the records the destination rejected with a permanent error
+pub const DEAD_LETTER_FILE: &str = "dead-letter.ndjson";
+
+/// Configuration of a `SpoolingDestination`
+#[derive(Debug, Clone)]
+pub struct SpoolConfig {
+    /// Directory holding the spool; created if missing
+    pub directory: PathBuf,
+    /// Maximum size of the spooled records in bytes; writes that do not fit fail
+    pub max_bytes: u64,
+    /// Delay before retrying a delivery that failed with a transient error
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between retries, which doubles after each failure
+    pub max_backoff: Duration,
+}
+
+impl SpoolConfig {
+    /// Creates a configuration spooling to `directory` with the default limits
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("ironscribe-spool"),
+            max_bytes: 64 * 1024 * 1024,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+/// State of the spool of a `SpoolingDestination`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpoolMetrics {
+    /// Units and entries waiting to be delivered
+    pub pending_records: u64,
+    /// Size of the waiting records in bytes
+    pub pending_bytes: u64,
+    /// Records rejected because the spool was full
+    pub dropped_records: u64,
+    /// Records moved to the dead-letter file because the destination rejected them permanently
+    pub dead_letter_records: u64,
+}
+
+/// Line of a spool segment
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SpoolRecord {
+    Unit(LogUnit),
+    Entry(LogEntry),
+}
+
+/// Number of records of `segment` that have been delivered
+#[derive(Debug, Clone, Copy)]
+struct Cursor {
+    segment: u64,
+    delivered: usize,
+}
+
+/// Segment currently appended to
+struct SegmentWriter {
+    open: Option<(u64, File)>,
+    next_segment: u64,
+}
+
+/// Append-only spool of records in numbered segment files, delivered oldest first
+struct Spool {
+    config: SpoolConfig,
+    writer: Mutex<SegmentWriter>,
+    pending_records: AtomicU64,
+    pending_bytes: AtomicU64,
+    dropped_records: AtomicU64,
+    dead_letter_records: AtomicU64,
+    /// Signalled after each append
+    appended: Notify,
+}
+
+impl Spool {
+    /// Opens the spool in the configured directory, counting the records left from a previous run
+    async fn open(config: SpoolConfig) -> LogResult<Self> {
+        fs::create_dir_all(&config.directory).await?;
+        let cursor = read_cursor(&config.directory).await;
+        let segments = list_segments(&config.directory).await?;
+
+        let mut pending_records = 0;
+        let mut pending_bytes = 0;
+        for &segment in &segments {
+            let content = fs::read(segment_path(&config.directory, segment)).await?;
+            let delivered = cursor.filter(|c| c.segment == segment).map_or(0, |c| c.delivered);
+            for line in String::from_utf8_lossy(&content).lines().skip(delivered) {
+                pending_records += 1;
+                pending_bytes += line.len() as u64 + 1;
+            }
+        }
+
+        Ok(Self {
+            writer: Mutex::new(SegmentWriter {
+                open: None,
+                next_segment: segments.last().map_or(0, |segment| segment + 1),
+            }),
+            config,
+            pending_records: AtomicU64::new(pending_records),
+            pending_bytes: AtomicU64::new(pending_bytes),
+            dropped_records: AtomicU64::new(0),
+            dead_letter_records: AtomicU64::new(0),
+            appended: Notify::new(),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending_records.load(Ordering::SeqCst) == 0
+    }
+
+    fn metrics(&self) -> SpoolMetrics {
+        SpoolMetrics {
+            pending_records: self.pending_records.load(Ordering::SeqCst),
+            pending_bytes: self.pending_bytes.load(Ordering::SeqCst),
+            dropped_records: self.dropped_records.load(Ordering::Relaxed),
+            dead_letter_records: self.dead_letter_records.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Appends `record` and syncs it to disk
+    async fn append(&self, record: &SpoolRecord) -> LogResult<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        let size = line.len() as u64;
+
+        let mut writer = self.writer.lock().await;
+        if self.pending_bytes.load(Ordering::SeqCst) + size > self.config.max_bytes {
+            self.dropped_records.fetch_add(1, Ordering::Relaxed);
+            return Err(format!("log spool is full ({} bytes)", self.config.max_bytes).into());
+        }
+
+        let writer = &mut *writer;
+        let file = match &mut writer.open {
+            Some((_, file)) => file,
+            open => {
+                let segment = writer.next_segment;
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(segment_path(&self.config.directory, segment))
+                    .await?;
+                writer.next_segment += 1;
+                &mut open.insert((segment, file)).1
+            }
+        };
+        file.write_all(line.as_bytes()).await?;
+        file.sync_data().await?;
+
+        self.pending_records.fetch_add(1, Ordering::SeqCst);
+        self.pending_bytes.fetch_add(size, Ordering::SeqCst);
+        self.appended.notify_one();
+        Ok(())
+    }
+
+    /// Delivers records to `destination` until the task is aborted
+    async fn run_delivery(&self, destination: &dyn LogService) {
+        loop {
+            match self.deliver_oldest_segment(destination).await {
+                Ok(true) => {}
+                Ok(false) => self.appended.notified().await,
+                Err(e) => {
+                    eprintln!("Failed to read log spool: {}", e);
+                    tokio::time::sleep(self.config.max_backoff).await;
+                }
+            }
+        }
+    }
+
+    /// Delivers and removes the oldest segment; returns `false` if there is none
+    async fn deliver_oldest_segment(&self, destination: &dyn LogService) -> LogResult<bool> {
+        let directory = &self.config.directory;
+        let Some(&segment) = list_segments(directory).await?.first() else {
+            return Ok(false);
+        };
+
+        // Further appends go to a new segment, so this one no longer changes
+        {
+            let mut writer = self.writer.lock().await;
+            if writer.open.as_ref().is_some_and(|(open, _)| *open == segment) {
+                writer.open = None;
+            }
+        }
+
+        let path = segment_path(directory, segment);
+        let content = fs::read(&path).await?;
+        let delivered = read_cursor(directory).await.filter(|c| c.segment == segment).map_or(0, |c| c.delivered);
+        for (index, line) in String::from_utf8_lossy(&content).lines().enumerate().skip(delivered) {
+            match serde_json::from_str(line) {
+                Ok(record) => self.deliver(destination, record).await?,
+                // Left by a crash while appending
+                Err(e) => eprintln!("Skipping unreadable spooled record: {}", e),
+            }
+            write_cursor(directory, Cursor { segment, delivered: index + 1 }).await?;
+            self.pending_records.fetch_sub(1, Ordering::SeqCst);
+            self.pending_bytes.fetch_sub(line.len() as u64 + 1, Ordering::SeqCst);
+        }
+
+        fs::remove_file(&path).await?;
+        match fs::remove_file(directory.join(CURSOR_FILE)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(true),
+        }
+    }
+
+    /// Delivers `record`, retrying transient failures with exponential backoff until it succeeds.
+    ///
+    /// A record failing with a permanent error, such as a constraint violation,
+    /// is moved to the dead-letter file so it does not block the records behind it.
+    async fn deliver(&self, destination: &dyn LogService, record: SpoolRecord) -> LogResult<()> {
+        let mut backoff = self.config.initial_backoff;
+        loop {
+            let delivered = match &record {
+                SpoolRecord::Unit(log_unit) => destination.insert_log_unit(log_unit.clone()).await,
+                SpoolRecord::Entry(entry) => destination.log(entry.clone()).await,
+            };
+            match delivered {
+                Ok(()) => return Ok(()),
+                Err(e) if retry::is_transient(e.as_ref()) => {
+                    eprintln!("Failed to deliver spooled record, retrying in {:?}: {}", backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.config.max_backoff);
+                }
+                Err(e) => {
+                    eprintln!("Failed to deliver spooled record, moving it to {}: {}", DEAD_LETTER_FILE, e);
+                    return self.dead_letter(&record).await;
+                }
+            }
+        }
+    }
+
+    /// Appends `record` to the dead-letter file and syncs it to disk
+    async fn dead_letter(&self, record: &SpoolRecord) -> LogResult<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.config.directory.join(DEAD_LETTER_FILE))
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        file.sync_data().await?;
+
+        self.dead_letter_records.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+fn segment_path(directory: &Path, segment: u64) -> PathBuf {
+    directory.join(format!("{:020}.{}", segment, SEGMENT_EXTENSION))
+}
+
+/// Returns the numbers of the segments in `directory`, oldest first
+async fn list_segments(directory: &Path) -> LogResult<Vec<u64>> {
+    let mut segments = Vec::new();
+    let mut dir = fs::read_dir(directory).await?;
+    while let Some(file) = dir.next_entry().await? {
+        let path = file.path();
+        if path.extension().is_some_and(|extension| extension == SEGMENT_EXTENSION)
+            && let Some(segment) = path.file_stem().and_then(|stem| stem.to_str()?.parse().ok())
+        {
+            segments.push(segment);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+/// Reads the cursor; a missing or unreadable cursor means nothing was delivered
+async fn read_cursor(directory: &Path) -> Option<Cursor> {
+    let content = fs::read_to_string(directory.join(CURSOR_FILE)).await.ok()?;
+    let (segment, delivered) = content.trim().split_once(' ')?;
+    Some(Cursor {
+        segment: segment.parse().ok()?,
+        delivered: delivered.parse().ok()?,
+    })
+}
+
+/// Replaces the cursor through a rename, so it is never left half written
+async fn write_cursor(directory: &Path, cursor: Cursor) -> LogResult<()> {
+    let temporary = directory.join(format!("{}.tmp", CURSOR_FILE));
+    fs::write(&temporary, format!("{} {}", cursor.segment, cursor.delivered)).await?;
+    fs::rename(&temporary, directory.join(CURSOR_FILE)).await?;
+    Ok(())
+}
+
+/// Destination that spools writes to disk while the wrapped destination fails.
+///
+/// Units and entries that fail with a transient error (see `retry::is_transient`)
+/// are appended to the spool and delivered in the background once the wrapped
+/// destination accepts them again. Permanent errors are returned to the caller.
+/// While records are waiting, new writes are spooled behind them, so entries
+/// reach the destination in the order they were logged. Delivery is at least
+/// once: a record may be delivered again after a crash. Spooled records the
+/// destination rejects permanently, e.g. such a redelivered duplicate, are
+/// moved to `DEAD_LETTER_FILE` in the spool directory.
+///
+/// Reads are answered by the wrapped destination and do not see spooled
+/// records. Only one destination may use a spool directory at a time.
+pub struct SpoolingDestination {
+    destination: Arc<dyn LogService>,
+    spool: Arc<Spool>,
+    delivery: JoinHandle<()>,
+}
+
+impl SpoolingDestination {
+    /// Wraps `destination`, delivering records left in the spool by a previous run first.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub async fn new(destination: Arc<dyn LogService>, config: SpoolConfig) -> LogResult<Self> {
+        let spool = Arc::new(Spool::open(config).await?);
+
+        let delivery_spool = Arc::clone(&spool);
+        let delivery_destination = Arc::clone(&destination);
+        let delivery = tokio::spawn(async move { delivery_spool.run_delivery(delivery_destination.as_ref()).await });
+
+        Ok(Self {
+            destination,
+            spool,
+            delivery,
+        })
+    }
+
+    /// Returns the current depth of the spool
+    pub fn metrics(&self) -> SpoolMetrics {
+        self.spool.metrics()
+    }
+
+    /// Writes through the wrapped destination, or spools `record` if that fails
+    /// with a transient error or records are waiting
+    async fn write(&self, record: SpoolRecord) -> LogResult<()> {
+        if self.spool.is_empty() {
+            let written = match &record {
+                SpoolRecord::Unit(log_unit) => self.destination.insert_log_unit(log_unit.clone()).await,
+                SpoolRecord::Entry(entry) => self.destination.log(entry.clone()).await,
+            };
+            match written {
+                Ok(()) => return Ok(()),
+                Err(e) if !retry::is_transient(e.as_ref()) => return Err(e),
+                Err(_) => {}
+            }
+        }
+        self.spool.append(&record).await
+    }
+}
+
+impl Drop for SpoolingDestination {
+    fn drop(&mut self) {
+        self.delivery.abort();
+    }
+}
+
+#[async_trait]
+impl LogService for SpoolingDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        self.write(SpoolRecord::Unit(log_unit)).await
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        self.write(SpoolRecord::Entry(entry)).await
+    }
+
+    async fn get_log_entries(&self, log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        self.destination.get_log_entries(log_unit_id).await
+    }
+
+    async fn get_log_unit(&self, log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        self.destination.get_log_unit(log_unit_id).await
+    }
+
+    async fn get_log_units_by_external_id(&self, external_id: &str) -> LogResult<Vec<LogUnit>> {
+        self.destination.get_log_units_by_external_id(external_id).await
+    }
+
+    async fn list_log_units(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> LogResult<Vec<LogUnit>> {
+        self.destination.list_log_units(from, to).await
+    }
+
+    async fn search_entries(&self, text: &str, options: &SearchOptions) -> LogResult<Vec<SearchHit>> {
+        self.destination.search_entries(text, options).await
+    }
+
+    async fn count_by_level(&self, filter: &StatsFilter) -> LogResult<LevelCounts> {
+        self.destination.count_by_level(filter).await
+    }
+
+    async fn entry_histogram(&self, filter: &StatsFilter, bucket: TimeBucket) -> LogResult<Vec<HistogramBucket>> {
+        self.destination.entry_histogram(filter, bucket).await
+    }
+
+    async fn top_units(&self, filter: &StatsFilter, ranking: UnitRanking, limit: usize) -> LogResult<Vec<UnitStats>> {
+        self.destination.top_units(filter, ranking, limit).await
+    }
+
+    async fn counts_by_external_id(&self, filter: &StatsFilter) -> LogResult<Vec<ExternalIdStats>> {
+        self.destination.counts_by_external_id(filter).await
+    }
+
+    async fn subscribe(&self, filter: SubscriptionFilter) -> LogResult<Subscription> {
+        self.destination.subscribe(filter).await
+    }
+
+    async fn delete_log_unit(&self, log_unit_id: Uuid) -> LogResult<bool> {
+        self.destination.delete_log_unit(log_unit_id).await
+    }
+
+    async fn purge_entries(&self, before: DateTime<Utc>, level: Option<LogLevel>) -> LogResult<u64> {
+        self.destination.purge_entries(before, level).await
+    }
+
+    async fn purge_empty_log_units(&self, before: DateTime<Utc>) -> LogResult<u64> {
+        self.destination.purge_empty_log_units(before).await
+    }
+
+    async fn trim_log_units(&self, max_units: usize) -> LogResult<u64> {
+        self.destination.trim_log_units(max_units).await
+    }
+
+    /// Flushes the wrapped destination.
+    ///
+    /// Records waiting in the spool are not waited for, as the destination may
+    /// be down for a long time; `metrics` tells how many are left.
+    async fn flush(&self) -> LogResult<()> {
+        self.destination.flush().await
+    }
+
+    /// Stops delivery and shuts the wrapped destination down.
+    ///
+    /// Records still waiting stay in the spool for the next `SpoolingDestination`
+    /// opened on the same directory.
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        self.delivery.abort();
+        self.destination.shutdown(timeout).await
+    }
+
+    fn retry_metrics(&self) -> Option<RetryMetrics> {
+        self.destination.retry_metrics()
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "console")]
+mod tests {
+    use super::*;
+    use crate::destinations::test_destination::FailingDestination;
+    use std::io;
+
+    /// Destination whose writes fail until it is healthy
+    fn flaky(healthy: bool) -> Arc<FailingDestination> {
+        let destination = FailingDestination::new();
+        if !healthy {
+            destination.fail_next(u32::MAX, io::ErrorKind::ConnectionRefused);
+        }
+        Arc::new(destination)
+    }
+
+    fn test_config() -> SpoolConfig {
+        SpoolConfig {
+            initial_backoff: Duration::from_millis(5),
+            max_backoff: Duration::from_millis(20),
+            ..SpoolConfig::new(std::env::temp_dir().join(format!("ironscribe-spool-{}", Uuid::new_v4())))
+        }
+    }
+
+    async fn wait_until_delivered(destination: &SpoolingDestination) {
+        for _ in 0..200 {
+            if destination.metrics().pending_records == 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("spool was not delivered: {:?}", destination.metrics());
+    }
+
+    #[tokio::test]
+    async fn test_spooled_entries_are_delivered_in_order() {
+        let config = test_config();
+        let backend = flaky(false);
+        let destination = SpoolingDestination::new(backend.clone(), config.clone()).await.unwrap();
+
+        let unit = destination.create_log_unit("job".to_string()).await.unwrap();
+        for i in 0..3 {
+            destination.log(LogEntry::info(unit.log_unit_id, format!("Entry {}", i))).await.unwrap();
+        }
+        assert_eq!(destination.metrics().pending_records, 4);
+
+        backend.fail_next(0, io::ErrorKind::ConnectionRefused);
+        // Spooled as well, behind the entries still waiting
+        destination.log(LogEntry::info(unit.log_unit_id, "Entry 3".to_string())).await.unwrap();
+        wait_until_delivered(&destination).await;
+
+        let entries = backend.get_log_entries(unit.log_unit_id).await.unwrap();
+        let messages: Vec<&str> = entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["Entry 0", "Entry 1", "Entry 2", "Entry 3"]);
+        assert_eq!(destination.metrics().pending_bytes, 0);
+        fs::remove_dir_all(&config.directory).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failures_do_not_block_the_spool() {
+        let config = test_config();
+        let backend = flaky(false);
+        let destination = SpoolingDestination::new(backend.clone(), config.clone()).await.unwrap();
+        let unit_id = Uuid::new_v4();
+
+        for message in ["Entry 0", "Invalid entry", "Entry 1"] {
+            destination.log(LogEntry::info(unit_id, message.to_string())).await.unwrap();
+        }
+        backend.fail_next(0, io::ErrorKind::ConnectionRefused);
+        wait_until_delivered(&destination).await;
+
+        let entries = backend.get_log_entries(unit_id).await.unwrap();
+        let messages: Vec<&str> = entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["Entry 0", "Entry 1"]);
+        assert_eq!(destination.metrics().dead_letter_records, 1);
+        let dead_letters = fs::read_to_string(config.directory.join(DEAD_LETTER_FILE)).await.unwrap();
+        assert!(dead_letters.contains("Invalid entry"));
+
+        // Not spooled, as retrying would not help
+        assert!(destination.log(LogEntry::info(unit_id, "Invalid again".to_string())).await.is_err());
+        assert_eq!(destination.metrics().pending_records, 0);
+        fs::remove_dir_all(&config.directory).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_spool_survives_restart_and_enforces_max_size() {
+        let config = SpoolConfig {
+            max_bytes: 1024,
+            ..test_config()
+        };
+        let unit_id = Uuid::new_v4();
+        {
+            let destination = SpoolingDestination::new(flaky(false), config.clone()).await.unwrap();
+            destination.log(LogEntry::info(unit_id, "Before restart".to_string())).await.unwrap();
+            let too_large = LogEntry::info(unit_id, "x".repeat(1024));
+            assert!(destination.log(too_large).await.is_err());
+            assert_eq!(destination.metrics().dropped_records, 1);
+        }
+
+        let backend = flaky(true);
+        let destination = SpoolingDestination::new(backend.clone(), config.clone()).await.unwrap();
+        wait_until_delivered(&destination).await;
+        assert_eq!(backend.get_log_entries(unit_id).await.unwrap()[0].message, "Before restart");
+        fs::remove_dir_all(&config.directory).await.unwrap();
+    }
+}
@@ -0,0 +1,557 @@
+//! Syslog destination sending entries as RFC 5424 or RFC 3164 messages
+
+use async_trait::async_trait;
+use chrono::Local;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+#[cfg(unix)]
+use std::path::PathBuf;
+#[cfg(unix)]
+use tokio::net::UnixDatagram;
+
+#[cfg(feature = "syslog-tls")]
+use std::path::Path;
+#[cfg(feature = "syslog-tls")]
+use std::sync::Arc;
+#[cfg(feature = "syslog-tls")]
+use tokio_rustls::rustls::{self, pki_types::{pem::PemObject, CertificateDer, ServerName}, ClientConfig, RootCertStore};
+#[cfg(feature = "syslog-tls")]
+use tokio_rustls::TlsConnector;
+
+use crate::core::{LogEntry, LogMessageType, LogService, LogUnit};
+use crate::core::log_service::{unsupported, LogResult};
+use crate::destinations::unit_cache::ExternalIdCache;
+
+/// How messages reach the syslog server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyslogTransport {
+    /// One message per UDP datagram (RFC 5426), e.g. `Udp("127.0.0.1:514")`
+    Udp(String),
+    /// Octet-counted frames over TCP (RFC 6587), e.g. `Tcp("logs.example.com:601")`
+    Tcp(String),
+    /// Octet-counted frames over TLS (RFC 5425).
+    ///
+    /// The server certificate is verified against the PEM certificates in
+    /// `ca_file`, or against the Mozilla root certificates without one.
+    #[cfg(feature = "syslog-tls")]
+    Tls {
+        address: String,
+        server_name: String,
+        ca_file: Option<std::path::PathBuf>,
+    },
+    /// Datagram Unix socket of the local syslog daemon, usually `/dev/log`
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// Message format written to the syslog server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogFormat {
+    /// RFC 5424, with the entry's IDs as structured data
+    Rfc5424,
+    /// BSD syslog (RFC 3164), with the entry's IDs appended to the message.
+    ///
+    /// Expected by most local daemons listening on `/dev/log`. The timestamp
+    /// carries no zone, so it is written in the local time zone like other
+    /// senders do.
+    Rfc3164,
+}
+
+/// Syslog facility of every message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogFacility {
+    Kern = 0,
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    AuthPriv = 10,
+    Ftp = 11,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyslogConfig {
+    pub transport: SyslogTransport,
+    pub format: SyslogFormat,
+    pub facility: SyslogFacility,
+    /// APP-NAME (RFC 5424) or TAG (RFC 3164) of every message
+    pub app_name: String,
+    /// HOSTNAME of every message; the name of the local host when `None`
+    pub hostname: Option<String>,
+    /// SD-ID of the structured data element carrying the entry's IDs
+    pub sd_id: String,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self {
+            transport: SyslogTransport::Udp("127.0.0.1:514".to_string()),
+            format: SyslogFormat::Rfc5424,
+            facility: SyslogFacility::User,
+            app_name: "ironscribe".to_string(),
+            hostname: None,
+            // 32473 is the enterprise number reserved for examples (RFC 5612)
+            sd_id: "ironscribe@32473".to_string(),
+        }
+    }
+}
+
+/// Open connection to the syslog server
+enum Connection {
+    Udp(UdpSocket),
+    Stream(Box<dyn AsyncWrite + Send + Unpin>),
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+}
+
+impl Connection {
+    /// Sends one message, framed with its length on streams
+    async fn send(&mut self, message: &str) -> io::Result<()> {
+        match self {
+            Connection::Udp(socket) => socket.send(message.as_bytes()).await.map(drop),
+            Connection::Stream(stream) => {
+                let frame = format!("{} {}", message.len(), message);
+                stream.write_all(frame.as_bytes()).await?;
+                stream.flush().await
+            }
+            #[cfg(unix)]
+            Connection::Unix(socket) => socket.send(message.as_bytes()).await.map(drop),
+        }
+    }
+
+    async fn close(self) -> io::Result<()> {
+        match self {
+            Connection::Stream(mut stream) => stream.shutdown().await,
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Write-only destination sending every entry to a syslog server.
+///
+/// Entry types map to the severities error (3), warning (4), notice (5) for
+/// success and informational (6). The server is reconnected to when a send
+/// fails; like any syslog sender, entries written to a connection the server
+/// has just dropped can be lost. Reads are not supported.
+pub struct SyslogDestination {
+    config: SyslogConfig,
+    hostname: String,
+    connection: Mutex<Option<Connection>>,
+    closed: AtomicBool,
+    external_ids: ExternalIdCache,
+    #[cfg(feature = "syslog-tls")]
+    tls: Option<TlsConnector>,
+}
+
+impl SyslogDestination {
+    /// Creates a syslog destination, connecting to the server right away
+    pub async fn new(config: SyslogConfig) -> LogResult<Self> {
+        #[cfg(feature = "syslog-tls")]
+        let tls = match &config.transport {
+            SyslogTransport::Tls { ca_file, .. } => Some(tls_connector(ca_file.as_deref())?),
+            _ => None,
+        };
+
+        let destination = Self {
+            hostname: config.hostname.clone().unwrap_or_else(local_hostname),
+            config,
+            connection: Mutex::new(None),
+            closed: AtomicBool::new(false),
+            external_ids: ExternalIdCache::default(),
+            #[cfg(feature = "syslog-tls")]
+            tls,
+        };
+        *destination.connection.lock().await = Some(destination.connect().await?);
+        Ok(destination)
+    }
+
+    async fn connect(&self) -> LogResult<Connection> {
+        let connection = match &self.config.transport {
+            SyslogTransport::Udp(address) => {
+                let target = tokio::net::lookup_host(address)
+                    .await?
+                    .next()
+                    .ok_or_else(|| format!("Failed to resolve syslog server {}", address))?;
+                let socket = UdpSocket::bind(if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).await?;
+                socket.connect(target).await?;
+                Connection::Udp(socket)
+            }
+            SyslogTransport::Tcp(address) => {
+                let stream = TcpStream::connect(address).await?;
+                stream.set_nodelay(true)?;
+                Connection::Stream(Box::new(stream))
+            }
+            #[cfg(feature = "syslog-tls")]
+            SyslogTransport::Tls { address, server_name, .. } => {
+                let connector = self.tls.as_ref().ok_or("TLS is not configured")?;
+                let server_name = ServerName::try_from(server_name.clone())?;
+                let stream = TcpStream::connect(address).await?;
+                stream.set_nodelay(true)?;
+                Connection::Stream(Box::new(connector.connect(server_name, stream).await?))
+            }
+            #[cfg(unix)]
+            SyslogTransport::Unix(path) => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                Connection::Unix(socket)
+            }
+        };
+        Ok(connection)
+    }
+
+    /// Sends `message`, reconnecting once if the current connection fails
+    async fn send(&self, message: &str) -> LogResult<()> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err("destination is shut down".into());
+        }
+
+        let mut connection = self.connection.lock().await;
+        let mut reconnected = false;
+        loop {
+            let current = match connection.as_mut() {
+                Some(current) => current,
+                None => {
+                    reconnected = true;
+                    connection.insert(self.connect().await?)
+                }
+            };
+            match current.send(message).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    *connection = None;
+                    if reconnected {
+                        return Err(format!("Failed to send to syslog server: {}", e).into());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Formats `entry` in the configured format, as it is sent without framing
+    fn format_message(&self, entry: &LogEntry, external_id: Option<&str>) -> String {
+        match self.config.format {
+            SyslogFormat::Rfc5424 => format_rfc5424(&self.config, &self.hostname, entry, external_id),
+            SyslogFormat::Rfc3164 => format_rfc3164(&self.config, &self.hostname, entry, external_id),
+        }
+    }
+}
+
+#[async_trait]
+impl LogService for SyslogDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        self.external_ids.insert(log_unit.log_unit_id, log_unit.external_id).await;
+        Ok(())
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        let external_id = self.external_ids.get(entry.log_unit_id).await;
+        let message = self.format_message(&entry, external_id.as_deref());
+        self.send(&message).await
+    }
+
+    async fn get_log_entries(&self, _log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        unsupported("get_log_entries")
+    }
+
+    async fn get_log_unit(&self, _log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        unsupported("get_log_unit")
+    }
+
+    async fn get_log_units_by_external_id(&self, _external_id: &str) -> LogResult<Vec<LogUnit>> {
+        unsupported("get_log_units_by_external_id")
+    }
+
+    async fn flush(&self) -> LogResult<()> {
+        // Sends complete before `log` returns; waiting for the lock waits for the one in flight
+        drop(self.connection.lock().await);
+        Ok(())
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        self.closed.store(true, Ordering::Release);
+        let mut connection = tokio::time::timeout(timeout, self.connection.lock())
+            .await
+            .map_err(|_| format!("timed out after {:?} waiting for writes in flight", timeout))?;
+        if let Some(connection) = connection.take() {
+            connection.close().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Maps an entry type to its syslog severity
+fn severity(message_type: LogMessageType) -> u8 {
+    match message_type {
+        LogMessageType::Error => 3,
+        LogMessageType::Warning => 4,
+        LogMessageType::Success => 5,
+        LogMessageType::Info => 6,
+    }
+}
+
+fn priority(config: &SyslogConfig, entry: &LogEntry) -> u8 {
+    (config.facility as u8) * 8 + severity(entry.message_type)
+}
+
+/// Keeps the printable ASCII characters of a header field, at most `max_len` of them, or `-` if none are left
+fn header_field(value: &str, max_len: usize) -> String {
+    let field: String = value.chars().filter(|c| c.is_ascii_graphic()).take(max_len).collect();
+    if field.is_empty() { "-".to_string() } else { field }
+}
+
+/// Escapes the characters RFC 5424 does not allow unescaped in a PARAM-VALUE
+fn escape_param(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Returns the entry's IDs, location and error chain as structured data parameters
+fn entry_params(entry: &LogEntry, external_id: Option<&str>) -> Vec<(&'static str, String)> {
+    let mut params = vec![
+        ("log_unit_id", entry.log_unit_id.to_string()),
+        ("message_id", entry.message_id.to_string()),
+    ];
+    if let Some(external_id) = external_id {
+        params.push(("external_id", external_id.to_string()));
+    }
+    if let Some(location) = &entry.location {
+        params.push(("file", location.file.clone()));
+        params.push(("line", location.line.to_string()));
+        if !location.module_path.is_empty() {
+            params.push(("module_path", location.module_path.clone()));
+        }
+    }
+    if let Some(error) = &entry.error {
+        params.extend(error.chain.iter().map(|cause| ("error", cause.clone())));
+    }
+    params
+}
+
+/// Formats `entry` as an RFC 5424 message
+fn format_rfc5424(config: &SyslogConfig, hostname: &str, entry: &LogEntry, external_id: Option<&str>) -> String {
+    let structured_data: String = entry_params(entry, external_id)
+        .into_iter()
+        .map(|(name, value)| format!(" {}=\"{}\"", name, escape_param(&value)))
+        .collect();
+    // The BOM marks the message as UTF-8
+    format!(
+        "<{}>1 {} {} {} {} - [{}{}] \u{feff}{}",
+        priority(config, entry),
+        entry.timestamp.format("%Y-%m-%dT%H:%M:%S%.6fZ"),
+        header_field(hostname, 255),
+        header_field(&config.app_name, 48),
+        std::process::id(),
+        header_field(&config.sd_id, 32),
+        structured_data,
+        entry.message
+    )
+}
+
+/// Formats `entry` as a BSD syslog (RFC 3164) message, timestamped in local time
+fn format_rfc3164(config: &SyslogConfig, hostname: &str, entry: &LogEntry, external_id: Option<&str>) -> String {
+    let params: String = entry_params(entry, external_id)
+        .into_iter()
+        .filter(|(name, _)| matches!(*name, "log_unit_id" | "external_id"))
+        .map(|(name, value)| format!(" {}={}", name, value))
+        .collect();
+    let tag: String = config.app_name.chars().filter(char::is_ascii_alphanumeric).take(32).collect();
+    format!(
+        "<{}>{} {} {}[{}]: {} [{}]",
+        priority(config, entry),
+        entry.timestamp.with_timezone(&Local).format("%b %e %H:%M:%S"),
+        header_field(hostname, 255),
+        tag,
+        std::process::id(),
+        entry.message,
+        params.trim_start()
+    )
+}
+
+/// Returns the name of the local host, or `-` if it is unknown
+fn local_hostname() -> String {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Builds the TLS connector trusting the certificates in `ca_file`, or the Mozilla roots without one
+#[cfg(feature = "syslog-tls")]
+fn tls_connector(ca_file: Option<&Path>) -> LogResult<TlsConnector> {
+    let mut roots = RootCertStore::empty();
+    match ca_file {
+        Some(path) => {
+            for certificate in CertificateDer::pem_file_iter(path)? {
+                roots.add(certificate?)?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SourceLocation;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_message_formats() {
+        let config = SyslogConfig {
+            facility: SyslogFacility::Local0,
+            ..Default::default()
+        };
+        let entry = LogEntry::error(Uuid::new_v4(), "disk \"full\"".to_string())
+            .with_location(SourceLocation::new("src/main.rs", 7, 1, "app::disk"));
+
+        let message = format_rfc5424(&config, "host 1", &entry, Some("job]1"));
+        // local0 (16) * 8 + error (3)
+        assert!(message.starts_with("<131>1 "));
+        assert!(message.contains(" host1 ironscribe "));
+        assert!(message.contains(&format!("[ironscribe@32473 log_unit_id=\"{}\"", entry.log_unit_id)));
+        assert!(message.contains(" external_id=\"job\\]1\" file=\"src/main.rs\" line=\"7\" module_path=\"app::disk\"]"));
+        assert!(message.ends_with("] \u{feff}disk \"full\""));
+
+        let message = format_rfc3164(&config, "host", &entry, None);
+        assert!(message.starts_with(&format!("<131>{} ", entry.timestamp.with_timezone(&Local).format("%b %e %H:%M:%S"))));
+        assert!(message.contains(&format!(" host ironscribe[{}]: disk \"full\" [log_unit_id={}]", std::process::id(), entry.log_unit_id)));
+    }
+
+    #[tokio::test]
+    async fn test_udp_and_tcp_transports() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let destination = SyslogDestination::new(SyslogConfig {
+            transport: SyslogTransport::Udp(server.local_addr().unwrap().to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let unit = destination.create_log_unit("job-1".to_string()).await.unwrap();
+        destination.log(LogEntry::info(unit.log_unit_id, "over udp".to_string())).await.unwrap();
+
+        let mut datagram = vec![0; 2048];
+        let len = server.recv(&mut datagram).await.unwrap();
+        let message = String::from_utf8_lossy(&datagram[..len]).to_string();
+        assert!(message.starts_with("<14>1 "));
+        assert!(message.contains("external_id=\"job-1\"") && message.ends_with("over udp"));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let destination = SyslogDestination::new(SyslogConfig {
+            transport: SyslogTransport::Tcp(listener.local_addr().unwrap().to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+        destination.log(LogEntry::warning(unit.log_unit_id, "over tcp".to_string())).await.unwrap();
+        destination.shutdown(Duration::from_secs(1)).await.unwrap();
+
+        let mut received = String::new();
+        stream.read_to_string(&mut received).await.unwrap();
+        let (len, message) = received.split_once(' ').unwrap();
+        assert_eq!(len.parse::<usize>().unwrap(), message.len());
+        assert!(message.starts_with("<12>1 ") && message.ends_with("over tcp"));
+        assert!(destination.log(LogEntry::info(unit.log_unit_id, "late".to_string())).await.is_err());
+    }
+
+    #[cfg(feature = "syslog-tls")]
+    #[tokio::test]
+    async fn test_tls_transport() {
+        use tokio_rustls::rustls::pki_types::PrivateKeyDer;
+        use tokio_rustls::rustls::ServerConfig;
+        use tokio_rustls::TlsAcceptor;
+
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let ca_file = std::env::temp_dir().join(format!("ironscribe-syslog-{}.pem", Uuid::new_v4()));
+        std::fs::write(&ca_file, certified.cert.pem()).unwrap();
+        let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![certified.cert.der().clone()],
+                PrivateKeyDer::try_from(certified.signing_key.serialize_der()).unwrap(),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = acceptor.accept(stream).await.unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).await.unwrap();
+            received
+        });
+
+        let destination = SyslogDestination::new(SyslogConfig {
+            transport: SyslogTransport::Tls { address, server_name: "localhost".to_string(), ca_file: Some(ca_file.clone()) },
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        destination.log(LogEntry::error(Uuid::new_v4(), "over tls".to_string())).await.unwrap();
+        destination.shutdown(Duration::from_secs(1)).await.unwrap();
+        std::fs::remove_file(&ca_file).unwrap();
+
+        let received = server.await.unwrap();
+        let (len, message) = received.split_once(' ').unwrap();
+        assert_eq!(len.parse::<usize>().unwrap(), message.len());
+        assert!(message.starts_with("<11>1 ") && message.ends_with("over tls"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_transport() {
+        let path = std::env::temp_dir().join(format!("ironscribe-syslog-{}.sock", Uuid::new_v4()));
+        let server = UnixDatagram::bind(&path).unwrap();
+        let destination = SyslogDestination::new(SyslogConfig {
+            transport: SyslogTransport::Unix(path.clone()),
+            format: SyslogFormat::Rfc3164,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        destination.log(LogEntry::success(Uuid::new_v4(), "done".to_string())).await.unwrap();
+
+        let mut datagram = vec![0; 2048];
+        let len = server.recv(&mut datagram).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let message = String::from_utf8_lossy(&datagram[..len]).to_string();
+        assert!(message.starts_with("<13>") && message.contains("]: done [log_unit_id="));
+    }
+}
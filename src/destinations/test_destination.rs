@@ -0,0 +1,77 @@
+//! Destination standing in for an unreliable database in tests of wrapping destinations
+
+use async_trait::async_trait;
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::core::log_service::LogResult;
+use crate::core::{LogEntry, LogService, LogUnit};
+use crate::destinations::ConsoleDestination;
+
+/// Console destination whose writes fail on demand, and which permanently
+/// rejects entries starting with "Invalid"
+pub(crate) struct FailingDestination {
+    console: ConsoleDestination,
+    /// Writes left to fail; `u32::MAX` fails every write
+    failures: AtomicU32,
+    kind: Mutex<io::ErrorKind>,
+}
+
+impl FailingDestination {
+    /// Creates a destination whose writes succeed
+    pub(crate) fn new() -> Self {
+        Self {
+            console: ConsoleDestination::new(),
+            failures: AtomicU32::new(0),
+            kind: Mutex::new(io::ErrorKind::ConnectionRefused),
+        }
+    }
+
+    /// Fails the next `failures` writes with `kind`, or every write for `u32::MAX`
+    pub(crate) fn fail_next(&self, failures: u32, kind: io::ErrorKind) {
+        *self.kind.lock().unwrap() = kind;
+        self.failures.store(failures, Ordering::SeqCst);
+    }
+
+    fn check(&self) -> LogResult<()> {
+        let failing = self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| match n {
+            0 => None,
+            u32::MAX => Some(n),
+            n => Some(n - 1),
+        });
+        match failing {
+            Ok(_) => Err(io::Error::from(*self.kind.lock().unwrap()).into()),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl LogService for FailingDestination {
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        self.check()?;
+        self.console.insert_log_unit(log_unit).await
+    }
+
+    async fn log(&self, entry: LogEntry) -> LogResult<()> {
+        self.check()?;
+        if entry.message.starts_with("Invalid") {
+            return Err("entry violates a constraint".into());
+        }
+        self.console.log(entry).await
+    }
+
+    async fn get_log_entries(&self, log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
+        self.console.get_log_entries(log_unit_id).await
+    }
+
+    async fn get_log_unit(&self, log_unit_id: Uuid) -> LogResult<Option<LogUnit>> {
+        self.console.get_log_unit(log_unit_id).await
+    }
+
+    async fn get_log_units_by_external_id(&self, external_id: &str) -> LogResult<Vec<LogUnit>> {
+        self.console.get_log_units_by_external_id(external_id).await
+    }
+}
@@ -0,0 +1,94 @@
+//! Minimal HTTP/1.1 server standing in for the services of HTTP-based destinations in tests
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+
+use crate::destinations::retry::RetryPolicy;
+
+/// Request received by `MockServer`, with lowercase header names
+pub(crate) struct Request {
+    pub(crate) method: String,
+    /// Path including the query string
+    pub(crate) path: String,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Request {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Records every request and answers it with the status and JSON body returned by a handler
+pub(crate) struct MockServer {
+    pub(crate) url: String,
+    pub(crate) requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    pub(crate) async fn start<H>(handler: H) -> Self
+    where
+        H: Fn(&Request) -> (u16, String) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        let handler = Arc::new(handler);
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (recorded, handler) = (Arc::clone(&recorded), Arc::clone(&handler));
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    loop {
+                        let mut line = String::new();
+                        if stream.read_line(&mut line).await.unwrap() == 0 {
+                            return;
+                        }
+                        let mut parts = line.split_whitespace();
+                        let (method, path) = (parts.next().unwrap().to_string(), parts.next().unwrap().to_string());
+                        let mut headers = Vec::new();
+                        loop {
+                            line.clear();
+                            stream.read_line(&mut line).await.unwrap();
+                            match line.trim_end().split_once(": ") {
+                                Some((name, value)) => headers.push((name.to_lowercase(), value.to_string())),
+                                None => break,
+                            }
+                        }
+                        let length = headers.iter().find(|(n, _)| n == "content-length").map_or(0, |(_, v)| v.parse().unwrap());
+                        let mut body = vec![0; length];
+                        stream.read_exact(&mut body).await.unwrap();
+
+                        let request = Request { method, path, headers, body };
+                        let (status, body) = handler(&request);
+                        recorded.lock().await.push(request);
+                        let response = format!(
+                            "HTTP/1.1 {} Mock\r\ncontent-type: application/json\r\nretry-after: 0\r\ncontent-length: {}\r\n\r\n{}",
+                            status,
+                            body.len(),
+                            body
+                        );
+                        stream.get_mut().write_all(response.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        Self { url, requests }
+    }
+}
+
+/// Retries twice without noticeable delays
+pub(crate) fn fast_retries() -> RetryPolicy {
+    RetryPolicy {
+        max_retries: 2,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(10),
+        jitter: 0.0,
+    }
+}
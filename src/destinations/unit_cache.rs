@@ -0,0 +1,66 @@
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Number of units whose external ID is remembered by default
+pub(crate) const DEFAULT_CACHED_UNITS: usize = 10_000;
+
+/// External IDs of the most recently inserted units.
+///
+/// Lets write-only destinations label entries, which only carry the ID of
+/// their unit, with the unit's external ID.
+pub(crate) struct ExternalIdCache {
+    capacity: usize,
+    /// External IDs by unit ID, with the unit IDs in insertion order
+    units: RwLock<(HashMap<Uuid, String>, VecDeque<Uuid>)>,
+}
+
+impl ExternalIdCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            units: RwLock::new((HashMap::new(), VecDeque::new())),
+        }
+    }
+
+    /// Remembers the external ID of a unit, forgetting the oldest unit when full
+    pub(crate) async fn insert(&self, log_unit_id: Uuid, external_id: String) {
+        let mut units = self.units.write().await;
+        let (external_ids, order) = &mut *units;
+        if external_ids.insert(log_unit_id, external_id).is_none() {
+            order.push_back(log_unit_id);
+        }
+        while order.len() > self.capacity {
+            if let Some(oldest) = order.pop_front() {
+                external_ids.remove(&oldest);
+            }
+        }
+    }
+
+    pub(crate) async fn get(&self, log_unit_id: Uuid) -> Option<String> {
+        self.units.read().await.0.get(&log_unit_id).cloned()
+    }
+}
+
+impl Default for ExternalIdCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHED_UNITS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_oldest_unit_is_forgotten() {
+        let cache = ExternalIdCache::new(2);
+        let units = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        for (i, unit) in units.iter().enumerate() {
+            cache.insert(*unit, format!("job-{}", i)).await;
+        }
+
+        assert_eq!(cache.get(units[0]).await, None);
+        assert_eq!(cache.get(units[2]).await.as_deref(), Some("job-2"));
+    }
+}
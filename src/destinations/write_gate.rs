@@ -0,0 +1,57 @@
+use std::time::Duration;
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+use crate::core::log_service::LogResult;
+
+/// Tracks the writes in flight on a destination so they can be waited for on flush and shutdown
+pub(crate) struct WriteGate {
+    /// Held for reading by each write; `true` once shut down
+    closed: RwLock<bool>,
+}
+
+impl WriteGate {
+    pub(crate) fn new() -> Self {
+        Self {
+            closed: RwLock::new(false),
+        }
+    }
+
+    /// Starts a write, which `flush` and `close` wait for until the guard is dropped
+    pub(crate) async fn enter(&self) -> LogResult<RwLockReadGuard<'_, bool>> {
+        let closed = self.closed.read().await;
+        if *closed {
+            return Err("destination is shut down".into());
+        }
+        Ok(closed)
+    }
+
+    /// Waits until the writes started before the call have completed
+    pub(crate) async fn drain(&self) {
+        drop(self.closed.write().await);
+    }
+
+    /// Waits at most `timeout` for the writes in flight, then makes further writes fail
+    pub(crate) async fn close(&self, timeout: Duration) -> LogResult<()> {
+        let mut closed = tokio::time::timeout(timeout, self.closed.write())
+            .await
+            .map_err(|_| format!("timed out after {:?} waiting for writes in flight", timeout))?;
+        *closed = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_close_waits_for_writes_in_flight() {
+        let gate = WriteGate::new();
+        let write = gate.enter().await.unwrap();
+
+        assert!(gate.close(Duration::from_millis(10)).await.is_err());
+        drop(write);
+        gate.close(Duration::from_millis(10)).await.unwrap();
+        assert!(gate.enter().await.is_err());
+    }
+}
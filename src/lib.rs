@@ -6,24 +6,72 @@
 pub use crate::core::*;
 pub use crate::service::LogService;
 
+pub mod context;
 pub mod core;
 pub mod destinations;
 pub mod service;
 pub mod macros;
+pub mod panic_hook;
 
 // Re-export commonly used types
-pub use core::log_entry::{LogEntry, LogLevel, LogMessageType};
+pub use core::log_entry::{ErrorDetails, LogEntry, LogLevel, LogMessageType, SourceLocation, TraceContext};
+pub use core::analytics::{ExternalIdStats, HistogramBucket, LevelCounts, StatsFilter, TimeBucket, UnitRanking, UnitStats};
 pub use core::log_unit::LogUnit;
+pub use core::retention::{PurgeReport, RetentionPolicy};
+pub use core::search::{SearchHit, SearchOptions};
+pub use core::subscription::{Subscription, SubscriptionFilter};
+pub use context::{clear_default_handle, scope, scope_with, set_default_handle, LogContext};
+pub use panic_hook::PanicHook;
 pub use service::default::DefaultLogService;
+pub use service::handle::{LogHandle, TryLogError};
+pub use service::shutdown::ShutdownGuard;
+pub use destinations::MirrorDestination;
+pub use destinations::retry::{CircuitBreakerConfig, CircuitState, RetryMetrics, RetryPolicy, RetryingDestination};
 
-// Re-export macros
-pub use crate::macros::*;
+#[cfg(feature = "archive")]
+pub use core::archive::{ArchiveHeader, ArchiveReport, Compression, ExportOptions};
+
+#[cfg(feature = "bus")]
+pub use destinations::bus::{BusMessage, InMemoryPublisher, MessageBusConfig, MessageBusDestination, MessageFormat, MessagePublisher};
+
+#[cfg(feature = "clickhouse")]
+pub use destinations::clickhouse::{ClickHouseConfig, ClickHouseDestination, ClickHouseFormat};
+
+#[cfg(feature = "elastic")]
+pub use destinations::elastic::{ElasticConfig, ElasticDestination};
+
+#[cfg(feature = "http")]
+pub use destinations::http::{HttpAuth, HttpBodyFormat, HttpConfig, HttpDestination, HttpTemplate};
+
+#[cfg(all(feature = "journald", target_os = "linux"))]
+pub use destinations::journald::{JournaldConfig, JournaldDestination};
+
+#[cfg(feature = "kafka")]
+pub use destinations::kafka::{KafkaConfig, KafkaPublisher};
+
+#[cfg(feature = "loki")]
+pub use destinations::loki::{LokiConfig, LokiDestination, LokiEncoding};
 
 #[cfg(feature = "mongo")]
 pub use destinations::mongodb::{MongoDestination, MongoConfig};
 
+#[cfg(feature = "nats")]
+pub use destinations::nats::NatsPublisher;
+
+#[cfg(feature = "otlp")]
+pub use destinations::otlp::{OtlpConfig, OtlpDestination, OtlpProtocol};
+
 #[cfg(feature = "postgres")]
-pub use destinations::postgres::{PostgresDestination, PostgresConfig};
+pub use destinations::postgres::{PartitionConfig, PartitionInterval, PostgresDestination, PostgresConfig};
+
+#[cfg(feature = "redis-streams")]
+pub use destinations::redis_streams::{RedisStreamsConfig, RedisStreamsDestination};
+
+#[cfg(feature = "spool")]
+pub use destinations::spool::{SpoolConfig, SpoolMetrics, SpoolingDestination};
+
+#[cfg(feature = "syslog")]
+pub use destinations::syslog::{SyslogConfig, SyslogDestination, SyslogFacility, SyslogFormat, SyslogTransport};
 
 #[cfg(feature = "console")]
 pub use destinations::console::ConsoleDestination;
\ No newline at end of file
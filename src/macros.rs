@@ -1,10 +1,64 @@
 //! Macros for convenient logging
 
+use std::error::Error;
+
+/// Argument of `error!(service, unit, err = e; ...)`, accepting both error
+/// types and boxed errors such as the ones in a `LogResult`
+#[doc(hidden)]
+pub struct ErrorArg<'a, T: ?Sized>(pub &'a T);
+
+/// Picked for boxed errors, which do not implement `Error` themselves
+#[doc(hidden)]
+pub trait BoxedErrorArg {
+    fn as_dyn_error(&self) -> &dyn Error;
+}
+
+impl BoxedErrorArg for ErrorArg<'_, Box<dyn Error + Send + Sync>> {
+    fn as_dyn_error(&self) -> &dyn Error {
+        self.0.as_ref()
+    }
+}
+
+impl BoxedErrorArg for ErrorArg<'_, Box<dyn Error>> {
+    fn as_dyn_error(&self) -> &dyn Error {
+        self.0.as_ref()
+    }
+}
+
+/// Picked for everything implementing `Error`, through the extra reference
+/// method resolution adds when `BoxedErrorArg` does not apply
+#[doc(hidden)]
+pub trait PlainErrorArg {
+    fn as_dyn_error(&self) -> &dyn Error;
+}
+
+impl<E: Error> PlainErrorArg for &ErrorArg<'_, E> {
+    fn as_dyn_error(&self) -> &dyn Error {
+        self.0
+    }
+}
+
 /// Creates an info log entry and logs it
+///
+/// Without a service and unit, the entry is queued on the current context set by
+/// `ironscribe::scope`. With `handle = h, unit`, it is queued on the `LogHandle` `h`.
+/// Neither form awaits, so both also work in synchronous code; the
+/// `service, unit` form has to be awaited.
 #[macro_export]
 macro_rules! info {
+    ($fmt:literal $(, $($arg:tt)*)?) => {{
+        $crate::context::log_in_scope($crate::LogMessageType::Info, format!($fmt $(, $($arg)*)?), $crate::source_location!())
+    }};
+    (handle = $handle:expr, $unit:expr, $($arg:tt)*) => {{
+        let entry = $crate::LogEntry::info($unit.log_unit_id, format!($($arg)*))
+            .with_location($crate::source_location!());
+        if let Err(e) = $handle.try_log(entry) {
+            eprintln!("Failed to log info message: {}", e);
+        }
+    }};
     ($service:expr, $unit:expr, $($arg:tt)*) => {{
-        let entry = $crate::LogEntry::info($unit.log_unit_id, format!($($arg)*));
+        let entry = $crate::LogEntry::info($unit.log_unit_id, format!($($arg)*))
+            .with_location($crate::source_location!());
         match $service.log(entry).await {
             Ok(_) => {}
             Err(e) => eprintln!("Failed to log info message: {}", e),
@@ -13,10 +67,26 @@ macro_rules! info {
 }
 
 /// Creates a warning log entry and logs it
+///
+/// Without a service and unit, the entry is queued on the current context set by
+/// `ironscribe::scope`. With `handle = h, unit`, it is queued on the `LogHandle` `h`.
+/// Neither form awaits, so both also work in synchronous code; the
+/// `service, unit` form has to be awaited.
 #[macro_export]
 macro_rules! warn {
+    ($fmt:literal $(, $($arg:tt)*)?) => {{
+        $crate::context::log_in_scope($crate::LogMessageType::Warning, format!($fmt $(, $($arg)*)?), $crate::source_location!())
+    }};
+    (handle = $handle:expr, $unit:expr, $($arg:tt)*) => {{
+        let entry = $crate::LogEntry::warning($unit.log_unit_id, format!($($arg)*))
+            .with_location($crate::source_location!());
+        if let Err(e) = $handle.try_log(entry) {
+            eprintln!("Failed to log warning message: {}", e);
+        }
+    }};
     ($service:expr, $unit:expr, $($arg:tt)*) => {{
-        let entry = $crate::LogEntry::warning($unit.log_unit_id, format!($($arg)*));
+        let entry = $crate::LogEntry::warning($unit.log_unit_id, format!($($arg)*))
+            .with_location($crate::source_location!());
         match $service.log(entry).await {
             Ok(_) => {}
             Err(e) => eprintln!("Failed to log warning message: {}", e),
@@ -25,10 +95,50 @@ macro_rules! warn {
 }
 
 /// Creates an error log entry and logs it
+///
+/// Without a service and unit, the entry is queued on the current context set by
+/// `ironscribe::scope`. With `handle = h, unit`, it is queued on the `LogHandle` `h`.
+/// Neither form awaits, so both also work in synchronous code; the
+/// `service, unit` form has to be awaited.
+///
+/// `error!(service, unit, err = e; "...")` and `error!(handle = h, unit, err = e; "...")`
+/// also record the error `e` with its chain of sources, see `LogEntry::with_error`.
 #[macro_export]
 macro_rules! error {
+    ($fmt:literal $(, $($arg:tt)*)?) => {{
+        $crate::context::log_in_scope($crate::LogMessageType::Error, format!($fmt $(, $($arg)*)?), $crate::source_location!())
+    }};
+    (handle = $handle:expr, $unit:expr, err = $err:expr; $($arg:tt)*) => {{
+        #[allow(unused_imports)]
+        use $crate::macros::{BoxedErrorArg as _, PlainErrorArg as _};
+        let entry = $crate::LogEntry::error($unit.log_unit_id, format!($($arg)*))
+            .with_error((&$crate::macros::ErrorArg(&$err)).as_dyn_error())
+            .with_location($crate::source_location!());
+        if let Err(e) = $handle.try_log(entry) {
+            eprintln!("Failed to log error message: {}", e);
+        }
+    }};
+    (handle = $handle:expr, $unit:expr, $($arg:tt)*) => {{
+        let entry = $crate::LogEntry::error($unit.log_unit_id, format!($($arg)*))
+            .with_location($crate::source_location!());
+        if let Err(e) = $handle.try_log(entry) {
+            eprintln!("Failed to log error message: {}", e);
+        }
+    }};
+    ($service:expr, $unit:expr, err = $err:expr; $($arg:tt)*) => {{
+        #[allow(unused_imports)]
+        use $crate::macros::{BoxedErrorArg as _, PlainErrorArg as _};
+        let entry = $crate::LogEntry::error($unit.log_unit_id, format!($($arg)*))
+            .with_error((&$crate::macros::ErrorArg(&$err)).as_dyn_error())
+            .with_location($crate::source_location!());
+        match $service.log(entry).await {
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to log error message: {}", e),
+        }
+    }};
     ($service:expr, $unit:expr, $($arg:tt)*) => {{
-        let entry = $crate::LogEntry::error($unit.log_unit_id, format!($($arg)*));
+        let entry = $crate::LogEntry::error($unit.log_unit_id, format!($($arg)*))
+            .with_location($crate::source_location!());
         match $service.log(entry).await {
             Ok(_) => {}
             Err(e) => eprintln!("Failed to log error message: {}", e),
@@ -37,10 +147,26 @@ macro_rules! error {
 }
 
 /// Creates a success log entry and logs it
+///
+/// Without a service and unit, the entry is queued on the current context set by
+/// `ironscribe::scope`. With `handle = h, unit`, it is queued on the `LogHandle` `h`.
+/// Neither form awaits, so both also work in synchronous code; the
+/// `service, unit` form has to be awaited.
 #[macro_export]
 macro_rules! success {
+    ($fmt:literal $(, $($arg:tt)*)?) => {{
+        $crate::context::log_in_scope($crate::LogMessageType::Success, format!($fmt $(, $($arg)*)?), $crate::source_location!())
+    }};
+    (handle = $handle:expr, $unit:expr, $($arg:tt)*) => {{
+        let entry = $crate::LogEntry::success($unit.log_unit_id, format!($($arg)*))
+            .with_location($crate::source_location!());
+        if let Err(e) = $handle.try_log(entry) {
+            eprintln!("Failed to log success message: {}", e);
+        }
+    }};
     ($service:expr, $unit:expr, $($arg:tt)*) => {{
-        let entry = $crate::LogEntry::success($unit.log_unit_id, format!($($arg)*));
+        let entry = $crate::LogEntry::success($unit.log_unit_id, format!($($arg)*))
+            .with_location($crate::source_location!());
         match $service.log(entry).await {
             Ok(_) => {}
             Err(e) => eprintln!("Failed to log success message: {}", e),
@@ -48,6 +174,14 @@ macro_rules! success {
     }};
 }
 
+/// Captures the source location of the call as a `SourceLocation`
+#[macro_export]
+macro_rules! source_location {
+    () => {
+        $crate::SourceLocation::new(file!(), line!(), column!(), module_path!())
+    };
+}
+
 /// Convenience macro to create a log unit and return its ID
 #[macro_export]
 macro_rules! create_log_unit {
@@ -80,5 +214,51 @@ mod tests {
 
         let entries = service.get_log_entries(unit.log_unit_id).await.unwrap();
         assert_eq!(entries.len(), 4);
+        let location = entries[0].location.as_ref().unwrap();
+        assert_eq!(location.file, file!());
+        assert_eq!(location.module_path, module_path!());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "console")]
+    async fn test_error_macro_records_errors() {
+        let service = DefaultLogService::new();
+        let unit = create_log_unit!(service, "errors");
+
+        let io_error = std::io::Error::other("disk full");
+        error!(service, unit, err = io_error; "Failed to write {}", "report.csv");
+        let boxed: crate::core::log_service::LogResult<()> = Err("connection reset".into());
+        if let Err(e) = boxed {
+            error!(service, unit, err = e; "Failed to sync");
+        }
+
+        let entries = service.get_log_entries(unit.log_unit_id).await.unwrap();
+        assert_eq!(entries[0].message, "Failed to write report.csv");
+        assert_eq!(entries[0].error.as_ref().unwrap().chain, vec!["disk full".to_string()]);
+        assert_eq!(entries[1].error.as_ref().unwrap().chain, vec!["connection reset".to_string()]);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "console")]
+    async fn test_handle_macros_log_from_sync_code() {
+        let service = DefaultLogService::new();
+        let unit = create_log_unit!(service, "sync");
+        let handle = service.handle();
+
+        std::thread::spawn(move || {
+            info!(handle = handle, unit, "Started {}", "job");
+            warn!(handle = handle, unit, "Slow");
+            error!(handle = handle, unit, err = std::io::Error::other("timeout"); "Failed");
+            success!(handle = handle, unit, "Done");
+        })
+        .join()
+        .unwrap();
+
+        service.flush().await.unwrap();
+        let entries = service.get_log_entries(unit.log_unit_id).await.unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].message, "Started job");
+        assert_eq!(entries[2].error.as_ref().unwrap().chain, vec!["timeout".to_string()]);
+        assert_eq!(entries[3].location.as_ref().unwrap().file, file!());
     }
 }
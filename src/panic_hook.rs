@@ -0,0 +1,139 @@
+//! Panic hook that records panics in the log before the default output
+
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::panic::{self, PanicHookInfo};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::context;
+use crate::core::log_service::LogResult;
+use crate::core::{ErrorDetails, LogEntry, LogService, LogUnit, SourceLocation};
+use crate::service::shutdown::block_on_detached;
+use crate::service::LogHandle;
+
+/// How long a panicking thread waits for its entry to be written by default
+pub const DEFAULT_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+thread_local! {
+    /// Set on the thread writing a panic entry, so a panic while writing is not recorded again
+    static WRITING_PANIC: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Records every panic as an error entry, then runs the previously installed hook.
+///
+/// The entry goes to the unit of the current context (see `ironscribe::scope`),
+/// or to the default unit for panics outside of any context.
+pub struct PanicHook {
+    service: Arc<dyn LogService>,
+    default_unit: Option<LogUnit>,
+    flush_timeout: Duration,
+    force_backtrace: bool,
+}
+
+impl PanicHook {
+    /// Creates a hook writing panic entries to `service`
+    pub fn new(service: Arc<dyn LogService>) -> Self {
+        Self {
+            service,
+            default_unit: None,
+            flush_timeout: DEFAULT_FLUSH_TIMEOUT,
+            force_backtrace: false,
+        }
+    }
+
+    /// Records panics outside of any log context in `unit`; without one they are not recorded
+    pub fn with_default_unit(mut self, unit: LogUnit) -> Self {
+        self.default_unit = Some(unit);
+        self
+    }
+
+    /// Sets how long a panicking thread waits for its entry to be written
+    pub fn with_flush_timeout(mut self, timeout: Duration) -> Self {
+        self.flush_timeout = timeout;
+        self
+    }
+
+    /// Captures a backtrace for every panic, even when `RUST_BACKTRACE` is not set
+    pub fn with_forced_backtrace(mut self) -> Self {
+        self.force_backtrace = true;
+        self
+    }
+
+    /// Installs the hook, chaining to the hook installed before
+    pub fn install(self) {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            self.record(info);
+            previous(info);
+        }));
+    }
+
+    fn record(&self, info: &PanicHookInfo<'_>) {
+        if WRITING_PANIC.with(Cell::get) {
+            return;
+        }
+
+        let context = context::current();
+        let Some(unit) = context.as_ref().map(|c| c.unit()).or(self.default_unit.as_ref()) else {
+            return;
+        };
+        let entry = self.entry(unit.log_unit_id, info);
+        let handle = context.as_ref().and_then(|c| c.handle()).cloned();
+
+        if let Err(e) = self.write(entry, handle) {
+            eprintln!("Failed to log panic: {}", e);
+        }
+    }
+
+    /// Builds the error entry describing a panic
+    fn entry(&self, log_unit_id: Uuid, info: &PanicHookInfo<'_>) -> LogEntry {
+        let payload = info
+            .payload()
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| info.payload().downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Box<dyn Any>");
+        let thread = thread::current();
+        let message = format!("thread '{}' panicked: {}", thread.name().unwrap_or("<unnamed>"), payload);
+
+        let backtrace = if self.force_backtrace {
+            Backtrace::force_capture()
+        } else {
+            Backtrace::capture()
+        };
+        let mut entry = LogEntry::error(log_unit_id, message);
+        entry.error = Some(
+            ErrorDetails {
+                chain: vec![payload.to_string()],
+                backtrace: None,
+            }
+            .with_backtrace(&backtrace),
+        );
+        // The panic location carries no module path
+        match info.location() {
+            Some(location) => entry.with_location(SourceLocation::new(location.file(), location.line(), location.column(), "")),
+            None => entry,
+        }
+    }
+
+    /// Writes `entry` after the entries already queued on `handle`, waiting at most `flush_timeout`.
+    ///
+    /// Queued entries are only waited for during half of the timeout, as their
+    /// background task may need the runtime blocked by the panicking thread.
+    fn write(&self, entry: LogEntry, handle: Option<LogHandle>) -> LogResult<()> {
+        let service = Arc::clone(&self.service);
+        let queue_timeout = self.flush_timeout / 2;
+        let write = async move {
+            WRITING_PANIC.with(|writing| writing.set(true));
+            if let Some(handle) = handle {
+                // A closed queue has nothing left to write
+                let _ = tokio::time::timeout(queue_timeout, handle.flush()).await;
+            }
+            service.log(entry).await
+        };
+        block_on_detached(write, self.flush_timeout)
+    }
+}
@@ -1,47 +1,60 @@
 use async_trait::async_trait;
-use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use uuid::Uuid;
 
-use crate::core::{LogEntry, LogService as LogServiceTrait, LogUnit};
+use crate::core::{ExternalIdStats, HistogramBucket, LevelCounts, LogEntry, LogLevel, LogService as LogServiceTrait, LogUnit, RetentionPolicy, SearchHit, SearchOptions, StatsFilter, Subscription, SubscriptionFilter, TimeBucket, UnitRanking, UnitStats};
 use crate::core::log_service::LogResult;
+use crate::context;
+use crate::core::subscription;
+use crate::destinations::retry::RetryMetrics;
+use crate::panic_hook::PanicHook;
+use crate::service::handle::{LogHandle, DEFAULT_QUEUE_CAPACITY};
+use crate::service::shutdown::ShutdownGuard;
 
 #[cfg(feature = "console")]
-use crate::destinations::ConsoleDestination;
+use crate::destinations::{ConsoleDestination, MirrorDestination};
 #[cfg(feature = "mongo")]
 use crate::destinations::MongoDestination;
 #[cfg(feature = "postgres")]
 use crate::destinations::PostgresDestination;
 
+/// Number of entries buffered per subscriber before it starts missing entries
+const DEFAULT_SUBSCRIPTION_CAPACITY: usize = 1024;
+
 /// Default log service implementation that delegates to the configured destination
 pub struct DefaultLogService {
     destination: Arc<dyn LogServiceTrait>,
+    /// Publishes every successfully logged entry to subscribers
+    subscribers: broadcast::Sender<LogEntry>,
+    queue_capacity: usize,
+    /// Shared handle for synchronous logging, created on first use
+    handle: Arc<OnceLock<LogHandle>>,
 }
 
 impl DefaultLogService {
     /// Creates a new default log service with console destination
     #[cfg(feature = "console")]
     pub fn new_console() -> Self {
-        Self {
-            destination: Arc::new(ConsoleDestination::new()),
-        }
+        Self::with_destination(Arc::new(ConsoleDestination::new()))
     }
 
     /// Creates a new default log service with MongoDB destination
     #[cfg(feature = "mongo")]
     pub async fn new_mongodb(config: crate::destinations::mongodb::MongoConfig) -> LogResult<Self> {
         let destination = MongoDestination::new(config).await?;
-        Ok(Self {
-            destination: Arc::new(destination),
-        })
+        Ok(Self::with_destination(Arc::new(destination)))
     }
 
     /// Creates a new default log service with PostgreSQL destination
     #[cfg(feature = "postgres")]
     pub async fn new_postgres(config: crate::destinations::postgres::PostgresConfig) -> LogResult<Self> {
         let destination = PostgresDestination::new(config).await?;
-        Ok(Self {
-            destination: Arc::new(destination),
-        })
+        Ok(Self::with_destination(Arc::new(destination)))
     }
 
     /// Creates a new default log service with the default destination (console)
@@ -58,7 +71,118 @@ impl DefaultLogService {
 
     /// Creates a service with a custom destination
     pub fn with_destination(destination: Arc<dyn LogServiceTrait>) -> Self {
-        Self { destination }
+        let (subscribers, _) = broadcast::channel(DEFAULT_SUBSCRIPTION_CAPACITY);
+        Self {
+            destination,
+            subscribers,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            handle: Arc::default(),
+        }
+    }
+
+    /// Sets how many entries a subscriber can fall behind before it misses entries.
+    ///
+    /// Existing subscriptions keep their previous capacity and stop receiving entries.
+    pub fn with_subscription_capacity(mut self, capacity: usize) -> Self {
+        self.subscribers = broadcast::channel(capacity).0;
+        self
+    }
+
+    /// Sets how many entries the handle returned by `handle` can queue
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /// Returns a handle for logging from synchronous code without blocking.
+    ///
+    /// Entries queued on the handle are written through this service, so
+    /// subscribers see them as well. The first call spawns the background
+    /// task and must happen within a Tokio runtime.
+    pub fn handle(&self) -> LogHandle {
+        self.handle
+            .get_or_init(|| LogHandle::spawn(self.writer(), self.queue_capacity))
+            .clone()
+    }
+
+    /// Makes the handle of this service the default that `ironscribe::scope`
+    /// logs through outside of any context.
+    ///
+    /// The handle and its background task, and with them the destination,
+    /// stay alive until another default is set or `clear_default_handle` is
+    /// called. Must be called within a Tokio runtime.
+    pub fn set_as_default(&self) {
+        context::set_default_handle(self.handle());
+    }
+
+    /// Returns a panic hook writing through this service; call `install` on it to activate it
+    pub fn panic_hook(&self) -> PanicHook {
+        PanicHook::new(self.writer())
+    }
+
+    /// Returns a guard that flushes the handle and shuts the destination down when dropped.
+    ///
+    /// Create it right after the service, e.g. `let _guard = service.shutdown_guard();`,
+    /// or construct the service with `guarded`.
+    pub fn shutdown_guard(&self) -> ShutdownGuard {
+        ShutdownGuard::new(Arc::new(self.share(Arc::clone(&self.handle))))
+    }
+
+    /// Finishes construction, returning the service together with its shutdown guard.
+    ///
+    /// E.g. `let (service, _guard) = DefaultLogService::new().guarded();` in `main`.
+    pub fn guarded(self) -> (Self, ShutdownGuard) {
+        let guard = self.shutdown_guard();
+        (self, guard)
+    }
+
+    /// Service sharing the destination and subscribers of this one, for writing from elsewhere
+    fn writer(&self) -> Arc<dyn LogServiceTrait> {
+        Arc::new(self.share(Arc::default()))
+    }
+
+    fn share(&self, handle: Arc<OnceLock<LogHandle>>) -> Self {
+        Self {
+            destination: Arc::clone(&self.destination),
+            subscribers: self.subscribers.clone(),
+            queue_capacity: self.queue_capacity,
+            handle,
+        }
+    }
+
+    /// Spawns a background task that applies `policy` every `interval`.
+    ///
+    /// The first run happens immediately. Failures are reported on stderr and
+    /// retried on the next tick; abort the returned handle to stop the task.
+    /// Intervals shorter than a second, including zero, are raised to a second.
+    pub fn spawn_retention(&self, policy: RetentionPolicy, interval: Duration) -> JoinHandle<()> {
+        let destination = Arc::clone(&self.destination);
+        let interval = interval.max(Duration::from_secs(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = destination.apply_retention(&policy).await {
+                    eprintln!("Failed to apply log retention: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Echoes every write of the current destination to the console.
+    ///
+    /// Reads are still answered by the current destination, and log units keep
+    /// the same ID on the console as in the underlying storage.
+    #[cfg(feature = "console")]
+    pub fn with_console_echo(self) -> Self {
+        let mirror = MirrorDestination::new(self.destination)
+            .with_mirror(Arc::new(ConsoleDestination::echo_only()));
+        Self {
+            destination: Arc::new(mirror),
+            // A handle created before would bypass the echo
+            handle: Arc::default(),
+            ..self
+        }
     }
 }
 
@@ -74,8 +198,19 @@ impl LogServiceTrait for DefaultLogService {
         self.destination.create_log_unit(external_id).await
     }
 
+    async fn insert_log_unit(&self, log_unit: LogUnit) -> LogResult<()> {
+        self.destination.insert_log_unit(log_unit).await
+    }
+
     async fn log(&self, entry: LogEntry) -> LogResult<()> {
-        self.destination.log(entry).await
+        if self.subscribers.receiver_count() == 0 {
+            return self.destination.log(entry).await;
+        }
+
+        self.destination.log(entry.clone()).await?;
+        // Sending only fails when the last subscriber went away in the meantime
+        let _ = self.subscribers.send(entry);
+        Ok(())
     }
 
     async fn get_log_entries(&self, log_unit_id: Uuid) -> LogResult<Vec<LogEntry>> {
@@ -89,6 +224,82 @@ impl LogServiceTrait for DefaultLogService {
     async fn get_log_units_by_external_id(&self, external_id: &str) -> LogResult<Vec<LogUnit>> {
         self.destination.get_log_units_by_external_id(external_id).await
     }
+
+    async fn list_log_units(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> LogResult<Vec<LogUnit>> {
+        self.destination.list_log_units(from, to).await
+    }
+
+    async fn search_entries(&self, text: &str, options: &SearchOptions) -> LogResult<Vec<SearchHit>> {
+        self.destination.search_entries(text, options).await
+    }
+
+    /// Counts the entries stored in the destination, including those written by other services
+    async fn count_by_level(&self, filter: &StatsFilter) -> LogResult<LevelCounts> {
+        self.destination.count_by_level(filter).await
+    }
+
+    async fn entry_histogram(&self, filter: &StatsFilter, bucket: TimeBucket) -> LogResult<Vec<HistogramBucket>> {
+        self.destination.entry_histogram(filter, bucket).await
+    }
+
+    async fn top_units(&self, filter: &StatsFilter, ranking: UnitRanking, limit: usize) -> LogResult<Vec<UnitStats>> {
+        self.destination.top_units(filter, ranking, limit).await
+    }
+
+    async fn counts_by_external_id(&self, filter: &StatsFilter) -> LogResult<Vec<ExternalIdStats>> {
+        self.destination.counts_by_external_id(filter).await
+    }
+
+    /// Subscribes to entries logged through this service.
+    ///
+    /// Entries written to the destination by other services are not included;
+    /// subscribe on the destination itself to see those, if it supports it.
+    async fn subscribe(&self, filter: SubscriptionFilter) -> LogResult<Subscription> {
+        Ok(subscription::from_broadcast(
+            self.subscribers.subscribe(),
+            filter,
+            Arc::clone(&self.destination),
+        ))
+    }
+
+    async fn delete_log_unit(&self, log_unit_id: Uuid) -> LogResult<bool> {
+        self.destination.delete_log_unit(log_unit_id).await
+    }
+
+    async fn purge_entries(&self, before: DateTime<Utc>, level: Option<LogLevel>) -> LogResult<u64> {
+        self.destination.purge_entries(before, level).await
+    }
+
+    async fn purge_empty_log_units(&self, before: DateTime<Utc>) -> LogResult<u64> {
+        self.destination.purge_empty_log_units(before).await
+    }
+
+    async fn trim_log_units(&self, max_units: usize) -> LogResult<u64> {
+        self.destination.trim_log_units(max_units).await
+    }
+
+    /// Flushes the entries queued on the handle, then the destination
+    async fn flush(&self) -> LogResult<()> {
+        if let Some(handle) = self.handle.get() {
+            handle.flush().await?;
+        }
+        self.destination.flush().await
+    }
+
+    /// Flushes the entries queued on the handle, then shuts the destination down
+    async fn shutdown(&self, timeout: Duration) -> LogResult<()> {
+        let deadline = Instant::now() + timeout;
+        if let Some(handle) = self.handle.get() {
+            tokio::time::timeout(timeout, handle.flush())
+                .await
+                .map_err(|_| format!("timed out after {:?} flushing the log queue", timeout))??;
+        }
+        self.destination.shutdown(deadline.saturating_duration_since(Instant::now())).await
+    }
+
+    fn retry_metrics(&self) -> Option<RetryMetrics> {
+        self.destination.retry_metrics()
+    }
 }
 
 #[cfg(test)]
@@ -108,4 +319,49 @@ mod tests {
         let entries = service.get_log_entries(unit.log_unit_id).await.unwrap();
         assert_eq!(entries.len(), 1);
     }
+
+    #[tokio::test]
+    #[cfg(feature = "console")]
+    async fn test_zero_retention_interval_is_raised() {
+        let service = DefaultLogService::new();
+        let retention = service.spawn_retention(RetentionPolicy::default(), Duration::ZERO);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!retention.is_finished());
+        retention.abort();
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "console")]
+    async fn test_console_echo_reads_from_storage() {
+        let storage = Arc::new(ConsoleDestination::new());
+        let service = DefaultLogService::with_destination(storage.clone()).with_console_echo();
+        let unit = service.create_log_unit("echo".to_string()).await.unwrap();
+
+        service.log(LogEntry::info(unit.log_unit_id, "Echoed".to_string())).await.unwrap();
+
+        assert_eq!(storage.get_log_unit(unit.log_unit_id).await.unwrap(), Some(unit.clone()));
+        assert_eq!(service.get_log_entries(unit.log_unit_id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "console")]
+    async fn test_subscribe_filters_and_reports_lag() {
+        use futures::StreamExt;
+
+        let service = DefaultLogService::new().with_subscription_capacity(2);
+        let job = service.create_log_unit("job".to_string()).await.unwrap();
+        let other = service.create_log_unit("other".to_string()).await.unwrap();
+
+        let mut errors = service
+            .subscribe(SubscriptionFilter::for_external_id("job").min_level(LogLevel::Warning))
+            .await
+            .unwrap();
+        service.log(LogEntry::info(job.log_unit_id, "Ignored".to_string())).await.unwrap();
+        service.log(LogEntry::error(other.log_unit_id, "Other unit".to_string())).await.unwrap();
+        service.log(LogEntry::error(job.log_unit_id, "Failed".to_string())).await.unwrap();
+
+        let entry = errors.next().await.unwrap();
+        assert_eq!(entry.message, "Failed");
+        assert_eq!(errors.missed_entries(), 1);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,169 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::core::log_service::LogResult;
+use crate::core::{LogEntry, LogService};
+
+/// Number of entries a `LogHandle` queues before it starts rejecting entries
+pub const DEFAULT_QUEUE_CAPACITY: usize = 8192;
+
+enum QueueCommand {
+    Entry(Box<LogEntry>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Error returned by `LogHandle::try_log`, giving the entry back
+#[derive(Debug)]
+pub enum TryLogError {
+    /// The queue is full; the background task is behind
+    Full(Box<LogEntry>),
+    /// The background task has stopped, e.g. because its runtime shut down
+    Closed(Box<LogEntry>),
+}
+
+impl TryLogError {
+    /// Returns the entry that was not queued
+    pub fn into_entry(self) -> LogEntry {
+        match self {
+            TryLogError::Full(entry) | TryLogError::Closed(entry) => *entry,
+        }
+    }
+}
+
+impl fmt::Display for TryLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryLogError::Full(_) => write!(f, "log queue is full"),
+            TryLogError::Closed(_) => write!(f, "log queue is closed"),
+        }
+    }
+}
+
+impl std::error::Error for TryLogError {}
+
+/// Synchronous, non-blocking handle for logging from code that cannot await.
+///
+/// Entries are queued and written by a background task in the order they
+/// were queued. Handles are cheap to clone and can be used from any thread,
+/// including threads outside the Tokio runtime and `Drop` implementations.
+#[derive(Clone)]
+pub struct LogHandle {
+    sender: mpsc::Sender<QueueCommand>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl LogHandle {
+    /// Spawns a background task writing queued entries to `service`.
+    ///
+    /// Must be called from within a Tokio runtime. The task ends once every
+    /// clone of the handle is dropped and the queue is drained.
+    pub fn spawn(service: Arc<dyn LogService>, capacity: usize) -> Self {
+        let (sender, mut receiver) = mpsc::channel(capacity);
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                match command {
+                    QueueCommand::Entry(entry) => {
+                        if let Err(e) = service.log(*entry).await {
+                            eprintln!("Failed to log queued entry: {}", e);
+                        }
+                    }
+                    QueueCommand::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+
+        Self {
+            sender,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Queues `entry` and returns immediately.
+    ///
+    /// If the queue is full or closed the entry is dropped and counted in
+    /// `dropped_entries`.
+    pub fn log(&self, entry: LogEntry) {
+        if self.try_log(entry).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Queues `entry`, or gives it back if the queue is full or closed
+    pub fn try_log(&self, entry: LogEntry) -> Result<(), TryLogError> {
+        self.sender.try_send(QueueCommand::Entry(Box::new(entry))).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(QueueCommand::Entry(entry)) => TryLogError::Full(entry),
+            mpsc::error::TrySendError::Closed(QueueCommand::Entry(entry)) => TryLogError::Closed(entry),
+            _ => unreachable!("only entries are sent with try_send"),
+        })
+    }
+
+    /// Waits until every entry queued before the call has been written
+    pub async fn flush(&self) -> LogResult<()> {
+        let (done, flushed) = oneshot::channel();
+        self.sender
+            .send(QueueCommand::Flush(done))
+            .await
+            .map_err(|_| "log queue is closed")?;
+        flushed.await.map_err(|_| "log queue is closed")?;
+        Ok(())
+    }
+
+    /// Returns whether the background task has stopped, so entries can no longer be queued
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// Returns the number of entries dropped by `log` because the queue was full or closed
+    pub fn dropped_entries(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "console")]
+mod tests {
+    use super::*;
+    use crate::destinations::ConsoleDestination;
+
+    #[tokio::test]
+    async fn test_handle_logs_from_other_threads() {
+        let service: Arc<dyn LogService> = Arc::new(ConsoleDestination::new());
+        let unit = service.create_log_unit("sync".to_string()).await.unwrap();
+        let handle = LogHandle::spawn(Arc::clone(&service), 16);
+
+        let thread_handle = handle.clone();
+        std::thread::spawn(move || {
+            for i in 0..3 {
+                thread_handle.log(LogEntry::info(unit.log_unit_id, format!("Entry {}", i)));
+            }
+        })
+        .join()
+        .unwrap();
+
+        handle.flush().await.unwrap();
+        let entries = service.get_log_entries(unit.log_unit_id).await.unwrap();
+        let messages: Vec<&str> = entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["Entry 0", "Entry 1", "Entry 2"]);
+        assert_eq!(handle.dropped_entries(), 0);
+    }
+
+    #[test]
+    fn test_try_log_reports_full_queue() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        // The worker never runs on this idle runtime, so the queue fills up
+        let handle = runtime.block_on(async { LogHandle::spawn(Arc::new(ConsoleDestination::new()), 1) });
+        let unit_id = uuid::Uuid::new_v4();
+
+        assert!(handle.try_log(LogEntry::info(unit_id, "First".to_string())).is_ok());
+        let rejected = handle.try_log(LogEntry::info(unit_id, "Second".to_string())).unwrap_err();
+        assert!(matches!(rejected, TryLogError::Full(_)));
+        assert_eq!(rejected.into_entry().message, "Second");
+
+        handle.log(LogEntry::info(unit_id, "Third".to_string()));
+        assert_eq!(handle.dropped_entries(), 1);
+    }
+}
@@ -1,8 +1,12 @@
 //! Default log service implementation
 
 pub mod default;
+pub mod handle;
+pub mod shutdown;
 
 pub use default::DefaultLogService;
+pub use handle::{LogHandle, TryLogError};
+pub use shutdown::ShutdownGuard;
 
 /// Type alias for the default log service
 pub type LogService = DefaultLogService;
\ No newline at end of file
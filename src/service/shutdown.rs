@@ -0,0 +1,111 @@
+use std::future::Future;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use crate::core::log_service::LogResult;
+use crate::core::LogService;
+
+/// How long a `ShutdownGuard` waits for the service to shut down by default
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Shuts a service down when dropped, flushing what was logged before.
+///
+/// Keep the guard in a local of `main` so entries are written however `main`
+/// ends, including an early return of an error with `?`.
+///
+/// Dropping the guard on the thread of a `current_thread` runtime blocks that
+/// runtime, so entries still queued on a `LogHandle` cannot be written and the
+/// shutdown times out. With such a runtime, await `LogService::shutdown`
+/// before `main` returns instead.
+#[must_use = "the service is shut down as soon as the guard is dropped"]
+pub struct ShutdownGuard {
+    service: Arc<dyn LogService>,
+    timeout: Duration,
+}
+
+impl ShutdownGuard {
+    /// Creates a guard shutting `service` down when dropped
+    pub fn new(service: Arc<dyn LogService>) -> Self {
+        Self {
+            service,
+            timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+        }
+    }
+
+    /// Sets how long dropping the guard waits for the service to shut down
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        let service = Arc::clone(&self.service);
+        let timeout = self.timeout;
+        if let Err(e) = block_on_detached(async move { service.shutdown(timeout).await }, timeout) {
+            eprintln!("Failed to shut down log service: {}", e);
+        }
+    }
+}
+
+/// Runs `future` on a new thread with a runtime of its own, waiting at most `timeout` for it.
+///
+/// Lets synchronous code such as `Drop` or a panic hook wait for a write even
+/// on a thread driving a runtime, where `block_on` would panic. Work the
+/// future leaves to tasks of that runtime, such as a database connection or
+/// the queue of a `LogHandle`, only progresses if the runtime has other
+/// threads; on a `current_thread` runtime it waits for the full `timeout`.
+pub(crate) fn block_on_detached<F>(future: F, timeout: Duration) -> LogResult<()>
+where
+    F: Future<Output = LogResult<()>> + Send + 'static,
+{
+    let (done, finished) = mpsc::channel();
+    thread::Builder::new().name("ironscribe-flush".to_string()).spawn(move || {
+        let result = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(Into::into)
+            .and_then(|runtime| runtime.block_on(future));
+        let _ = done.send(result);
+    })?;
+
+    finished
+        .recv_timeout(timeout)
+        .map_err(|_| format!("timed out after {:?}", timeout))?
+}
+
+#[cfg(test)]
+#[cfg(feature = "console")]
+mod tests {
+    use super::*;
+    use crate::core::{LogEntry, LogUnit};
+    use crate::service::DefaultLogService;
+
+    async fn run_job(service: &DefaultLogService, unit: &LogUnit) -> LogResult<()> {
+        let _guard = service.shutdown_guard();
+        service.handle().log(LogEntry::info(unit.log_unit_id, "Queued before the error".to_string()));
+        Err("job failed".into())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_guarded_service_shuts_down_on_drop() {
+        let (service, guard) = DefaultLogService::new().guarded();
+        let unit = service.create_log_unit("main".to_string()).await.unwrap();
+        service.handle().log(LogEntry::info(unit.log_unit_id, "Queued before exit".to_string()));
+
+        drop(guard);
+        assert_eq!(service.get_log_entries(unit.log_unit_id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_guard_flushes_on_early_return() {
+        let service = DefaultLogService::new();
+        let unit = service.create_log_unit("job".to_string()).await.unwrap();
+
+        assert!(run_job(&service, &unit).await.is_err());
+        let entries = service.get_log_entries(unit.log_unit_id).await.unwrap();
+        assert_eq!(entries.len(), 1);
+    }
+}
@@ -0,0 +1,28 @@
+//! Runs in its own process, as installing a panic hook affects every thread
+#![cfg(feature = "console")]
+
+use ironscribe::{context, core::LogService, DefaultLogService};
+use std::{panic, thread};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_panics_are_recorded_in_the_current_unit() {
+    let service = DefaultLogService::new();
+    let main_unit = service.create_log_unit("main".to_string()).await.unwrap();
+    let job_unit = service.create_log_unit("job".to_string()).await.unwrap();
+    service.panic_hook().with_default_unit(main_unit.clone()).install();
+
+    let in_job = context::scope_with(service.handle(), job_unit.clone(), async {
+        context::spawn(async { panic!("job crashed") }).await
+    })
+    .await;
+    let outside = thread::spawn(|| panic!("main crashed")).join();
+    drop(panic::take_hook());
+
+    assert!(in_job.is_err() && outside.is_err());
+    let job_entries = service.get_log_entries(job_unit.log_unit_id).await.unwrap();
+    assert!(job_entries[0].message.ends_with("panicked: job crashed"));
+    assert_eq!(job_entries[0].location.as_ref().unwrap().file, file!());
+    assert_eq!(job_entries[0].error.as_ref().unwrap().chain, vec!["job crashed".to_string()]);
+    let main_entries = service.get_log_entries(main_unit.log_unit_id).await.unwrap();
+    assert!(main_entries.iter().any(|entry| entry.message.ends_with("panicked: main crashed")));
+}
@@ -0,0 +1,32 @@
+//! Runs in its own process, as it relies on the process-wide default log handle
+#![cfg(feature = "console")]
+
+use ironscribe::{core::LogService, info, scope, DefaultLogService};
+
+#[tokio::test]
+async fn test_scope_logs_through_the_default_handle() {
+    let service = DefaultLogService::new();
+    let unit = service.create_log_unit("scoped".to_string()).await.unwrap();
+
+    // Creating a unit alone does not make the service the default
+    let before = service.create_log_unit("unscoped".to_string()).await.unwrap();
+    scope(before.clone(), async {
+        info!("Not logged");
+    })
+    .await;
+
+    service.set_as_default();
+
+    scope(unit.clone(), async {
+        info!("Logged without a handle in scope");
+    })
+    .await;
+
+    service.flush().await.unwrap();
+    let entries = service.get_log_entries(unit.log_unit_id).await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].message, "Logged without a handle in scope");
+    assert!(service.get_log_entries(before.log_unit_id).await.unwrap().is_empty());
+
+    ironscribe::clear_default_handle();
+}